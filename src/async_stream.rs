@@ -0,0 +1,109 @@
+use std::io::{Error, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::parser::{ElemCompose, Limits, PushDecoder, ParseError};
+use crate::packet::{Packet, frame_len, check_len};
+
+// Decodes concatenated terms from a tokio reader without blocking the
+// runtime, a term is composed as soon as its last byte arrives.
+pub struct AsyncDecoder<R> {
+    reader: R,
+    decoder: PushDecoder,
+    chunk: Vec<u8>
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecoder<R> {
+    pub fn new(reader: R, limits: &Limits) -> AsyncDecoder<R> {
+        AsyncDecoder{
+            reader,
+            decoder: PushDecoder::new(limits),
+            chunk: vec![0; 65536]
+        }
+    }
+
+    // Returns Ok(false) when the reader ends cleanly on a term boundary.
+    pub async fn next_term<C: ElemCompose>(&mut self, c: &mut C) -> Result<bool, ParseError> {
+        loop {
+            if self.decoder.next_term(c)? {
+                return Ok(true);
+            }
+            let n = self.reader.read(&mut self.chunk).await?;
+            if n == 0 {
+                self.decoder.finish()?;
+                return Ok(false);
+            }
+            self.decoder.feed(&self.chunk[..n]);
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+// Async counterpart of `packet::read_frame`, sharing its length check.
+// Decode the frame with `packet::parse_frame`.
+pub async fn read_frame<R: AsyncRead + Unpin>(r: &mut R, packet: Packet, limits: &Limits) -> Result<Option<Vec<u8>>, ParseError> {
+    let mut header: [u8; 4] = [0; 4];
+    let header = &mut header[..packet.header_len()];
+    let mut filled = 0;
+    while filled < header.len() {
+        match r.read(&mut header[filled..]).await? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(Error::new(ErrorKind::UnexpectedEof, "truncated frame header").into()),
+            n => filled += n
+        }
+    }
+    let len = frame_len(header);
+    check_len(len, limits)?;
+    let mut frame = vec![];
+    r.take(len as u64).read_to_end(&mut frame).await?;
+    if frame.len() < len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "truncated frame").into());
+    }
+    Ok(Some(frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    use super::{AsyncDecoder, read_frame};
+    use crate::json_composer::JsonComposer;
+    use crate::packet::Packet;
+    use crate::parser::{ErrorCode, Limits};
+
+    // Readers over byte slices never wait, so one poll finishes the future.
+    fn ready<F: Future>(future: F) -> F::Output {
+        let mut cx = Context::from_waker(Waker::noop());
+        match pin!(future).poll(&mut cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("slice reader was pending")
+        }
+    }
+
+    #[test]
+    fn decodes_concatenated_terms() {
+        let mut decoder = AsyncDecoder::new(&[131, 97, 1, 131, 97, 2][..], &Limits::default());
+        let mut c = JsonComposer::new();
+        assert!(ready(decoder.next_term(&mut c)).unwrap());
+        assert_eq!(c.result(), "{\"int\":1}");
+        let mut c = JsonComposer::new();
+        assert!(ready(decoder.next_term(&mut c)).unwrap());
+        assert_eq!(c.result(), "{\"int\":2}");
+        assert!(!ready(decoder.next_term(&mut JsonComposer::new())).unwrap());
+    }
+
+    #[test]
+    fn frames_are_limited() {
+        let mut r = &[0, 3, 131, 97, 1, 255, 255][..];
+        assert_eq!(ready(read_frame(&mut r, Packet::Two, &Limits::default())).unwrap(), Some(vec![131, 97, 1]));
+        let limits = Limits{ max_alloc: 100, ..Limits::default() };
+        let err = ready(read_frame(&mut r, Packet::Two, &limits)).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::AllocLimitExceeded);
+        let err = ready(read_frame(&mut &[0, 3, 131][..], Packet::Two, &limits)).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::UnexpectedEof);
+    }
+}
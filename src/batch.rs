@@ -0,0 +1,300 @@
+use eterm2json::{ParseError, Format};
+use eterm2json::json_composer::push_json_str;
+
+use crate::cli::Options;
+use crate::convert_input;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+const WILDCARDS: [char; 3] = ['*', '?', '['];
+
+struct Job {
+    input: PathBuf,
+    output: PathBuf
+}
+
+pub struct Failure {
+    input: PathBuf,
+    error: ParseError
+}
+
+pub struct Summary {
+    pub converted: usize,
+    pub failures: Vec<Failure>
+}
+
+// Converts every file named by the inputs into `out_dir`, keeping the layout
+// below each directory or glob base.
+pub fn run(options: &Options, out_dir: &Path) -> io::Result<Summary> {
+    let mut jobs = vec![];
+    for input in &options.inputs {
+        collect_jobs(input, options, out_dir, &mut jobs)?;
+    }
+    let (jobs, rejected) = check_outputs(jobs);
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(vec![]);
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.min(jobs.len()).max(1) {
+            scope.spawn(|| {
+                while let Some(job) = jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    if let Err(error) = convert_file(job, options) {
+                        failures.lock().unwrap().push(Failure{ input: job.input.clone(), error });
+                    }
+                }
+            });
+        }
+    });
+    let mut failures = failures.into_inner().unwrap();
+    let converted = jobs.len() - failures.len();
+    failures.extend(rejected);
+    failures.sort_by(|a, b| a.input.cmp(&b.input));
+    Ok(Summary{ converted, failures })
+}
+
+// Fails the jobs that would write over their own input or share an output
+// with another job before anything is written, a file named twice is
+// converted once.
+fn check_outputs(mut jobs: Vec<Job>) -> (Vec<Job>, Vec<Failure>) {
+    let mut seen = HashSet::new();
+    jobs.retain(|job| seen.insert((job.input.clone(), job.output.clone())));
+    let mut outputs: HashMap<PathBuf, usize> = HashMap::new();
+    for job in &jobs {
+        *outputs.entry(job.output.clone()).or_default() += 1;
+    }
+    let mut failures = vec![];
+    let mut valid = vec![];
+    for job in jobs {
+        let error = if outputs[&job.output] > 1 {
+            let msg = format!("{} is the output of more than one input", job.output.display());
+            io::Error::new(io::ErrorKind::AlreadyExists, msg)
+        } else if is_same_file(&job.input, &job.output) {
+            let msg = format!("{} would be overwritten by its own output", job.input.display());
+            io::Error::new(io::ErrorKind::AlreadyExists, msg)
+        } else {
+            valid.push(job);
+            continue;
+        };
+        failures.push(Failure{ input: job.input, error: error.into() });
+    }
+    (valid, failures)
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false
+    }
+}
+
+fn convert_file(job: &Job, options: &Options) -> Result<(), ParseError> {
+    if let Some(dir) = job.output.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut out = BufWriter::new(File::create(&job.output)?);
+    let input = job.input.to_string_lossy();
+    let res = convert_input(&input, options, &mut out).and_then(|()| Ok(out.flush()?));
+    if res.is_err() {
+        // Don't leave half-written output behind a failure.
+        let _ = fs::remove_file(&job.output);
+    }
+    res
+}
+
+fn collect_jobs(input: &str, options: &Options, out_dir: &Path, jobs: &mut Vec<Job>) -> io::Result<()> {
+    let path = Path::new(input);
+    let out_dir = &OutDir{ path: out_dir, extension: output_extension(options.convert.format) };
+    if path.is_dir() {
+        let mut files = vec![];
+        walk(path, &options.extension, &mut files)?;
+        for file in files {
+            push_job(file, path, out_dir, jobs);
+        }
+    } else if input.contains(WILDCARDS) {
+        let base = glob_base(input);
+        let paths = glob::glob(input).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.msg))?;
+        for file in paths {
+            let file = file.map_err(io::Error::from)?;
+            if file.is_file() {
+                push_job(file, &base, out_dir, jobs);
+            }
+        }
+    } else {
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        push_job(path.to_path_buf(), base, out_dir, jobs);
+    }
+    Ok(())
+}
+
+struct OutDir<'a> {
+    path: &'a Path,
+    extension: &'static str
+}
+
+fn output_extension(format: Format) -> &'static str {
+    match format {
+        Format::Json | Format::Ejson | Format::Bert | Format::BertRpc => "json",
+        Format::Erlang => "term",
+        Format::Elixir => "exs",
+        Format::Etf => "etf"
+    }
+}
+
+fn push_job(input: PathBuf, base: &Path, out_dir: &OutDir, jobs: &mut Vec<Job>) {
+    let relative = input.strip_prefix(base).unwrap_or(&input).to_path_buf();
+    let output = out_dir.path.join(relative).with_extension(out_dir.extension);
+    jobs.push(Job{ input, output });
+}
+
+fn walk(dir: &Path, extension: &str, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            walk(&path, extension, files)?;
+        } else if path.extension().is_some_and(|ext| ext == extension) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Leading components of a pattern that contain no wildcards.
+fn glob_base(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(WILDCARDS))
+        .collect()
+}
+
+impl Summary {
+    pub fn to_json(&self) -> String {
+        let mut res = String::new();
+        let _ = write!(res, "{{\"converted\":{},\"failed\":{},\"failures\":[", self.converted, self.failures.len());
+        for (i, failure) in self.failures.iter().enumerate() {
+            if i > 0 {
+                res.push(',');
+            }
+            let error = &failure.error;
+            res.push_str("{\"file\":");
+            push_json_str(&mut res, &failure.input.to_string_lossy());
+            let _ = write!(res, ",\"code\":{},\"kind\":", error.error_code.code());
+            push_json_str(&mut res, &format!("{:?}", error.error_code));
+            res.push_str(",\"message\":");
+            push_json_str(&mut res, &error.to_string());
+            if let Some(offset) = error.offset {
+                let _ = write!(res, ",\"offset\":{}", offset);
+            }
+            if error.offset.is_some() || !error.path.is_empty() {
+                res.push_str(",\"path\":");
+                push_json_str(&mut res, &error.path_string());
+            }
+            if let Some(tag) = error.tag {
+                let _ = write!(res, ",\"tag\":{}", tag);
+            }
+            if let Some(offset) = error.term_offset {
+                let _ = write!(res, ",\"term_offset\":{}", offset);
+            }
+            res.push('}');
+        }
+        res.push_str("]}");
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::cli::{parse_args, ArgsError, Options};
+
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // A fresh directory under the system temp dir.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eterm2json-batch-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse_args(args.iter().map(|arg| arg.to_string())) {
+            Ok(options) => options,
+            Err(ArgsError::Help) | Err(ArgsError::Usage(_)) => panic!("bad arguments {:?}", args)
+        }
+    }
+
+    fn path(p: &Path) -> &str {
+        p.to_str().unwrap()
+    }
+
+    #[test]
+    fn mirrors_directories() {
+        let dir = temp_dir("mirror");
+        let input = dir.join("in");
+        fs::create_dir_all(input.join("sub")).unwrap();
+        fs::write(input.join("a.etf"), [131, 97, 1]).unwrap();
+        fs::write(input.join("sub/b.etf"), [131, 100, 0, 2, b'o', b'k']).unwrap();
+        fs::write(input.join("bad.etf"), [131, 255]).unwrap();
+        fs::write(input.join("skipped.txt"), "x").unwrap();
+        let out = dir.join("out");
+        let summary = run(&options(&["-d", path(&out), path(&input)]), &out).unwrap();
+        assert_eq!(summary.converted, 2);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].input, input.join("bad.etf"));
+        assert_eq!(fs::read_to_string(out.join("a.json")).unwrap(), "{\"int\":1}\n");
+        assert_eq!(fs::read_to_string(out.join("sub/b.json")).unwrap(), "{\"atom\":\"ok\"}\n");
+        assert!(!out.join("bad.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn shared_outputs_fail() {
+        let dir = temp_dir("shared");
+        fs::write(dir.join("a.etf"), [131, 97, 1]).unwrap();
+        fs::write(dir.join("a.bin"), [131, 97, 2]).unwrap();
+        fs::write(dir.join("c.etf"), [131, 97, 3]).unwrap();
+        let out = dir.join("out");
+        let pattern = format!("{}/*.*", path(&dir));
+        let summary = run(&options(&["-d", path(&out), &pattern]), &out).unwrap();
+        assert_eq!(summary.converted, 1);
+        let failed: Vec<_> = summary.failures.iter().map(|f| f.input.clone()).collect();
+        assert_eq!(failed, vec![dir.join("a.bin"), dir.join("a.etf")]);
+        assert!(summary.to_json().contains("is the output of more than one input"));
+        assert!(!out.join("a.json").exists());
+        assert!(out.join("c.json").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn repeated_inputs_convert_once() {
+        let dir = temp_dir("repeated");
+        fs::write(dir.join("a.etf"), [131, 97, 1]).unwrap();
+        let out = dir.join("out");
+        let file = dir.join("a.etf");
+        let summary = run(&options(&["-d", path(&out), path(&file), path(&dir), path(&file)]), &out).unwrap();
+        assert_eq!(summary.converted, 1);
+        assert!(summary.failures.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn output_over_input_fails() {
+        let dir = temp_dir("overwrite");
+        fs::write(dir.join("a.etf"), [131, 97, 1]).unwrap();
+        let summary = run(&options(&["-f", "etf", "-d", path(&dir), path(&dir)]), &dir).unwrap();
+        assert_eq!(summary.converted, 0);
+        assert_eq!(summary.failures.len(), 1);
+        assert!(summary.to_json().contains("would be overwritten by its own output"));
+        assert_eq!(fs::read(dir.join("a.etf")).unwrap(), [131, 97, 1]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
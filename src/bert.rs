@@ -0,0 +1,377 @@
+use num_traits::ToPrimitive;
+
+use crate::calendar::push_utc;
+use crate::json_composer::{JsonComposer, JsonOptions};
+use crate::json_writer::{JsonWrite, CompactWriter};
+use crate::parser::ParseError;
+use crate::pretty_composer::{PrettyOptions, PrettyWriter};
+use crate::term::{Term, Render, RenderComposer};
+
+enum Work<'a> {
+    Value(&'a Term),
+    Key(&'a Term),
+    // A key of the output's own, such as `tail`.
+    Name(&'static str),
+    BeginArray,
+    EndArray,
+    EndObject
+}
+
+// Renders a term as plain JSON with the BERT complex types resolved:
+// `{bert, nil}` is null, `{bert, true}` and `{bert, false}` are booleans,
+// `{bert, dict, Pairs}` is an object, `{bert, time, Mega, Sec, Micro}` an
+// ISO 8601 string and `{bert, regex, Source, Options}` an object of both.
+// Atoms and UTF-8 binaries are strings, tuples are arrays. Terms plain JSON
+// can't hold keep the tagged form of `JsonComposer`.
+pub fn bert_to_json(term: &Term) -> String {
+    let mut w = CompactWriter::new();
+    write_bert(term, &mut w);
+    w.into_result()
+}
+
+// `bert_to_json` into any writer.
+pub fn write_bert<W: JsonWrite>(term: &Term, w: &mut W) {
+    let mut stack = vec![Work::Value(term)];
+    while let Some(work) = stack.pop() {
+        match work {
+            Work::Value(value) => push_value(w, &mut stack, value),
+            Work::Key(key) => push_key(w, key),
+            Work::Name(name) => w.key(name),
+            Work::BeginArray => w.begin_array(),
+            Work::EndArray => w.end_array(),
+            Work::EndObject => w.end_object()
+        }
+    }
+}
+
+// Labels a BERT-RPC packet, `{"type":"call","module":..,"function":..,
+// "args":[..]}` and likewise for cast, reply, noreply, error and info.
+// Anything else fails with `InvalidBertRpc`.
+pub fn bert_rpc_to_json(term: &Term) -> Result<String, ParseError> {
+    let mut w = CompactWriter::new();
+    write_bert_rpc(term, &mut w)?;
+    Ok(w.into_result())
+}
+
+// `bert_rpc_to_json` into any writer.
+pub fn write_bert_rpc<W: JsonWrite>(term: &Term, w: &mut W) -> Result<(), ParseError> {
+    let items = match *term {
+        Term::Tuple(ref items) if !items.is_empty() => items,
+        _ => return Err(ParseError::invalid_bert_rpc())
+    };
+    let fields: &[&str] = match (items[0].as_atom(), items.len()) {
+        (Some("call"), 4) | (Some("cast"), 4) => &["module", "function", "args"],
+        (Some("reply"), 2) => &["result"],
+        (Some("noreply"), 1) => &[],
+        (Some("error"), 2) => &["error"],
+        (Some("info"), 3) => &["command", "options"],
+        _ => return Err(ParseError::invalid_bert_rpc())
+    };
+    w.begin_object();
+    w.key("type");
+    w.string(items[0].as_atom().unwrap_or_default());
+    for (name, value) in fields.iter().zip(&items[1..]) {
+        w.key(name);
+        match (*name, value) {
+            ("error", Term::Tuple(ref detail)) if detail.len() == 5 => {
+                let names = ["type", "code", "class", "detail", "backtrace"];
+                w.begin_object();
+                for (name, value) in names.iter().zip(detail) {
+                    w.key(name);
+                    write_bert(value, w);
+                }
+                w.end_object();
+            },
+            _ => write_bert(value, w)
+        }
+    }
+    w.end_object();
+    Ok(())
+}
+
+fn push_value<'a, W: JsonWrite>(w: &mut W, stack: &mut Vec<Work<'a>>, term: &'a Term) {
+    match *term {
+        // Tuples that only look like BERT types fall through to arrays.
+        Term::Tuple(ref items) if items.first().and_then(Term::as_atom) == Some("bert")
+            && push_bert(w, stack, &items[1..]) => return,
+        Term::Map(ref pairs) if pairs.iter().all(|(k, _)| is_key(k)) => {
+            push_object(w, stack, pairs.iter().map(|(k, v)| (k, v)));
+            return;
+        },
+        _ => {}
+    }
+    match *term {
+        Term::Atom(ref name) => w.string(name),
+        Term::Int(v) => w.value(v),
+        Term::BigInt(ref v) => w.value(v),
+        Term::Float(v) if v.is_finite() => w.value(format_args!("{:?}", v)),
+        Term::Float(_) => w.value("null"),
+        Term::String(ref chars) => w.string(&chars.iter().map(|&c| c as char).collect::<String>()),
+        Term::Binary(ref data) if std::str::from_utf8(data).is_ok() => {
+            w.string(std::str::from_utf8(data).unwrap_or_default());
+        },
+        Term::List(ref items, None) | Term::Tuple(ref items) => push_array(w, stack, items),
+        Term::List(ref items, Some(ref tail)) => {
+            w.begin_object();
+            w.key("list");
+            stack.push(Work::EndObject);
+            stack.push(Work::Value(tail));
+            stack.push(Work::Name("tail"));
+            push_array(w, stack, items);
+        },
+        Term::Map(ref pairs) => {
+            // Keys JSON can't hold, as `[key, value]` pairs.
+            w.begin_array();
+            stack.push(Work::EndArray);
+            for (key, value) in pairs.iter().rev() {
+                stack.push(Work::EndArray);
+                stack.push(Work::Value(value));
+                stack.push(Work::Value(key));
+                stack.push(Work::BeginArray);
+            }
+        },
+        _ => {
+            let mut composer = JsonComposer::with_writer(JsonOptions::default(), &mut *w);
+            term.compose(&mut composer);
+        }
+    }
+}
+
+// The elements after `bert` in a complex type, false when they aren't one.
+fn push_bert<'a, W: JsonWrite>(w: &mut W, stack: &mut Vec<Work<'a>>, items: &'a [Term]) -> bool {
+    let (kind, items) = match items.split_first() {
+        Some((kind, items)) => (kind.as_atom(), items),
+        None => return false
+    };
+    match (kind, items) {
+        (Some("nil"), []) => w.value("null"),
+        (Some("true"), []) => w.value("true"),
+        (Some("false"), []) => w.value("false"),
+        (Some("dict"), [Term::List(ref pairs, None)]) => {
+            let entries: Option<Vec<(&Term, &Term)>> = pairs.iter().map(|pair| match *pair {
+                Term::Tuple(ref kv) if kv.len() == 2 && is_key(&kv[0]) => Some((&kv[0], &kv[1])),
+                _ => None
+            }).collect();
+            match entries {
+                Some(entries) => push_object(w, stack, entries.into_iter()),
+                None => push_array(w, stack, pairs)
+            }
+        },
+        (Some("time"), [ref mega, ref secs, ref micros]) => {
+            let secs = match (int(mega), int(secs), int(micros)) {
+                (Some(mega), Some(secs), Some(micros)) if (0..1_000_000).contains(&micros) => {
+                    mega.checked_mul(1_000_000).and_then(|mega| mega.checked_add(secs)).map(|s| (s, micros as u32))
+                },
+                _ => None
+            };
+            match secs {
+                // Years 1970 to 9999.
+                Some((secs, micros)) if (0..253_402_300_800).contains(&secs) => {
+                    let mut text = String::new();
+                    push_utc(&mut text, secs, micros);
+                    w.string(&text);
+                },
+                _ => return false
+            }
+        },
+        (Some("regex"), [ref source, ref options]) => {
+            w.begin_object();
+            w.key("regex");
+            stack.push(Work::EndObject);
+            stack.push(Work::Value(options));
+            stack.push(Work::Name("options"));
+            stack.push(Work::Value(source));
+        },
+        _ => return false
+    }
+    true
+}
+
+fn push_array<'a, W: JsonWrite>(w: &mut W, stack: &mut Vec<Work<'a>>, items: &'a [Term]) {
+    w.begin_array();
+    stack.push(Work::EndArray);
+    stack.extend(items.iter().rev().map(Work::Value));
+}
+
+fn push_object<'a, W, I>(w: &mut W, stack: &mut Vec<Work<'a>>, pairs: I)
+    where W: JsonWrite, I: DoubleEndedIterator<Item = (&'a Term, &'a Term)> {
+    w.begin_object();
+    stack.push(Work::EndObject);
+    for (key, value) in pairs.rev() {
+        stack.push(Work::Value(value));
+        stack.push(Work::Key(key));
+    }
+}
+
+fn int(term: &Term) -> Option<i64> {
+    match *term {
+        Term::Int(v) => Some(v as i64),
+        Term::BigInt(ref v) => v.to_i64(),
+        _ => None
+    }
+}
+
+// Terms that read naturally as an object key.
+fn is_key(term: &Term) -> bool {
+    match *term {
+        Term::Binary(ref data) => std::str::from_utf8(data).is_ok(),
+        Term::Atom(_) | Term::String(_) | Term::Int(_) | Term::BigInt(_) => true,
+        _ => false
+    }
+}
+
+fn push_key<W: JsonWrite>(w: &mut W, key: &Term) {
+    match *key {
+        Term::Binary(ref data) => w.key(std::str::from_utf8(data).unwrap_or_default()),
+        Term::Atom(ref name) => w.key(name),
+        Term::String(ref chars) => w.key(&chars.iter().map(|&c| c as char).collect::<String>()),
+        Term::Int(v) => w.key(&v.to_string()),
+        Term::BigInt(ref v) => w.key(&v.to_string()),
+        _ => {}
+    }
+}
+
+// Renders whole terms with `bert_to_json`, or as BERT-RPC packets.
+pub struct BertRenderer {
+    rpc: bool,
+    pretty: Option<PrettyOptions>
+}
+
+impl BertRenderer {
+    fn write<W: JsonWrite>(&self, term: &Term, w: &mut W) -> Result<(), ParseError> {
+        if self.rpc {
+            return write_bert_rpc(term, w);
+        }
+        write_bert(term, w);
+        Ok(())
+    }
+}
+
+impl Render for BertRenderer {
+    fn render(&self, term: &Term) -> Result<String, ParseError> {
+        match self.pretty {
+            Some(pretty) => {
+                let mut w = PrettyWriter::new(pretty);
+                self.write(term, &mut w)?;
+                Ok(w.into_result())
+            },
+            None => {
+                let mut w = CompactWriter::new();
+                self.write(term, &mut w)?;
+                Ok(w.into_result())
+            }
+        }
+    }
+}
+
+pub type BertComposer = RenderComposer<BertRenderer>;
+
+impl BertComposer {
+    pub fn with_options(rpc: bool, pretty: Option<PrettyOptions>) -> BertComposer {
+        let pretty = pretty.map(|pretty| PrettyOptions{ color: false, ..pretty });
+        RenderComposer::new(BertRenderer{ rpc, pretty })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bert_to_json, bert_rpc_to_json, BertComposer};
+    use crate::parse_erlang_term;
+    use crate::parser::{ErrorCode, Limits};
+    use crate::pretty_composer::PrettyOptions;
+    use crate::term::Term;
+
+    fn parse(text: &str) -> Term {
+        parse_erlang_term(text, &Limits::default()).unwrap()
+    }
+
+    fn bert(text: &str) -> String {
+        bert_to_json(&parse(text))
+    }
+
+    fn rpc(text: &str) -> Result<String, ErrorCode> {
+        bert_rpc_to_json(&parse(text)).map_err(|e| e.error_code)
+    }
+
+    #[test]
+    fn plain_terms() {
+        assert_eq!(bert("{ok, [1, 2.5, \"ab\", <<\"x\">>]}"), r#"["ok",[1,2.5,"ab","x"]]"#);
+        assert_eq!(bert("#{a => 1, <<\"b\">> => 2, 3 => c}"), r#"{"a":1,"b":2,"3":"c"}"#);
+        assert_eq!(bert("#{[1] => 2}"), r#"[[[1],2]]"#);
+        assert_eq!(bert("[1 | 2]"), r#"{"list":[1],"tail":2}"#);
+        assert_eq!(bert("<<255>>"), r#"{"binary":"/w=="}"#);
+    }
+
+    #[test]
+    fn nil_and_booleans() {
+        assert_eq!(bert("[{bert, nil}, {bert, true}, {bert, false}]"), "[null,true,false]");
+        assert_eq!(bert("[nil, true, false]"), r#"["nil","true","false"]"#);
+        assert_eq!(bert("{bert, maybe}"), r#"["bert","maybe"]"#);
+        assert_eq!(bert("{bert, nil, 1}"), r#"["bert","nil",1]"#);
+    }
+
+    #[test]
+    fn dicts() {
+        assert_eq!(bert("{bert, dict, [{a, 1}, {<<\"b\">>, [2]}]}"), r#"{"a":1,"b":[2]}"#);
+        assert_eq!(bert("{bert, dict, []}"), "{}");
+        // Not all pairs with keys, a list of the entries.
+        assert_eq!(bert("{bert, dict, [{a, 1}, {[], 2}]}"), r#"[["a",1],[[],2]]"#);
+        assert_eq!(bert("{bert, dict, nope}"), r#"["bert","dict","nope"]"#);
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(bert("{bert, time, 0, 0, 0}"), r#""1970-01-01T00:00:00Z""#);
+        assert_eq!(bert("{bert, time, 1255, 295581, 446228}"), r#""2009-10-11T21:13:01.446228Z""#);
+        assert_eq!(bert("{bert, time, 253402, 300799, 999999}"), r#""9999-12-31T23:59:59.999999Z""#);
+        assert_eq!(bert("{bert, time, 253402, 300800, 0}"), r#"["bert","time",253402,300800,0]"#);
+        assert_eq!(bert("{bert, time, 0, -1, 0}"), r#"["bert","time",0,-1,0]"#);
+        assert_eq!(bert("{bert, time, 0, 0, 1000000}"), r#"["bert","time",0,0,1000000]"#);
+    }
+
+    #[test]
+    fn regexes() {
+        assert_eq!(bert("{bert, regex, <<\"^a+$\">>, [caseless]}"), r#"{"regex":"^a+$","options":["caseless"]}"#);
+        assert_eq!(bert("{bert, regex, <<\"a\">>}"), r#"["bert","regex","a"]"#);
+    }
+
+    #[test]
+    fn non_finite_floats() {
+        let term = Term::List(vec![Term::Float(f64::INFINITY), Term::Float(f64::NAN)], None);
+        assert_eq!(bert_to_json(&term), "[null,null]");
+    }
+
+    #[test]
+    fn rpc_packets() {
+        assert_eq!(rpc("{call, nat, add, [1, 2]}").unwrap(),
+            r#"{"type":"call","module":"nat","function":"add","args":[1,2]}"#);
+        assert_eq!(rpc("{cast, nat, die, []}").unwrap(),
+            r#"{"type":"cast","module":"nat","function":"die","args":[]}"#);
+        assert_eq!(rpc("{reply, {bert, nil}}").unwrap(), r#"{"type":"reply","result":null}"#);
+        assert_eq!(rpc("{noreply}").unwrap(), r#"{"type":"noreply"}"#);
+        assert_eq!(rpc("{info, stream, []}").unwrap(), r#"{"type":"info","command":"stream","options":[]}"#);
+        assert_eq!(rpc("{error, {server, 2, 'UnknownFunction', <<\"no\">>, []}}").unwrap(), concat!(
+            r#"{"type":"error","error":{"type":"server","code":2,"class":"UnknownFunction","#,
+            r#""detail":"no","backtrace":[]}}"#));
+        assert_eq!(rpc("{error, oops}").unwrap(), r#"{"type":"error","error":"oops"}"#);
+    }
+
+    #[test]
+    fn invalid_rpc_packets() {
+        for text in ["{call, nat, add}", "{reply}", "{}", "[call]", "{other, 1}", "{noreply, 1}"] {
+            assert_eq!(rpc(text).unwrap_err(), ErrorCode::InvalidBertRpc, "{}", text);
+        }
+    }
+
+    #[test]
+    fn composer() {
+        let term = parse("{reply, [1, 2]}");
+        let mut c = BertComposer::with_options(true, None);
+        term.compose(&mut c);
+        assert_eq!(c.result().unwrap(), r#"{"type":"reply","result":[1,2]}"#);
+        let pretty = PrettyOptions{ width: 0, color: true, ..PrettyOptions::default() };
+        let mut c = BertComposer::with_options(false, Some(pretty));
+        term.compose(&mut c);
+        assert_eq!(c.result().unwrap(), "[\n  \"reply\",\n  [\n    1,\n    2\n  ]\n]");
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::Write;
+
+// Proleptic Gregorian date of a day count from 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// Writes Unix time as `YYYY-MM-DDTHH:MM:SS.ffffffZ`, the fraction only when
+// there are microseconds.
+pub fn push_utc(out: &mut String, secs: i64, micros: u32) {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let time = secs.rem_euclid(86400);
+    let _ = write!(out, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60);
+    if micros > 0 {
+        let _ = write!(out, ".{:06}", micros);
+    }
+    out.push('Z');
+}
+
+pub fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{civil_from_days, days_in_month, push_utc};
+
+    fn utc(secs: i64, micros: u32) -> String {
+        let mut out = String::new();
+        push_utc(&mut out, secs, micros);
+        out
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        assert_eq!(civil_from_days(-25509), (1900, 2, 28));
+        assert_eq!(civil_from_days(-25508), (1900, 3, 1));
+        assert_eq!(civil_from_days(2932896), (9999, 12, 31));
+        assert_eq!(civil_from_days(-719528), (0, 1, 1));
+    }
+
+    #[test]
+    fn month_lengths() {
+        assert_eq!(days_in_month(2000, 2), 29);
+        assert_eq!(days_in_month(1900, 2), 28);
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2023, 2), 28);
+        assert_eq!(days_in_month(2023, 4), 30);
+        assert_eq!(days_in_month(2023, 12), 31);
+    }
+
+    #[test]
+    fn utc_text() {
+        assert_eq!(utc(0, 0), "1970-01-01T00:00:00Z");
+        assert_eq!(utc(-1, 0), "1969-12-31T23:59:59Z");
+        assert_eq!(utc(951_782_400, 5), "2000-02-29T00:00:00.000005Z");
+        assert_eq!(utc(253_402_300_799, 999_999), "9999-12-31T23:59:59.999999Z");
+    }
+}
@@ -0,0 +1,427 @@
+use eterm2json::{Limits, Packet, BinaryFormat, BigIntFormat, InputEncoding, PrettyOptions};
+use eterm2json::{Dialect, EjsonOptions, TimeRule, ConvertOptions, Format, InputFormat};
+
+use std::thread;
+
+pub const USAGE: &str = "\
+Usage: eterm2json [OPTIONS] [FILE...]
+       eterm2json [OPTIONS] --out-dir DIR PATH...
+
+Converts Erlang external term format to JSON, Erlang or Elixir term
+text, and Erlang term text or JSON back to the external format. Reads
+stdin when no FILE is given or FILE is '-'. With --out-dir every PATH,
+a file, directory or glob pattern, is converted in parallel into a
+mirrored tree under DIR.
+
+Options:
+  -o, --output FILE          write to FILE instead of stdout
+  -f, --format FORMAT        output format: json (default), ejson, bert,
+                             bert-rpc, erlang, elixir or etf. bert-rpc
+                             reads {packet, 4} unless --packet or
+                             --ndjson says otherwise
+  -i, --input-format FORMAT  input format: etf (default), erlang, for
+                             term text such as {ok, [1,2]}, or json, read
+                             as the terms of --dialect
+  -e, --input-encoding ENC   input encoding: raw (default), hex, pghex,
+                             base64, base64url, erlang or auto
+      --pretty               indent the JSON output
+      --indent N             spaces per level with --pretty (2)
+      --compact-width N      keep containers that fit in N columns on one
+                             line with --pretty (80), 0 never does
+      --color WHEN           colour --pretty output: auto (default, when
+                             stdout is a terminal), always or never
+      --binary POLICY        binaries as base64 (default), hex, array, utf8
+      --bigint POLICY        big integers as number (default) or string
+      --structs              JSON with Elixir structs as named objects and
+                             Date, Time, NaiveDateTime, DateTime, Decimal
+                             and URI as text, MapSet and Range in short form
+      --hrl FILE             JSON with tuples of the records declared in
+                             FILE as named objects, repeatable
+      --record DECL          the same for a declaration such as
+                             '-record(user, {id, name}).', repeatable
+      --times                JSON with {{Y,M,D},{H,Mi,S}} datetimes and
+                             os:timestamp() triples as ISO 8601 text
+      --int-times            the same for integers that read as system
+                             time in s, ms, us or ns in years 2000-2099
+      --time-rule PATH=KIND  force KIND, one of datetime, timestamp, s,
+                             ms, us, ns or none, at PATH such as
+                             '$[*].created_at', repeatable; with
+                             --split PATH starts at each record
+      --dialect DIALECT      JSON objects in ejson output and json input:
+                             jiffy (default, {[{K,V}]}), jsx ([{K,V}]) or
+                             maps
+      --null ATOM            atom standing for JSON null, defaults to nil
+                             for maps and null otherwise
+      --ndjson               decode concatenated terms, one line each;
+                             without it data after the term fails (6)
+      --split                emit each element of a root list or tuple
+                             as its own line while decoding
+      --packet N             input framed as {packet, N}, N is 1, 2 or 4
+      --out-packet N         frame every output document as {packet, N}
+      --port                 serve an Erlang port opened with {packet, 4},
+                             replying in JSON; takes only the limits
+      --max-depth N          limit nesting of lists, tuples and maps
+                             (1000000)
+      --max-alloc N          limit a single binary, atom or bignum in bytes
+                             (67108864)
+      --max-total N          limit bytes allocated for one term
+                             (1073741824)
+      --max-elements N       limit terms decoded for one term (50000000)
+      --no-limits            lift all four limits, for trusted input;
+                             --max-* options given after it still apply
+  -d, --out-dir DIR          batch mode, one output file per input
+  -j, --jobs N               batch worker threads, defaults to CPU count
+      --ext EXT              batch file extension in directories (etf)
+      --summary FILE         write the batch summary to FILE, not stdout
+  -h, --help                 print this help
+
+Exit status:
+  0 success, 1 I/O error, 2 unknown tag, 3 reserved,
+  4 not an external term, 5 invalid UTF-8 atom, 6 trailing frame data,
+  7 unexpected end of input, 8 invalid float, 9 length overflow,
+  10-13 depth, allocation, total size and element limits,
+  14 invalid input encoding, 15 invalid term text, 16 term not valid
+  for ejson, 17 invalid JSON, 18 not a BERT-RPC packet,
+  64 bad command line, 65 some files of a batch failed
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Auto,
+    Always,
+    Never
+}
+
+pub struct Options {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    pub convert: ConvertOptions,
+    pub records: Vec<RecordSource>,
+    pub color: Color,
+    pub port: bool,
+    pub out_dir: Option<String>,
+    pub jobs: usize,
+    pub extension: String,
+    pub summary: Option<String>
+}
+
+// Where record definitions come from, in command line order.
+pub enum RecordSource {
+    Hrl(String),
+    Decl(String)
+}
+
+pub enum ArgsError {
+    Help,
+    Usage(String)
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, ArgsError> {
+    let mut options = Options{
+        inputs: vec![],
+        output: None,
+        convert: ConvertOptions::default(),
+        records: vec![],
+        color: Color::Auto,
+        port: false,
+        out_dir: None,
+        jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+        extension: "etf".to_string(),
+        summary: None
+    };
+    let mut null = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Err(ArgsError::Help),
+            "-o" | "--output" => options.output = Some(value(&arg, args.next())?),
+            "-f" | "--format" => options.convert.format = format_arg(&arg, args.next())?,
+            "-e" | "--input-encoding" => options.convert.encoding = encoding_arg(&arg, args.next())?,
+            "-i" | "--input-format" => options.convert.input_format = input_format_arg(&arg, args.next())?,
+            "--pretty" => {
+                options.convert.pretty.get_or_insert_with(PrettyOptions::default);
+            },
+            "--indent" => options.convert.pretty.get_or_insert_with(PrettyOptions::default).indent = number_arg(&arg, args.next())?,
+            "--compact-width" => options.convert.pretty.get_or_insert_with(PrettyOptions::default).width = number_arg(&arg, args.next())?,
+            "--color" => options.color = color_arg(&arg, args.next())?,
+            "--binary" => options.convert.json.binary = binary_arg(&arg, args.next())?,
+            "--bigint" => options.convert.json.bigint = bigint_arg(&arg, args.next())?,
+            "--dialect" => options.convert.ejson = EjsonOptions::new(dialect_arg(&arg, args.next())?),
+            "--null" => null = Some(value(&arg, args.next())?),
+            "--structs" => options.convert.semantic.structs = true,
+            "--hrl" => options.records.push(RecordSource::Hrl(value(&arg, args.next())?)),
+            "--record" => options.records.push(RecordSource::Decl(value(&arg, args.next())?)),
+            "--times" => options.convert.semantic.times.tuples = true,
+            "--int-times" => options.convert.semantic.times.integers = true,
+            "--time-rule" => options.convert.semantic.times.rules.push(time_rule_arg(&arg, args.next())?),
+            "--ndjson" => options.convert.ndjson = true,
+            "--split" => options.convert.split = true,
+            "--port" => options.port = true,
+            "--packet" => options.convert.packet = Some(packet_arg(&arg, args.next())?),
+            "--out-packet" => options.convert.out_packet = Some(packet_arg(&arg, args.next())?),
+            "--no-limits" => options.convert.limits = Limits::unlimited(),
+            "--max-depth" => options.convert.limits.max_depth = number_arg(&arg, args.next())?,
+            "--max-alloc" => options.convert.limits.max_alloc = number_arg(&arg, args.next())?,
+            "--max-total" => options.convert.limits.max_total = number_arg(&arg, args.next())?,
+            "--max-elements" => options.convert.limits.max_elements = number_arg(&arg, args.next())?,
+            "-d" | "--out-dir" => options.out_dir = Some(value(&arg, args.next())?),
+            "-j" | "--jobs" => options.jobs = positive_arg(&arg, args.next())?,
+            "--ext" => options.extension = value(&arg, args.next())?,
+            "--summary" => options.summary = Some(value(&arg, args.next())?),
+            "-" => options.inputs.push(arg),
+            _ if arg.starts_with('-') => return Err(usage(format!("unknown option: {}", arg))),
+            _ => options.inputs.push(arg)
+        }
+    }
+    if let Some(null) = null {
+        options.convert.ejson.null = null;
+    }
+    if options.convert.format == Format::BertRpc && options.convert.packet.is_none() && !options.convert.ndjson {
+        options.convert.packet = Some(Packet::Four);
+    }
+    if options.out_dir.is_some() {
+        if options.output.is_some() {
+            return Err(usage("--output can't be combined with --out-dir".to_string()));
+        }
+        if options.inputs.is_empty() || options.inputs.iter().any(|input| input == "-") {
+            return Err(usage("--out-dir needs files, directories or patterns".to_string()));
+        }
+    }
+    // The port always replies in JSON to {packet, 4} on stdio.
+    let limits_only = ConvertOptions{ limits: options.convert.limits, ..ConvertOptions::default() };
+    let port_only = options.inputs.is_empty() && options.output.is_none() && options.out_dir.is_none()
+        && options.convert == limits_only && options.records.is_empty() && options.color == Color::Auto
+        && options.extension == "etf" && options.summary.is_none();
+    if options.port && !port_only {
+        return Err(usage("--port only takes --max-* and --no-limits".to_string()));
+    }
+    if options.inputs.is_empty() {
+        options.inputs.push("-".to_string());
+    }
+    Ok(options)
+}
+
+fn usage(msg: String) -> ArgsError {
+    ArgsError::Usage(msg)
+}
+
+fn value(name: &str, value: Option<String>) -> Result<String, ArgsError> {
+    value.ok_or_else(|| usage(format!("{} expects a value", name)))
+}
+
+fn choice<T: Copy>(name: &str, value: Option<String>, choices: &[(&str, T)]) -> Result<T, ArgsError> {
+    let value = self::value(name, value)?;
+    choices.iter()
+        .find(|(key, _)| *key == value)
+        .map(|(_, v)| *v)
+        .ok_or_else(|| {
+            let keys: Vec<&str> = choices.iter().map(|(key, _)| *key).collect();
+            usage(format!("{} expects one of {}", name, keys.join(", ")))
+        })
+}
+
+fn format_arg(name: &str, value: Option<String>) -> Result<Format, ArgsError> {
+    choice(name, value, &[
+        ("json", Format::Json),
+        ("ejson", Format::Ejson),
+        ("bert", Format::Bert),
+        ("bert-rpc", Format::BertRpc),
+        ("erlang", Format::Erlang),
+        ("elixir", Format::Elixir),
+        ("etf", Format::Etf)
+    ])
+}
+
+fn dialect_arg(name: &str, value: Option<String>) -> Result<Dialect, ArgsError> {
+    choice(name, value, &[("jiffy", Dialect::Jiffy), ("jsx", Dialect::Jsx), ("maps", Dialect::Maps)])
+}
+
+fn input_format_arg(name: &str, value: Option<String>) -> Result<InputFormat, ArgsError> {
+    choice(name, value, &[
+        ("etf", InputFormat::Etf),
+        ("erlang", InputFormat::Erlang),
+        ("json", InputFormat::Json)
+    ])
+}
+
+fn encoding_arg(name: &str, value: Option<String>) -> Result<InputEncoding, ArgsError> {
+    choice(name, value, &[
+        ("raw", InputEncoding::Raw),
+        ("hex", InputEncoding::Hex),
+        ("pghex", InputEncoding::PgHex),
+        ("base64", InputEncoding::Base64),
+        ("base64url", InputEncoding::Base64Url),
+        ("erlang", InputEncoding::Erlang),
+        ("auto", InputEncoding::Auto)
+    ])
+}
+
+fn color_arg(name: &str, value: Option<String>) -> Result<Color, ArgsError> {
+    choice(name, value, &[("auto", Color::Auto), ("always", Color::Always), ("never", Color::Never)])
+}
+
+fn binary_arg(name: &str, value: Option<String>) -> Result<BinaryFormat, ArgsError> {
+    choice(name, value, &[
+        ("base64", BinaryFormat::Base64),
+        ("hex", BinaryFormat::Hex),
+        ("array", BinaryFormat::Array),
+        ("utf8", BinaryFormat::Utf8)
+    ])
+}
+
+fn bigint_arg(name: &str, value: Option<String>) -> Result<BigIntFormat, ArgsError> {
+    choice(name, value, &[("number", BigIntFormat::Number), ("string", BigIntFormat::String)])
+}
+
+fn packet_arg(name: &str, value: Option<String>) -> Result<Packet, ArgsError> {
+    choice(name, value, &[("1", Packet::One), ("2", Packet::Two), ("4", Packet::Four)])
+}
+
+fn number_arg<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, ArgsError> {
+    self::value(name, value)?
+        .parse::<T>()
+        .map_err(|_| usage(format!("{} expects a number", name)))
+}
+
+fn positive_arg(name: &str, value: Option<String>) -> Result<usize, ArgsError> {
+    match number_arg(name, value)? {
+        0 => Err(usage(format!("{} expects a positive number", name))),
+        n => Ok(n)
+    }
+}
+
+fn time_rule_arg(name: &str, value: Option<String>) -> Result<TimeRule, ArgsError> {
+    let value = self::value(name, value)?;
+    TimeRule::parse(&value).ok_or_else(|| usage(format!("{} expects PATH=KIND, got {}", name, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, ArgsError, Options, Color, USAGE};
+    use eterm2json::{Limits, Packet, Format, InputFormat, InputEncoding, BinaryFormat, Dialect};
+
+    fn parse(args: &[&str]) -> Result<Options, ArgsError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(options) => options,
+            Err(_) => panic!("rejected {:?}", args)
+        }
+    }
+
+    fn usage_error(args: &[&str]) -> String {
+        match parse(args) {
+            Err(ArgsError::Usage(msg)) => msg,
+            Err(ArgsError::Help) => panic!("help for {:?}", args),
+            Ok(_) => panic!("accepted {:?}", args)
+        }
+    }
+
+    #[test]
+    fn defaults() {
+        let options = options(&[]);
+        assert_eq!(options.inputs, vec!["-"]);
+        assert_eq!(options.output, None);
+        assert_eq!(options.convert.format, Format::Json);
+        assert_eq!(options.convert.input_format, InputFormat::Etf);
+        assert_eq!(options.convert.encoding, InputEncoding::Raw);
+        assert_eq!(options.convert.pretty, None);
+        assert_eq!(options.convert.packet, None);
+        assert_eq!(options.convert.limits, Limits::default());
+        assert_eq!(options.color, Color::Auto);
+        assert!(!options.convert.ndjson && !options.convert.split && !options.port);
+        assert!(options.jobs > 0);
+    }
+
+    #[test]
+    fn values() {
+        let options = options(&["-f", "elixir", "-i", "erlang", "-e", "base64", "--binary", "hex", "--out-packet", "2",
+            "--indent", "4", "a", "-", "b"]);
+        assert_eq!(options.convert.format, Format::Elixir);
+        assert_eq!(options.convert.input_format, InputFormat::Erlang);
+        assert_eq!(options.convert.encoding, InputEncoding::Base64);
+        assert_eq!(options.convert.json.binary, BinaryFormat::Hex);
+        assert_eq!(options.convert.out_packet, Some(Packet::Two));
+        assert_eq!(options.convert.pretty.map(|p| (p.indent, p.width)), Some((4, 80)));
+        assert_eq!(options.inputs, vec!["a", "-", "b"]);
+        assert!(matches!(parse(&["-o", "x", "--help"]), Err(ArgsError::Help)));
+    }
+
+    #[test]
+    fn bad_values() {
+        assert_eq!(usage_error(&["--format", "xml"]),
+            "--format expects one of json, ejson, bert, bert-rpc, erlang, elixir, etf");
+        assert_eq!(usage_error(&["--packet", "3"]), "--packet expects one of 1, 2, 4");
+        assert_eq!(usage_error(&["-o"]), "-o expects a value");
+        assert_eq!(usage_error(&["--indent", "-1"]), "--indent expects a number");
+        assert_eq!(usage_error(&["--frobnicate"]), "unknown option: --frobnicate");
+        assert_eq!(usage_error(&["--time-rule", "$.a"]), "--time-rule expects PATH=KIND, got $.a");
+    }
+
+    #[test]
+    fn limits() {
+        let options = options(&["--max-depth", "10", "--max-alloc", "20", "--max-total", "30", "--max-elements", "40"]);
+        let limits = options.convert.limits;
+        assert_eq!((limits.max_depth, limits.max_alloc, limits.max_total, limits.max_elements), (10, 20, 30, 40));
+        assert_eq!(usage_error(&["--max-depth", "ten"]), "--max-depth expects a number");
+        assert_eq!(usage_error(&["--max-alloc"]), "--max-alloc expects a value");
+        // Later options win over --no-limits, and it over earlier ones.
+        let options = self::options(&["--max-depth", "10", "--no-limits", "--max-total", "30"]);
+        let unlimited = Limits::unlimited();
+        assert_eq!(options.convert.limits, Limits{ max_total: 30, ..unlimited });
+    }
+
+    #[test]
+    fn bert_rpc_packets() {
+        assert_eq!(options(&["-f", "bert-rpc"]).convert.packet, Some(Packet::Four));
+        assert_eq!(options(&["-f", "bert-rpc", "--packet", "2"]).convert.packet, Some(Packet::Two));
+        assert_eq!(options(&["-f", "bert-rpc", "--ndjson"]).convert.packet, None);
+    }
+
+    #[test]
+    fn null_follows_dialect() {
+        assert_eq!(options(&["--dialect", "maps"]).convert.ejson.null, "nil");
+        let options = self::options(&["--null", "undefined", "--dialect", "jsx"]);
+        assert_eq!(options.convert.ejson.dialect, Dialect::Jsx);
+        assert_eq!(options.convert.ejson.null, "undefined");
+    }
+
+    #[test]
+    fn port_takes_only_limits() {
+        let options = options(&["--port", "--no-limits", "--max-depth", "5"]);
+        assert!(options.port);
+        assert_eq!(options.convert.limits.max_depth, 5);
+        for args in [&["-f", "erlang"][..], &["-o", "x"], &["--ndjson"], &["--packet", "2"], &["--split"], &["--pretty"], &["--binary", "hex"],
+                &["-e", "hex"], &["--structs"], &["--record", "-record(a, {b})."], &["--color", "never"],
+                &["-d", "out", "a"], &["--summary", "s.json"], &["a"], &["-"]] {
+            let args = [&["--port"][..], args].concat();
+            assert_eq!(usage_error(&args), "--port only takes --max-* and --no-limits", "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn conflicts() {
+        assert_eq!(usage_error(&["-d", "out", "-o", "x", "a"]), "--output can't be combined with --out-dir");
+        assert_eq!(usage_error(&["-d", "out"]), "--out-dir needs files, directories or patterns");
+        assert_eq!(usage_error(&["-d", "out", "-"]), "--out-dir needs files, directories or patterns");
+        assert_eq!(usage_error(&["-d", "out", "--jobs", "0", "a"]), "--jobs expects a positive number");
+        assert_eq!(usage_error(&["-j", "0"]), "-j expects a positive number");
+        assert_eq!(options(&["-j", "3"]).jobs, 3);
+    }
+
+    #[test]
+    fn usage_layout() {
+        assert!(USAGE.lines().all(|line| line.chars().count() <= 80));
+        let options = USAGE.lines()
+            .skip_while(|line| *line != "Options:")
+            .skip(1)
+            .take_while(|line| !line.is_empty());
+        for line in options {
+            // Descriptions, continued or not, start in column 30.
+            let (name, text) = line.split_at(29);
+            assert!(name.trim().is_empty() || name.ends_with("  ") && name.trim_start().starts_with('-'), "{}", line);
+            assert!(!text.starts_with(' '), "{}", line);
+        }
+    }
+}
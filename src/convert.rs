@@ -0,0 +1,316 @@
+use std::borrow::Cow;
+use std::io::{BufRead, Write};
+
+use crate::parser::{Decoder, PushDecoder, ElemCompose, ReadStream, Limits, ParseResult, ParseError};
+use crate::json_composer::{JsonComposer, JsonOptions};
+use crate::pretty_composer::{PrettyComposer, PrettyOptions};
+use crate::semantic::{SemanticComposer, SemanticOptions};
+use crate::erlang_composer::ErlangComposer;
+use crate::elixir_composer::ElixirComposer;
+use crate::etf_composer::EtfComposer;
+use crate::ejson::{EjsonComposer, EjsonOptions};
+use crate::bert::BertComposer;
+use crate::erlang_text::parse_erlang_terms;
+use crate::json_text::parse_json_terms;
+use crate::term::{Term, Render, RenderComposer};
+use crate::split_composer::SplitComposer;
+use crate::input_encoding::{InputEncoding, decode_input};
+use crate::buf_read_streamer::BufReadStreamer;
+use crate::packet::{Packet, read_frame, write_frame, parse_frame};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Ejson,
+    Bert,
+    BertRpc,
+    Erlang,
+    Elixir,
+    Etf
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Etf,
+    // Erlang term text, `{ok, [1,2]}.`
+    Erlang,
+    // JSON read as the terms of `ejson.dialect`.
+    Json
+}
+
+// Everything `convert` needs to know about its input and output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvertOptions {
+    pub format: Format,
+    pub input_format: InputFormat,
+    pub json: JsonOptions,
+    pub semantic: SemanticOptions,
+    pub ejson: EjsonOptions,
+    pub pretty: Option<PrettyOptions>,
+    pub encoding: InputEncoding,
+    // Concatenated terms, each its own document.
+    pub ndjson: bool,
+    // Each element of a root list or tuple its own document.
+    pub split: bool,
+    pub packet: Option<Packet>,
+    pub out_packet: Option<Packet>,
+    pub limits: Limits
+}
+
+impl Default for ConvertOptions {
+    fn default() -> ConvertOptions {
+        ConvertOptions{
+            format: Format::Json,
+            input_format: InputFormat::Etf,
+            json: JsonOptions::default(),
+            semantic: SemanticOptions::default(),
+            ejson: EjsonOptions::default(),
+            pretty: None,
+            encoding: InputEncoding::Raw,
+            ndjson: false,
+            split: false,
+            packet: None,
+            out_packet: None,
+            limits: Limits::default()
+        }
+    }
+}
+
+// Composers producing one output document per term.
+// Text documents are written one per line, binary ones back to back.
+pub trait Document: ElemCompose {
+    fn bytes(&self) -> Result<Cow<'_, [u8]>, ParseError>;
+    fn is_text(&self) -> bool {
+        true
+    }
+}
+
+impl Document for JsonComposer {
+    fn bytes(&self) -> Result<Cow<'_, [u8]>, ParseError> {
+        Ok(Cow::Borrowed(self.result().as_bytes()))
+    }
+}
+
+impl Document for PrettyComposer {
+    fn bytes(&self) -> Result<Cow<'_, [u8]>, ParseError> {
+        Ok(Cow::Borrowed(self.result().as_bytes()))
+    }
+}
+
+// Terminated with a dot, so output files can be read by `file:consult`.
+impl Document for ErlangComposer {
+    fn bytes(&self) -> Result<Cow<'_, [u8]>, ParseError> {
+        Ok(Cow::Owned(format!("{}.", self.result()).into_bytes()))
+    }
+}
+
+impl Document for ElixirComposer {
+    fn bytes(&self) -> Result<Cow<'_, [u8]>, ParseError> {
+        Ok(Cow::Borrowed(self.result().as_bytes()))
+    }
+}
+
+// Rendered once the whole term is known, so a record can fail here.
+impl<R: Render> Document for RenderComposer<R> {
+    fn bytes(&self) -> Result<Cow<'_, [u8]>, ParseError> {
+        Ok(Cow::Owned(self.result()?.into_bytes()))
+    }
+}
+
+impl Document for EtfComposer {
+    fn bytes(&self) -> Result<Cow<'_, [u8]>, ParseError> {
+        Ok(Cow::Borrowed(self.result()?))
+    }
+    fn is_text(&self) -> bool {
+        false
+    }
+}
+
+// The composer for one document of `options.format`.
+pub fn new_composer(options: &ConvertOptions) -> Box<dyn Document> {
+    if options.format == Format::Json && options.semantic.is_enabled() {
+        return Box::new(SemanticComposer::with_options(options.json, options.semantic.clone(), options.pretty));
+    }
+    match (options.format, options.pretty) {
+        (Format::Json, None) => Box::new(JsonComposer::with_options(options.json)),
+        (Format::Json, Some(pretty)) => Box::new(PrettyComposer::with_options(options.json, pretty)),
+        (Format::Ejson, None) => Box::new(EjsonComposer::with_options(options.ejson.clone())),
+        (Format::Ejson, Some(pretty)) => Box::new(EjsonComposer::with_pretty(options.ejson.clone(), pretty)),
+        (Format::Bert, pretty) => Box::new(BertComposer::with_options(false, pretty)),
+        (Format::BertRpc, pretty) => Box::new(BertComposer::with_options(true, pretty)),
+        (Format::Erlang, _) => Box::new(ErlangComposer::new()),
+        (Format::Elixir, _) => Box::new(ElixirComposer::new()),
+        (Format::Etf, _) => Box::new(EtfComposer::new())
+    }
+}
+
+// Converts everything `r` holds and writes the documents to `out`. Framed
+// and concatenated input flushes `out` as each frame or chunk is done.
+pub fn convert<R: BufRead>(r: &mut R, options: &ConvertOptions, out: &mut dyn Write) -> ParseResult {
+    if options.input_format != InputFormat::Etf {
+        let mut text = String::new();
+        r.read_to_string(&mut text)?;
+        let terms = match options.input_format {
+            InputFormat::Json => parse_json_terms(&text, &options.ejson)?,
+            _ => parse_erlang_terms(&text, &options.limits)?
+        };
+        return convert_terms(&terms, options, out);
+    }
+    if options.encoding != InputEncoding::Raw {
+        let mut text = vec![];
+        r.read_to_end(&mut text)?;
+        let bytes = decode_input(options.encoding, &text)?;
+        return convert_etf(&mut &bytes[..], options, out);
+    }
+    convert_etf(r, options, out)
+}
+
+// Writes the documents of terms already decoded.
+pub fn convert_terms(terms: &[Term], options: &ConvertOptions, out: &mut dyn Write) -> ParseResult {
+    let mut composer = SplitComposer::new(options.split, || new_composer(options));
+    for term in terms {
+        term.compose(&mut composer);
+        emit_records(out, &mut composer, options.out_packet)?;
+    }
+    Ok(())
+}
+
+fn convert_etf<R: BufRead>(r: &mut R, options: &ConvertOptions, out: &mut dyn Write) -> ParseResult {
+    let mut composer = SplitComposer::new(options.split, || new_composer(options));
+    if let Some(packet) = options.packet {
+        while let Some(frame) = read_frame(r, packet, &options.limits)? {
+            parse_frame(&frame, &mut composer, &options.limits)?;
+            emit_records(out, &mut composer, options.out_packet)?;
+            out.flush()?;
+        }
+    } else if options.ndjson {
+        convert_concatenated(r, &mut composer, options, out)?;
+    } else {
+        // Stepping the decoder by hand lets split records out while the
+        // rest of the term is still being read.
+        let mut buf_streamer = BufReadStreamer::new(r);
+        let mut decoder = Decoder::new(&options.limits);
+        while !decoder.step(&mut buf_streamer, &mut composer)? {
+            emit_records(out, &mut composer, options.out_packet)?;
+        }
+        emit_records(out, &mut composer, options.out_packet)?;
+        // As with `decode`, bytes after the term are an error.
+        if !buf_streamer.at_eof()? {
+            return Err(ParseError::trailing_frame_data().located(buf_streamer.position(), None));
+        }
+    }
+    Ok(())
+}
+
+fn convert_concatenated<R, F>(r: &mut R, composer: &mut Records<F>, options: &ConvertOptions, out: &mut dyn Write) -> ParseResult
+    where R: BufRead, F: FnMut() -> Box<dyn Document> {
+    let mut decoder = PushDecoder::new(&options.limits);
+    let mut chunk: [u8; 65536] = [0; 65536];
+    loop {
+        let n = r.read(&mut chunk)?;
+        if n == 0 {
+            return decoder.finish();
+        }
+        decoder.feed(&chunk[..n]);
+        while decoder.next_term(composer)? {}
+        emit_records(out, composer, options.out_packet)?;
+        out.flush()?;
+    }
+}
+
+type Records<F> = SplitComposer<Box<dyn Document>, F>;
+
+fn emit_records<F>(out: &mut dyn Write, composer: &mut Records<F>, out_packet: Option<Packet>) -> ParseResult
+    where F: FnMut() -> Box<dyn Document> {
+    while let Some(record) = composer.next_record() {
+        let bytes = record.bytes()?;
+        match out_packet {
+            Some(packet) => write_frame(out, packet, &bytes)?,
+            None => {
+                out.write_all(&bytes)?;
+                if record.is_text() {
+                    out.write_all(b"\n")?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, ConvertOptions, Format, InputFormat};
+    use crate::input_encoding::InputEncoding;
+    use crate::json_composer::BinaryFormat;
+    use crate::packet::Packet;
+    use crate::parser::ErrorCode;
+    use crate::semantic::SemanticOptions;
+    use crate::times::{TimeOptions, TimeRule};
+
+    fn run(input: &[u8], options: &ConvertOptions) -> Vec<u8> {
+        let mut out = vec![];
+        convert(&mut &input[..], options, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn documents_are_lines() {
+        let options = ConvertOptions{ input_format: InputFormat::Erlang, ..ConvertOptions::default() };
+        assert_eq!(run(b"ok. 1.", &options), b"{\"atom\":\"ok\"}\n{\"int\":1}\n");
+        let options = ConvertOptions{ format: Format::Erlang, ..options };
+        assert_eq!(run(b"{a, \"b\"}.", &options), b"{a,\"b\"}.\n");
+    }
+
+    #[test]
+    fn binary_documents_back_to_back() {
+        let options = ConvertOptions{ input_format: InputFormat::Erlang, format: Format::Etf, ..ConvertOptions::default() };
+        assert_eq!(run(b"1. 2.", &options), [131, 97, 1, 131, 97, 2]);
+    }
+
+    #[test]
+    fn framed_input_and_output() {
+        let options = ConvertOptions{
+            packet: Some(Packet::One),
+            out_packet: Some(Packet::Two),
+            ..ConvertOptions::default()
+        };
+        assert_eq!(run(&[3, 131, 97, 7], &options), b"\x00\x09{\"int\":7}");
+    }
+
+    #[test]
+    fn options_reach_the_composer() {
+        let mut options = ConvertOptions{ encoding: InputEncoding::Hex, ..ConvertOptions::default() };
+        options.json.binary = BinaryFormat::Hex;
+        assert_eq!(run(b"836d00000002abcd\n", &options), b"{\"binary\":\"abcd\"}\n");
+    }
+
+    #[test]
+    fn trailing_input() {
+        let mut out = vec![];
+        let error = convert(&mut &[131, 97, 1, 0][..], &ConvertOptions::default(), &mut out).unwrap_err();
+        assert_eq!(error.error_code, ErrorCode::TrailingFrameData);
+        assert_eq!(error.offset, Some(3));
+        let options = ConvertOptions{ ndjson: true, ..ConvertOptions::default() };
+        assert_eq!(run(&[131, 97, 1, 131, 97, 2], &options), b"{\"int\":1}\n{\"int\":2}\n");
+    }
+
+    #[test]
+    fn time_rules_start_at_split_records() {
+        let rules = |rule: &str| ConvertOptions{
+            input_format: InputFormat::Erlang,
+            semantic: SemanticOptions{
+                times: TimeOptions{ rules: vec![TimeRule::parse(rule).unwrap()], ..TimeOptions::default() },
+                ..SemanticOptions::default()
+            },
+            ..ConvertOptions::default()
+        };
+        let converted = "{\"tuple\":[{\"atom\":\"a\"},{\"datetime\":\"1970-01-01T00:00:00Z\"}]}";
+        let unchanged = "{\"tuple\":[{\"atom\":\"a\"},{\"int\":0}]}";
+        let whole = rules("$[*].tuple[1]=s");
+        assert_eq!(run(b"[{a, 0}].", &whole), format!("{{\"list\":[{}]}}\n", converted).as_bytes());
+        let split = ConvertOptions{ split: true, ..whole };
+        assert_eq!(run(b"[{a, 0}].", &split), format!("{}\n", unchanged).as_bytes());
+        let split = ConvertOptions{ split: true, ..rules("$.tuple[1]=s") };
+        assert_eq!(run(b"[{a, 0}].", &split), format!("{}\n", converted).as_bytes());
+    }
+}
@@ -0,0 +1,318 @@
+use crate::json_writer::{JsonWrite, CompactWriter};
+use crate::pretty_composer::{PrettyOptions, PrettyWriter};
+use crate::parser::{ParseError, PathSegment};
+use crate::term::{Term, Render, RenderComposer};
+
+// How a library represents JSON objects as terms. Maps are read as objects
+// in every dialect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    // jiffy, `{[{K, V}, ..]}`.
+    Jiffy,
+    // jsx, `[{K, V}, ..]` with `[{}]` for an empty object.
+    Jsx,
+    // Jason and thoas, `#{K => V}`.
+    Maps
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EjsonOptions {
+    pub dialect: Dialect,
+    // Atom standing for JSON null.
+    pub null: String
+}
+
+impl EjsonOptions {
+    // Jason decodes null to `nil`, the Erlang libraries to `null`.
+    pub fn new(dialect: Dialect) -> EjsonOptions {
+        let null = match dialect {
+            Dialect::Maps => "nil",
+            Dialect::Jiffy | Dialect::Jsx => "null"
+        };
+        EjsonOptions{
+            dialect,
+            null: null.to_string()
+        }
+    }
+}
+
+impl Default for EjsonOptions {
+    fn default() -> EjsonOptions {
+        EjsonOptions::new(Dialect::Jiffy)
+    }
+}
+
+enum Work<'a> {
+    Value(&'a Term),
+    Key(&'a Term),
+    EndObject,
+    EndArray,
+    Enter(PathSegment),
+    Leave
+}
+
+// Renders a term holding a decoded JSON document back as JSON. Terms with no
+// JSON counterpart fail with `InvalidEjson` and their path.
+pub fn ejson_to_json(term: &Term, options: &EjsonOptions) -> Result<String, ParseError> {
+    let mut w = CompactWriter::new();
+    write_ejson(term, options, &mut w)?;
+    Ok(w.into_result())
+}
+
+// `ejson_to_json` into any writer.
+pub fn write_ejson<W: JsonWrite>(term: &Term, options: &EjsonOptions, w: &mut W) -> Result<(), ParseError> {
+    let mut path = vec![];
+    let mut stack = vec![Work::Value(term)];
+    while let Some(work) = stack.pop() {
+        match work {
+            Work::EndObject => w.end_object(),
+            Work::EndArray => w.end_array(),
+            Work::Enter(segment) => path.push(segment),
+            Work::Leave => {
+                path.pop();
+            },
+            Work::Key(key) => {
+                if !push_key(w, key) {
+                    return Err(ParseError::invalid_ejson().with_path(path));
+                }
+            },
+            Work::Value(value) => {
+                if let Some(pairs) = object(value, options.dialect) {
+                    w.begin_object();
+                    stack.push(Work::EndObject);
+                    for pair in pairs.into_iter().rev() {
+                        visit(&mut stack, Work::Value(pair.value), &pair.value_path);
+                        visit(&mut stack, Work::Key(pair.key), &pair.key_path);
+                    }
+                    continue;
+                }
+                match *value {
+                    Term::List(ref items, None) => {
+                        w.begin_array();
+                        stack.push(Work::EndArray);
+                        for (i, item) in items.iter().enumerate().rev() {
+                            visit(&mut stack, Work::Value(item), &[PathSegment::List(i as u32)]);
+                        }
+                    },
+                    Term::String(ref chars) => {
+                        w.begin_array();
+                        for &c in chars {
+                            w.value(c);
+                        }
+                        w.end_array();
+                    },
+                    Term::Atom(ref name) if name == "true" || name == "false" => w.value(name),
+                    Term::Atom(ref name) if *name == options.null => w.value("null"),
+                    Term::Atom(ref name) => w.string(name),
+                    Term::Int(v) => w.value(v),
+                    Term::BigInt(ref v) => w.value(v),
+                    Term::Float(v) if v.is_finite() => w.value(format_args!("{:?}", v)),
+                    Term::Binary(ref data) => match std::str::from_utf8(data) {
+                        Ok(text) => w.string(text),
+                        Err(_) => return Err(ParseError::invalid_ejson().with_path(path))
+                    },
+                    _ => return Err(ParseError::invalid_ejson().with_path(path))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Pushes `work` to run with `segments` appended to the path.
+fn visit<'a>(stack: &mut Vec<Work<'a>>, work: Work<'a>, segments: &[PathSegment]) {
+    for _ in segments {
+        stack.push(Work::Leave);
+    }
+    stack.push(work);
+    for &segment in segments.iter().rev() {
+        stack.push(Work::Enter(segment));
+    }
+}
+
+struct Pair<'a> {
+    key: &'a Term,
+    value: &'a Term,
+    key_path: Vec<PathSegment>,
+    value_path: Vec<PathSegment>
+}
+
+// The key/value pairs of a term the dialect reads as an object.
+fn object(term: &Term, dialect: Dialect) -> Option<Vec<Pair<'_>>> {
+    match (dialect, term) {
+        (_, Term::Map(pairs)) => Some(pairs.iter().enumerate().map(|(i, (key, value))| Pair{
+            key,
+            value,
+            key_path: vec![PathSegment::MapKey(i as u32)],
+            value_path: vec![PathSegment::MapValue(i as u32)]
+        }).collect()),
+        (Dialect::Jiffy, Term::Tuple(items)) if items.len() == 1 => match items[0] {
+            Term::List(ref items, None) => proplist(items, Some(PathSegment::Tuple(0))),
+            _ => None
+        },
+        (Dialect::Jsx, Term::List(items, None)) if !items.is_empty() => match items[..] {
+            [Term::Tuple(ref empty)] if empty.is_empty() => Some(vec![]),
+            _ if items.iter().all(is_pair) => proplist(items, None),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+fn proplist(items: &[Term], prefix: Option<PathSegment>) -> Option<Vec<Pair<'_>>> {
+    items.iter().enumerate().map(|(i, item)| match *item {
+        Term::Tuple(ref kv) if kv.len() == 2 => {
+            let path: Vec<PathSegment> = prefix.into_iter().chain(Some(PathSegment::List(i as u32))).collect();
+            Some(Pair{
+                key: &kv[0],
+                value: &kv[1],
+                key_path: path.iter().cloned().chain(Some(PathSegment::Tuple(0))).collect(),
+                value_path: path.iter().cloned().chain(Some(PathSegment::Tuple(1))).collect()
+            })
+        },
+        _ => None
+    }).collect()
+}
+
+// A jsx proplist entry, a pair with a key JSON can hold.
+fn is_pair(term: &Term) -> bool {
+    match *term {
+        Term::Tuple(ref kv) if kv.len() == 2 => match kv[0] {
+            Term::Binary(ref data) => std::str::from_utf8(data).is_ok(),
+            Term::Atom(_) | Term::Int(_) | Term::BigInt(_) => true,
+            _ => false
+        },
+        _ => false
+    }
+}
+
+fn push_key<W: JsonWrite>(w: &mut W, key: &Term) -> bool {
+    match *key {
+        Term::Binary(ref data) => match std::str::from_utf8(data) {
+            Ok(text) => w.key(text),
+            Err(_) => return false
+        },
+        Term::Atom(ref name) => w.key(name),
+        Term::Int(v) => w.key(&v.to_string()),
+        Term::BigInt(ref v) => w.key(&v.to_string()),
+        _ => return false
+    }
+    true
+}
+
+// Renders whole terms with `ejson_to_json`, optionally indented by a
+// `PrettyWriter`. Plain JSON has no type tags to colour by, so it never is.
+pub struct EjsonRenderer {
+    options: EjsonOptions,
+    pretty: Option<PrettyOptions>
+}
+
+impl Render for EjsonRenderer {
+    fn render(&self, term: &Term) -> Result<String, ParseError> {
+        match self.pretty {
+            Some(pretty) => {
+                let mut w = PrettyWriter::new(pretty);
+                write_ejson(term, &self.options, &mut w)?;
+                Ok(w.into_result())
+            },
+            None => ejson_to_json(term, &self.options)
+        }
+    }
+}
+
+// Buffers each term rather than writing as it composes: whether a list is
+// a jsx object or a `{[..]}` tuple a jiffy one is only known once all of it
+// has been seen, and a term with no JSON counterpart anywhere inside has to
+// fail the document before any of it is written.
+pub type EjsonComposer = RenderComposer<EjsonRenderer>;
+
+impl EjsonComposer {
+    pub fn with_options(options: EjsonOptions) -> EjsonComposer {
+        RenderComposer::new(EjsonRenderer{ options, pretty: None })
+    }
+
+    pub fn with_pretty(options: EjsonOptions, pretty: PrettyOptions) -> EjsonComposer {
+        RenderComposer::new(EjsonRenderer{ options, pretty: Some(PrettyOptions{ color: false, ..pretty }) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ejson_to_json, Dialect, EjsonComposer, EjsonOptions};
+    use crate::json_text::parse_json_term;
+    use crate::parse_erlang_term;
+    use crate::parser::{ErrorCode, Limits};
+    use crate::pretty_composer::PrettyOptions;
+
+    fn render(text: &str, dialect: Dialect) -> Result<String, String> {
+        let term = parse_erlang_term(text, &Limits::default()).unwrap();
+        ejson_to_json(&term, &EjsonOptions::new(dialect)).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn jiffy_objects() {
+        assert_eq!(render("{[{<<\"a\">>, 1}, {b, [2, 3.5]}]}", Dialect::Jiffy).unwrap(), r#"{"a":1,"b":[2,3.5]}"#);
+        assert_eq!(render("{[]}", Dialect::Jiffy).unwrap(), "{}");
+        assert_eq!(render("[{a, 1}]", Dialect::Jiffy).unwrap_err(), "InvalidEjson at path $[0]");
+        assert_eq!(render("{[{a, 1}, b]}", Dialect::Jiffy).unwrap_err(), "InvalidEjson");
+    }
+
+    #[test]
+    fn jsx_proplists() {
+        assert_eq!(render("[{<<\"a\">>, 1}, {1, b}]", Dialect::Jsx).unwrap(), r#"{"a":1,"1":"b"}"#);
+        assert_eq!(render("[{}]", Dialect::Jsx).unwrap(), "{}");
+        assert_eq!(render("[]", Dialect::Jsx).unwrap(), "[]");
+        // Not every element a pair, so an array, which a tuple can't be in.
+        assert_eq!(render("[{a, 1}, 2]", Dialect::Jsx).unwrap_err(), "InvalidEjson at path $[0]");
+    }
+
+    #[test]
+    fn maps() {
+        assert_eq!(render("#{<<\"a\">> => nil, b => #{}}", Dialect::Maps).unwrap(), r#"{"a":null,"b":{}}"#);
+        assert_eq!(render("#{<<\"a\">> => 1}", Dialect::Jiffy).unwrap(), r#"{"a":1}"#);
+        assert_eq!(render("#{[1] => 1}", Dialect::Maps).unwrap_err(), "InvalidEjson at path $.map[0].key");
+        assert_eq!(render("#{a => <<255>>}", Dialect::Maps).unwrap_err(), "InvalidEjson at path $.map[0].val");
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(render("[true, false, null, nil, ok]", Dialect::Jiffy).unwrap(), r#"[true,false,null,"nil","ok"]"#);
+        assert_eq!(render("[true, false, null, nil]", Dialect::Maps).unwrap(), r#"[true,false,"null",null]"#);
+        let options = EjsonOptions{ null: "undefined".to_string(), ..EjsonOptions::new(Dialect::Jsx) };
+        let term = parse_erlang_term("[undefined, null]", &Limits::default()).unwrap();
+        assert_eq!(ejson_to_json(&term, &options).unwrap(), r#"[null,"null"]"#);
+        assert_eq!(render("\"ab\"", Dialect::Jiffy).unwrap(), "[97,98]");
+        assert_eq!(render("<<\"\\\"\">>", Dialect::Jiffy).unwrap(), r#""\"""#);
+    }
+
+    #[test]
+    fn terms_without_json() {
+        let error = ejson_to_json(&parse_erlang_term("[1, {a, b}]", &Limits::default()).unwrap(), &EjsonOptions::default());
+        assert_eq!(error.unwrap_err().error_code, ErrorCode::InvalidEjson);
+        assert!(render("[1 | 2]", Dialect::Jiffy).is_err());
+        assert!(render("<<1:3>>", Dialect::Jiffy).is_err());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let text = r#"{"a":[1,-2.5,"x",true,false,null,{}],"b":{"c":[]},"d":"\u00e9\n"}"#;
+        for &dialect in &[Dialect::Jiffy, Dialect::Jsx, Dialect::Maps] {
+            let options = EjsonOptions::new(dialect);
+            let term = parse_json_term(text, &options).unwrap();
+            let json = ejson_to_json(&term, &options).unwrap();
+            assert_eq!(parse_json_term(&json, &options).unwrap(), term, "{:?}", dialect);
+        }
+    }
+
+    #[test]
+    fn composer() {
+        let term = parse_erlang_term("{[{a, [1, 2]}]}", &Limits::default()).unwrap();
+        let mut c = EjsonComposer::with_options(EjsonOptions::default());
+        term.compose(&mut c);
+        assert_eq!(c.result().unwrap(), r#"{"a":[1,2]}"#);
+        let pretty = PrettyOptions{ width: 0, color: true, ..PrettyOptions::default() };
+        let mut c = EjsonComposer::with_pretty(EjsonOptions::default(), pretty);
+        term.compose(&mut c);
+        assert_eq!(c.result().unwrap(), "{\n  \"a\": [\n    1,\n    2\n  ]\n}");
+    }
+}
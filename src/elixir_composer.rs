@@ -0,0 +1,411 @@
+use std::fmt::Write;
+use num_bigint::BigInt;
+
+use crate::erlang_composer::push_erlang_float;
+use crate::parser::{ElemCompose, Pid, Port, Reference, Fun, NewFun, Export};
+
+// A rendered element together with what its container needs to pick the
+// keyword list, keyword map and struct sugar.
+struct Value {
+    text: String,
+    atom: Option<String>,
+    // Set for `{atom, value}` pairs, holds the rendered value.
+    keyword: Option<(String, String)>
+}
+
+impl Value {
+    fn text(text: String) -> Value {
+        Value{ text, atom: None, keyword: None }
+    }
+}
+
+// Renders terms the way Elixir's `IO.inspect` does. Containers are buffered
+// until they end, because the sugar depends on all of their elements.
+pub struct ElixirComposer {
+    result: String,
+    stack: Vec<Frame>
+}
+
+// Elements of an open container, `tail` is set once the last element is
+// the tail of an improper list.
+struct Frame {
+    items: Vec<Value>,
+    tail: bool
+}
+
+impl ElixirComposer {
+    pub fn new() -> ElixirComposer {
+        ElixirComposer{
+            result: String::new(),
+            stack: vec![]
+        }
+    }
+
+    pub fn result(&self) -> &str {
+        &self.result
+    }
+
+    fn push_value(&mut self, value: Value) {
+        match self.stack.last_mut() {
+            Some(frame) => frame.items.push(value),
+            None => self.result = value.text
+        }
+    }
+
+    fn leaf(&mut self, text: String) {
+        self.push_value(Value::text(text));
+    }
+
+    fn open(&mut self) {
+        self.stack.push(Frame{ items: vec![], tail: false });
+    }
+
+    fn close(&mut self) -> Vec<Value> {
+        self.stack.pop().map(|frame| frame.items).unwrap_or_default()
+    }
+}
+
+impl Default for ElixirComposer {
+    fn default() -> Self {
+        ElixirComposer::new()
+    }
+}
+
+impl ElemCompose for ElixirComposer {
+    fn begin_list(&mut self, _len: u32) {
+        self.open();
+    }
+    fn list_tail(&mut self) {
+        if let Some(frame) = self.stack.last_mut() {
+            frame.tail = true;
+        }
+    }
+    fn end_list(&mut self) {
+        let tail = self.stack.last().is_some_and(|frame| frame.tail);
+        let mut items = self.close();
+        let mut text = String::from("[");
+        if tail {
+            let tail = items.pop().map(|item| item.text).unwrap_or_default();
+            push_joined(&mut text, items.iter().map(|item| item.text.as_str()));
+            text.push_str(" | ");
+            text.push_str(&tail);
+        } else if !items.is_empty() && items.iter().all(|item| item.keyword.is_some()) {
+            let pairs = items.into_iter().filter_map(|item| item.keyword);
+            push_keywords(&mut text, pairs);
+        } else {
+            push_joined(&mut text, items.iter().map(|item| item.text.as_str()));
+        }
+        text.push(']');
+        self.leaf(text);
+    }
+    fn begin_tuple(&mut self, _arity: u32) {
+        self.open();
+    }
+    fn end_tuple(&mut self) {
+        let items = self.close();
+        let mut text = String::from("{");
+        push_joined(&mut text, items.iter().map(|item| item.text.as_str()));
+        text.push('}');
+        let keyword = match items.as_slice() {
+            [Value{ atom: Some(key), .. }, value] if is_keyword_key(key) => Some((key.clone(), value.text.clone())),
+            _ => None
+        };
+        self.push_value(Value{ text, atom: None, keyword });
+    }
+    fn begin_map(&mut self, _len: u32) {
+        self.open();
+    }
+    fn map_key(&mut self) {}
+    fn map_value(&mut self) {}
+    fn end_map(&mut self) {
+        let items = self.close();
+        let mut pairs = vec![];
+        let mut struct_name = None;
+        let mut items = items.into_iter();
+        while let (Some(key), Some(value)) = (items.next(), items.next()) {
+            match (key.atom.as_deref(), value.atom) {
+                (Some("__struct__"), Some(name)) if struct_name.is_none() => struct_name = Some(name),
+                (_, atom) => pairs.push((key, Value{ atom, ..value }))
+            }
+        }
+        let mut text = String::from("%");
+        if let Some(name) = struct_name {
+            push_module(&mut text, &name);
+        }
+        text.push('{');
+        if pairs.iter().all(|(key, _)| key.atom.as_deref().is_some_and(is_keyword_key)) {
+            push_keywords(&mut text, pairs.into_iter().filter_map(|(key, value)| key.atom.map(|k| (k, value.text))));
+        } else {
+            let pairs: Vec<String> = pairs.into_iter().map(|(key, value)| key.text + " => " + &value.text).collect();
+            push_joined(&mut text, pairs.iter().map(String::as_str));
+        }
+        text.push('}');
+        self.leaf(text);
+    }
+    fn atom(&mut self, name: &str) {
+        let mut text = String::new();
+        push_elixir_atom(&mut text, name);
+        self.push_value(Value{ text, atom: Some(name.to_string()), keyword: None });
+    }
+    fn int(&mut self, v: i32) {
+        self.leaf(v.to_string());
+    }
+    fn bigint(&mut self, v: &BigInt) {
+        self.leaf(v.to_string());
+    }
+    fn float(&mut self, v: f64) {
+        let mut text = String::new();
+        push_erlang_float(&mut text, v);
+        self.leaf(text);
+    }
+    fn string(&mut self, chars: &[u8]) {
+        let mut text = String::new();
+        if chars.iter().all(|&ch| is_printable(ch as char) && ch < 0x80) {
+            let s: String = chars.iter().map(|&b| b as char).collect();
+            text.push_str("~c");
+            push_elixir_str(&mut text, &s);
+        } else {
+            text.push('[');
+            push_joined(&mut text, chars.iter().map(|b| b.to_string()).collect::<Vec<_>>().iter().map(String::as_str));
+            text.push(']');
+        }
+        self.leaf(text);
+    }
+    fn binary(&mut self, data: &[u8]) {
+        let mut text = String::new();
+        match std::str::from_utf8(data) {
+            Ok(s) if s.chars().all(is_printable) => push_elixir_str(&mut text, s),
+            _ => push_bytes(&mut text, data, None)
+        }
+        self.leaf(text);
+    }
+    fn bit_binary(&mut self, data: &[u8], bits: u8) {
+        let mut text = String::new();
+        push_bytes(&mut text, data, Some(bits));
+        self.leaf(text);
+    }
+    fn pid(&mut self, pid: &Pid) {
+        self.leaf(format!("#PID<0.{}.{}>", pid.id, pid.serial));
+    }
+    fn port(&mut self, port: &Port) {
+        self.leaf(format!("#Port<0.{}>", port.id));
+    }
+    fn reference(&mut self, reference: &Reference) {
+        let mut text = String::from("#Reference<0");
+        for id in reference.id.iter().rev() {
+            let _ = write!(text, ".{}", id);
+        }
+        text.push('>');
+        self.leaf(text);
+    }
+    fn atom_cache_ref(&mut self, index: u8) {
+        self.leaf(format!("#AtomCacheRef<{}>", index));
+    }
+    fn fun(&mut self, fun: &Fun) {
+        let mut text = format!("#Function<{}.{} in ", fun.index, fun.uniq);
+        push_module(&mut text, &fun.module);
+        text.push('>');
+        self.leaf(text);
+    }
+    fn new_fun(&mut self, fun: &NewFun) {
+        let mut text = format!("#Function<{}.{}/{} in ", fun.old_index, fun.old_uniq, fun.arity);
+        push_module(&mut text, &fun.module);
+        text.push('>');
+        self.leaf(text);
+    }
+    fn export(&mut self, export: &Export) {
+        let mut text = String::from("&");
+        push_module(&mut text, &export.module);
+        text.push('.');
+        if is_identifier(&export.function) {
+            text.push_str(&export.function);
+        } else {
+            push_elixir_str(&mut text, &export.function);
+        }
+        let _ = write!(text, "/{}", export.arity);
+        self.leaf(text);
+    }
+}
+
+fn push_joined<'a, I: Iterator<Item = &'a str>>(out: &mut String, items: I) {
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        out.push_str(item);
+    }
+}
+
+fn push_keywords<I: Iterator<Item = (String, String)>>(out: &mut String, pairs: I) {
+    for (i, (key, value)) in pairs.enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        if is_identifier(&key) {
+            out.push_str(&key);
+        } else {
+            push_elixir_str(out, &key);
+        }
+        out.push_str(": ");
+        out.push_str(&value);
+    }
+}
+
+fn push_bytes(out: &mut String, data: &[u8], bits: Option<u8>) {
+    out.push_str("<<");
+    let (whole, last) = match (bits, data.split_last()) {
+        (Some(bits), Some((&last, whole))) if (1..8).contains(&bits) => (whole, Some((last >> (8 - bits), bits))),
+        _ => (data, None)
+    };
+    let mut items: Vec<String> = whole.iter().map(|b| b.to_string()).collect();
+    if let Some((v, bits)) = last {
+        items.push(format!("{}::size({})", v, bits));
+    }
+    push_joined(out, items.iter().map(String::as_str));
+    out.push_str(">>");
+}
+
+fn is_printable(ch: char) -> bool {
+    !ch.is_control() || matches!(ch, '\n' | '\r' | '\t' | '\x0b' | '\x08' | '\x0c' | '\x1b' | '\x07')
+}
+
+// Lower case identifiers, as in `:ok`, `:valid?` or `key: value`.
+fn is_identifier(name: &str) -> bool {
+    let body = name.strip_suffix(['?', '!']).unwrap_or(name);
+    let mut chars = body.chars();
+    match chars.next() {
+        Some(first) => (first.is_ascii_lowercase() || first == '_')
+            && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '@'),
+        None => false
+    }
+}
+
+// Capitalised dotted names, `Foo.Bar` for the atom `Elixir.Foo.Bar`.
+fn is_alias(name: &str) -> bool {
+    name.split('.').all(|part| {
+        let mut chars = part.chars();
+        chars.next().is_some_and(|first| first.is_ascii_uppercase())
+            && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    })
+}
+
+fn is_keyword_key(name: &str) -> bool {
+    !name.starts_with("Elixir.")
+}
+
+pub fn push_elixir_atom(out: &mut String, name: &str) {
+    match name {
+        "true" | "false" | "nil" => out.push_str(name),
+        _ => match name.strip_prefix("Elixir.") {
+            Some(alias) if is_alias(alias) => out.push_str(alias),
+            _ if is_identifier(name) || is_alias(name) => {
+                out.push(':');
+                out.push_str(name);
+            },
+            _ => {
+                out.push(':');
+                push_elixir_str(out, name);
+            }
+        }
+    }
+}
+
+// Module names as written in calls: `String` or `:lists`.
+fn push_module(out: &mut String, name: &str) {
+    match name.strip_prefix("Elixir.") {
+        Some(alias) if is_alias(alias) => out.push_str(alias),
+        _ => push_elixir_atom(out, name)
+    }
+}
+
+pub fn push_elixir_str(out: &mut String, s: &str) {
+    out.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x0b' => out.push_str("\\v"),
+            '\x08' => out.push_str("\\b"),
+            '\x0c' => out.push_str("\\f"),
+            '\x1b' => out.push_str("\\e"),
+            '\x07' => out.push_str("\\a"),
+            '#' if chars.peek() == Some(&'{') => out.push_str("\\#"),
+            c if c.is_control() => { let _ = write!(out, "\\x{:02X}", c as u32); },
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ElixirComposer;
+    use crate::parser::{ElemCompose, Limits, Pid};
+    use crate::{decode, parse_erlang_term};
+    use crate::term::Term;
+
+    fn render(term: &Term) -> String {
+        let mut c = ElixirComposer::new();
+        term.compose(&mut c);
+        c.result().to_string()
+    }
+
+    // Erlang term text rendered as Elixir would inspect it.
+    fn inspect(text: &str) -> String {
+        render(&parse_erlang_term(text, &Limits::default()).unwrap())
+    }
+
+    #[test]
+    fn atoms_and_aliases() {
+        assert_eq!(inspect("ok"), ":ok");
+        assert_eq!(inspect("'valid?'"), ":valid?");
+        assert_eq!(inspect("'hello world'"), ":\"hello world\"");
+        assert_eq!(inspect("{true, false, nil}"), "{true, false, nil}");
+        assert_eq!(inspect("'Elixir.Foo.Bar'"), "Foo.Bar");
+        assert_eq!(inspect("'Elixir.foo'"), ":\"Elixir.foo\"");
+        assert_eq!(inspect("'Foo'"), ":Foo");
+    }
+
+    #[test]
+    fn charlists_and_strings() {
+        assert_eq!(inspect("\"abc\""), "~c\"abc\"");
+        assert_eq!(inspect("[1, 2, 3]"), "[1, 2, 3]");
+        assert_eq!(inspect("<<\"a#{b}\">>"), "\"a\\#{b}\"");
+        assert_eq!(inspect("<<\"caf\u{e9}\"/utf8>>"), "\"caf\u{e9}\"");
+        assert_eq!(inspect("<<0, 255>>"), "<<0, 255>>");
+    }
+
+    #[test]
+    fn bitstrings() {
+        assert_eq!(inspect("<<1, 5:3>>"), "<<1, 5::size(3)>>");
+        let mut c = ElixirComposer::new();
+        decode(&[131, 77, 0, 0, 0, 1, 1, 0x80], &mut c, &Limits::default()).unwrap();
+        assert_eq!(c.result(), "<<1::size(1)>>");
+        let mut c = ElixirComposer::new();
+        c.bit_binary(&[1, 2], 0);
+        assert_eq!(c.result(), "<<1, 2>>");
+    }
+
+    #[test]
+    fn keywords_maps_and_structs() {
+        assert_eq!(inspect("[{a, 1}, {'my key', 2}]"), "[a: 1, \"my key\": 2]");
+        assert_eq!(inspect("[{a, 1}, 2]"), "[{:a, 1}, 2]");
+        assert_eq!(inspect("[1 | 2]"), "[1 | 2]");
+        assert_eq!(inspect("#{a => 1}"), "%{a: 1}");
+        assert_eq!(inspect("#{1 => a}"), "%{1 => :a}");
+        assert_eq!(inspect("#{'__struct__' => 'Elixir.User', name => <<\"jo\">>}"), "%User{name: \"jo\"}");
+    }
+
+    #[test]
+    fn pids_and_funs() {
+        let pid = Pid{ node: "a@b".to_string(), id: 85, serial: 2, creation: 1 };
+        assert_eq!(render(&Term::Pid(pid)), "#PID<0.85.2>");
+        assert_eq!(inspect("#Ref<0.1.2.3>"), "#Reference<0.1.2.3>");
+        assert_eq!(inspect("fun 'Elixir.String':upcase/1"), "&String.upcase/1");
+        assert_eq!(inspect("fun lists:'Map'/2"), "&:lists.\"Map\"/2");
+    }
+}
@@ -0,0 +1,339 @@
+use std::fmt::Write;
+use num_bigint::BigInt;
+
+use crate::parser::{ElemCompose, Pid, Port, Reference, Fun, NewFun, Export};
+
+const RESERVED: [&str; 29] = [
+    "after", "and", "andalso", "band", "begin", "bnot", "bor", "bsl", "bsr", "bxor",
+    "case", "catch", "cond", "div", "else", "end", "fun", "if", "let", "maybe",
+    "not", "of", "or", "orelse", "receive", "rem", "try", "when", "xor"
+];
+
+enum Frame {
+    Seq { first: bool },
+    Map { first: bool },
+    Tail
+}
+
+// Renders terms the way `io:format("~p")` does, on a single line. Pure data
+// reads back with `erl_scan`/`erl_parse` once a `.` is appended.
+pub struct ErlangComposer {
+    result: String,
+    stack: Vec<Frame>
+}
+
+impl ErlangComposer {
+    pub fn new() -> ErlangComposer {
+        ErlangComposer{
+            result: String::new(),
+            stack: vec![]
+        }
+    }
+
+    pub fn result(&self) -> &str {
+        &self.result
+    }
+
+    fn value_start(&mut self) {
+        if let Some(Frame::Seq{ first }) = self.stack.last_mut() {
+            if *first {
+                *first = false;
+            } else {
+                self.result.push(',');
+            }
+        }
+    }
+
+    fn open(&mut self, start: &str, frame: Frame) {
+        self.value_start();
+        self.result.push_str(start);
+        self.stack.push(frame);
+    }
+
+    fn close(&mut self, end: &str) {
+        self.stack.pop();
+        self.result.push_str(end);
+    }
+
+    fn leaf<T: std::fmt::Display>(&mut self, v: T) {
+        self.value_start();
+        let _ = write!(self.result, "{}", v);
+    }
+}
+
+impl Default for ErlangComposer {
+    fn default() -> Self {
+        ErlangComposer::new()
+    }
+}
+
+impl ElemCompose for ErlangComposer {
+    fn begin_list(&mut self, _len: u32) {
+        self.open("[", Frame::Seq{ first: true });
+    }
+    fn list_tail(&mut self) {
+        if let Some(top) = self.stack.last_mut() {
+            *top = Frame::Tail;
+        }
+        self.result.push('|');
+    }
+    fn end_list(&mut self) {
+        self.close("]");
+    }
+    fn begin_tuple(&mut self, _arity: u32) {
+        self.open("{", Frame::Seq{ first: true });
+    }
+    fn end_tuple(&mut self) {
+        self.close("}");
+    }
+    fn begin_map(&mut self, _len: u32) {
+        self.open("#{", Frame::Map{ first: true });
+    }
+    fn map_key(&mut self) {
+        if let Some(Frame::Map{ first }) = self.stack.last_mut() {
+            if *first {
+                *first = false;
+            } else {
+                self.result.push(',');
+            }
+        }
+    }
+    fn map_value(&mut self) {
+        self.result.push_str(" => ");
+    }
+    fn end_map(&mut self) {
+        self.close("}");
+    }
+    fn atom(&mut self, name: &str) {
+        self.value_start();
+        push_erlang_atom(&mut self.result, name);
+    }
+    fn int(&mut self, v: i32) {
+        self.leaf(v);
+    }
+    fn bigint(&mut self, v: &BigInt) {
+        self.leaf(v);
+    }
+    fn float(&mut self, v: f64) {
+        self.value_start();
+        push_erlang_float(&mut self.result, v);
+    }
+    fn string(&mut self, chars: &[u8]) {
+        self.value_start();
+        if chars.iter().all(|&ch| is_printable(ch as char)) {
+            let s: String = chars.iter().map(|&b| b as char).collect();
+            push_erlang_str(&mut self.result, &s, '"');
+        } else {
+            push_byte_list(&mut self.result, "[", chars, "]");
+        }
+    }
+    fn binary(&mut self, data: &[u8]) {
+        self.value_start();
+        push_erlang_binary(&mut self.result, data);
+    }
+    fn bit_binary(&mut self, data: &[u8], bits: u8) {
+        self.value_start();
+        match data.split_last() {
+            Some((&last, whole)) if (1..8).contains(&bits) => {
+                push_byte_list(&mut self.result, "<<", whole, "");
+                if !whole.is_empty() {
+                    self.result.push(',');
+                }
+                let _ = write!(self.result, "{}:{}>>", last >> (8 - bits), bits);
+            },
+            _ => push_erlang_binary(&mut self.result, data)
+        }
+    }
+    fn pid(&mut self, pid: &Pid) {
+        self.value_start();
+        push_pid(&mut self.result, pid);
+    }
+    fn port(&mut self, port: &Port) {
+        self.leaf(format_args!("#Port<0.{}>", port.id));
+    }
+    fn reference(&mut self, reference: &Reference) {
+        self.value_start();
+        self.result.push_str("#Ref<0");
+        for id in reference.id.iter().rev() {
+            let _ = write!(self.result, ".{}", id);
+        }
+        self.result.push('>');
+    }
+    fn atom_cache_ref(&mut self, index: u8) {
+        self.leaf(format_args!("#AtomCacheRef<{}>", index));
+    }
+    fn fun(&mut self, fun: &Fun) {
+        self.value_start();
+        self.result.push_str("#Fun<");
+        push_erlang_atom(&mut self.result, &fun.module);
+        let _ = write!(self.result, ".{}.{}>", fun.index, fun.uniq);
+    }
+    fn new_fun(&mut self, fun: &NewFun) {
+        self.value_start();
+        self.result.push_str("#Fun<");
+        push_erlang_atom(&mut self.result, &fun.module);
+        let _ = write!(self.result, ".{}.{}>", fun.old_index, fun.old_uniq);
+    }
+    fn export(&mut self, export: &Export) {
+        self.value_start();
+        self.result.push_str("fun ");
+        push_erlang_atom(&mut self.result, &export.module);
+        self.result.push(':');
+        push_erlang_atom(&mut self.result, &export.function);
+        let _ = write!(self.result, "/{}", export.arity);
+    }
+}
+
+fn push_pid(out: &mut String, pid: &Pid) {
+    let _ = write!(out, "<0.{}.{}>", pid.id, pid.serial);
+}
+
+fn is_printable(ch: char) -> bool {
+    (' '..='~').contains(&ch) || matches!(ch, '\n' | '\r' | '\t' | '\x0b' | '\x08' | '\x0c' | '\x1b')
+}
+
+pub fn push_erlang_atom(out: &mut String, name: &str) {
+    let mut chars = name.chars();
+    let bare = match chars.next() {
+        Some(first) => first.is_ascii_lowercase()
+            && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '@')
+            && !RESERVED.contains(&name),
+        None => false
+    };
+    if bare {
+        out.push_str(name);
+    } else {
+        push_erlang_str(out, name, '\'');
+    }
+}
+
+pub fn push_erlang_str(out: &mut String, s: &str, quote: char) {
+    out.push(quote);
+    for ch in s.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x0b' => out.push_str("\\v"),
+            '\x08' => out.push_str("\\b"),
+            '\x0c' => out.push_str("\\f"),
+            '\x1b' => out.push_str("\\e"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            },
+            c if (c as u32) < 0x20 || c == '\x7f' => { let _ = write!(out, "\\x{{{:X}}}", c as u32); },
+            c => out.push(c)
+        }
+    }
+    out.push(quote);
+}
+
+// Erlang floats always need a fraction before any exponent.
+pub fn push_erlang_float(out: &mut String, v: f64) {
+    let s = format!("{:?}", v);
+    match s.find('e') {
+        Some(e) if !s[..e].contains('.') => {
+            out.push_str(&s[..e]);
+            out.push_str(".0");
+            out.push_str(&s[e..]);
+        },
+        _ => out.push_str(&s)
+    }
+}
+
+fn push_erlang_binary(out: &mut String, data: &[u8]) {
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.is_empty() && text.chars().all(|ch| is_printable(ch) || ch > '\u{a0}') => {
+            out.push_str("<<");
+            push_erlang_str(out, text, '"');
+            if !text.is_ascii() {
+                out.push_str("/utf8");
+            }
+            out.push_str(">>");
+        },
+        _ => push_byte_list(out, "<<", data, ">>")
+    }
+}
+
+fn push_byte_list(out: &mut String, start: &str, data: &[u8], end: &str) {
+    out.push_str(start);
+    for (i, byte) in data.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{}", byte);
+    }
+    out.push_str(end);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ErlangComposer;
+    use crate::parser::{ElemCompose, Limits, Pid, Reference};
+    use crate::{decode, parse_erlang_term};
+    use crate::term::Term;
+
+    fn render(term: &Term) -> String {
+        let mut c = ErlangComposer::new();
+        term.compose(&mut c);
+        c.result().to_string()
+    }
+
+    // Text to term and back, as printed.
+    fn round_trip(text: &str) -> String {
+        render(&parse_erlang_term(text, &Limits::default()).unwrap())
+    }
+
+    #[test]
+    fn atom_quoting() {
+        assert_eq!(render(&Term::atom("ok")), "ok");
+        assert_eq!(render(&Term::atom("node@host_1")), "node@host_1");
+        assert_eq!(render(&Term::atom("Ok")), "'Ok'");
+        assert_eq!(render(&Term::atom("")), "''");
+        assert_eq!(render(&Term::atom("receive")), "'receive'");
+        assert_eq!(render(&Term::atom("it's\n")), "'it\\'s\\n'");
+        assert_eq!(render(&Term::atom("caf\u{e9}")), "'caf\u{e9}'");
+    }
+
+    #[test]
+    fn strings_and_binaries() {
+        assert_eq!(round_trip("\"say \\\"hi\\\"\""), "\"say \\\"hi\\\"\"");
+        assert_eq!(round_trip("[1, 2, 3]"), "[1,2,3]");
+        assert_eq!(round_trip("<<\"abc\">>"), "<<\"abc\">>");
+        assert_eq!(round_trip("<<\"\u{e9}\"/utf8>>"), "<<\"\u{e9}\"/utf8>>");
+        assert_eq!(round_trip("<<0, 255>>"), "<<0,255>>");
+        assert_eq!(round_trip("<<>>"), "<<>>");
+    }
+
+    #[test]
+    fn bitstrings() {
+        assert_eq!(round_trip("<<5:3>>"), "<<5:3>>");
+        assert_eq!(round_trip("<<1, 2, 1:1>>"), "<<1,2,1:1>>");
+        let mut c = ErlangComposer::new();
+        decode(&[131, 77, 0, 0, 0, 2, 4, 7, 0xf0], &mut c, &Limits::default()).unwrap();
+        assert_eq!(c.result(), "<<7,15:4>>");
+        let mut c = ErlangComposer::new();
+        c.bit_binary(&[1, 2], 0);
+        assert_eq!(c.result(), "<<1,2>>");
+    }
+
+    #[test]
+    fn lists_tuples_and_maps() {
+        assert_eq!(round_trip("[a, {b, []} | c]"), "[a,{b,[]}|c]");
+        assert_eq!(round_trip("[[1 | 2], {}]"), "[[1|2],{}]");
+        assert_eq!(round_trip("#{a => [1], {k} => #{}}"), "#{a => [1],{k} => #{}}");
+        assert_eq!(round_trip("{-1, 2.5, 1.0e300, 18446744073709551616}"), "{-1,2.5,1.0e300,18446744073709551616}");
+    }
+
+    #[test]
+    fn pids_refs_and_funs() {
+        let pid = Pid{ node: "a@b".to_string(), id: 85, serial: 2, creation: 1 };
+        assert_eq!(render(&Term::Pid(pid)), "<0.85.2>");
+        let reference = Reference{ node: "a@b".to_string(), creation: 1, id: vec![3, 2, 1] };
+        assert_eq!(render(&Term::Reference(reference)), "#Ref<0.1.2.3>");
+        assert_eq!(round_trip("#Port<0.7>"), "#Port<0.7>");
+        assert_eq!(round_trip("fun 'M':'end'/0"), "fun 'M':'end'/0");
+    }
+}
@@ -0,0 +1,830 @@
+use std::str::FromStr;
+use num_bigint::{BigInt, Sign};
+use num_traits::ToPrimitive;
+
+use crate::parser::{Limits, ParseError, Pid, Port, Reference};
+use crate::term::Term;
+
+// Node given to pid, port and reference literals, which don't name one.
+const LOCAL_NODE: &str = "nonode@nohost";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Atom(String),
+    Int(BigInt),
+    Float(f64),
+    Str(String),
+    Pid(u32, u32),
+    Port(u32),
+    Ref(Vec<u32>),
+    Punct(&'static str)
+}
+
+const PUNCTS: [&str; 17] = [
+    "<<", ">>", "#{", "=>", "{", "}", "[", "]", "(", ")", "|", ",", ":", "/", "-", "+", "."
+];
+
+// Parses Erlang term text as `file:consult` does: every term ends with a
+// dot, the dot after the last one may be left out. No binary may be sized
+// past `limits.max_alloc`.
+pub fn parse_erlang_terms(text: &str, limits: &Limits) -> Result<Vec<Term>, ParseError> {
+    let mut p = Parser{ lexer: Lexer{ text, pos: 0 }, peeked: None, max_alloc: limits.max_alloc };
+    let mut terms = vec![];
+    while p.peek()?.is_some() {
+        terms.push(p.term()?);
+        if p.peek()?.is_some() {
+            p.expect(".")?;
+        }
+    }
+    Ok(terms)
+}
+
+// Parses exactly one term, with or without the final dot.
+pub fn parse_erlang_term(text: &str, limits: &Limits) -> Result<Term, ParseError> {
+    let mut terms = parse_erlang_terms(text, limits)?;
+    match terms.len() {
+        1 => Ok(terms.remove(0)),
+        _ => Err(ParseError::invalid_term_syntax().located(0, None))
+    }
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize
+}
+
+impl<'a> Lexer<'a> {
+    fn error(&self, pos: usize) -> ParseError {
+        ParseError::invalid_term_syntax().located(pos as u64, None)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let ch = self.peek_char()?;
+        self.pos += ch.len_utf8();
+        Some(ch)
+    }
+
+    fn skip_blanks(&mut self) {
+        loop {
+            match self.peek_char() {
+                Some(ch) if ch.is_whitespace() => { self.next_char(); },
+                Some('%') => {
+                    while !matches!(self.next_char(), Some('\n') | None) {}
+                },
+                _ => return
+            }
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> &'a str {
+        let start = self.pos;
+        while self.peek_char().is_some_and(&f) {
+            self.next_char();
+        }
+        &self.text[start..self.pos]
+    }
+
+    // Returns the token and the offset it starts at.
+    fn next(&mut self) -> Result<Option<(usize, Token)>, ParseError> {
+        self.skip_blanks();
+        let start = self.pos;
+        let ch = match self.peek_char() {
+            Some(ch) => ch,
+            None => return Ok(None)
+        };
+        let rest = self.rest();
+        let token = if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_digit()) {
+            self.pos += 1;
+            let parts = self.literal_ids(start)?;
+            match parts.as_slice() {
+                [_, id, serial] => Token::Pid(*id, *serial),
+                _ => return Err(self.error(start))
+            }
+        } else if rest.starts_with("#Ref<") {
+            self.pos += 5;
+            let mut parts = self.literal_ids(start)?;
+            // Printed most significant word first, after the node number.
+            parts.remove(0);
+            parts.reverse();
+            Token::Ref(parts)
+        } else if rest.starts_with("#Port<") {
+            self.pos += 6;
+            match self.literal_ids(start)?.as_slice() {
+                [_, id] => Token::Port(*id),
+                _ => return Err(self.error(start))
+            }
+        } else if let Some(punct) = PUNCTS.iter().find(|p| rest.starts_with(**p)) {
+            self.pos += punct.len();
+            Token::Punct(punct)
+        } else if ch.is_ascii_digit() {
+            self.number(start)?
+        } else if ch == '$' {
+            self.next_char();
+            let ch = self.char_literal(start)?;
+            Token::Int(BigInt::from(ch as u32))
+        } else if ch == '\'' {
+            self.next_char();
+            Token::Atom(self.quoted('\'', start)?)
+        } else if ch == '"' {
+            self.next_char();
+            Token::Str(self.quoted('"', start)?)
+        } else if ch.is_lowercase() {
+            let name = self.take_while(|c| c.is_alphanumeric() || c == '_' || c == '@');
+            if name == "fun" {
+                Token::Punct("fun")
+            } else {
+                Token::Atom(name.to_string())
+            }
+        } else {
+            // Variables and anything else can't appear in a literal term.
+            return Err(self.error(start));
+        };
+        Ok(Some((start, token)))
+    }
+
+    // The dot separated numbers of `<0.1.2>`, up to the closing `>`.
+    fn literal_ids(&mut self, start: usize) -> Result<Vec<u32>, ParseError> {
+        let mut parts = vec![];
+        loop {
+            let digits = self.take_while(|c| c.is_ascii_digit());
+            parts.push(digits.parse::<u32>().map_err(|_| self.error(start))?);
+            match self.next_char() {
+                Some('.') => {},
+                Some('>') => return Ok(parts),
+                _ => return Err(self.error(start))
+            }
+        }
+    }
+
+    fn number(&mut self, start: usize) -> Result<Token, ParseError> {
+        let digits = self.take_while(|c| c.is_ascii_digit() || c == '_');
+        let int = parse_radix(digits, 10).ok_or_else(|| self.error(start))?;
+        if self.rest().starts_with('#') {
+            let radix = int.to_u32().filter(|r| (2..=36).contains(r)).ok_or_else(|| self.error(start))?;
+            self.next_char();
+            let digits = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            return parse_radix(digits, radix).map(Token::Int).ok_or_else(|| self.error(start));
+        }
+        let rest = self.rest();
+        if !(rest.starts_with('.') && rest[1..].starts_with(|c: char| c.is_ascii_digit())) {
+            return Ok(Token::Int(int));
+        }
+        self.next_char();
+        self.take_while(|c| c.is_ascii_digit() || c == '_');
+        if self.rest().starts_with(['e', 'E']) {
+            self.next_char();
+            if self.rest().starts_with(['+', '-']) {
+                self.next_char();
+            }
+            self.take_while(|c| c.is_ascii_digit());
+        }
+        self.text[start..self.pos]
+            .replace('_', "")
+            .parse::<f64>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .map(Token::Float)
+            .ok_or_else(|| self.error(start))
+    }
+
+    fn quoted(&mut self, quote: char, start: usize) -> Result<String, ParseError> {
+        let mut res = String::new();
+        loop {
+            match self.next_char() {
+                Some(ch) if ch == quote => return Ok(res),
+                Some('\\') => res.push(self.escape(start)?),
+                Some(ch) => res.push(ch),
+                None => return Err(self.error(start))
+            }
+        }
+    }
+
+    fn char_literal(&mut self, start: usize) -> Result<char, ParseError> {
+        match self.next_char() {
+            Some('\\') => self.escape(start),
+            Some(ch) => Ok(ch),
+            None => Err(self.error(start))
+        }
+    }
+
+    fn escape(&mut self, start: usize) -> Result<char, ParseError> {
+        let ch = self.next_char().ok_or_else(|| self.error(start))?;
+        let code = match ch {
+            'b' => 8,
+            'd' => 127,
+            'e' => 27,
+            'f' => 12,
+            'n' => 10,
+            'r' => 13,
+            's' => 32,
+            't' => 9,
+            'v' => 11,
+            '0'..='7' => {
+                let mut code = ch.to_digit(8).unwrap_or(0);
+                for _ in 0..2 {
+                    match self.peek_char().and_then(|c| c.to_digit(8)) {
+                        Some(d) => {
+                            self.next_char();
+                            code = code * 8 + d;
+                        },
+                        None => break
+                    }
+                }
+                code
+            },
+            'x' => {
+                let digits = if self.rest().starts_with('{') {
+                    self.next_char();
+                    let digits = self.take_while(|c| c.is_ascii_hexdigit());
+                    if self.next_char() != Some('}') {
+                        return Err(self.error(start));
+                    }
+                    digits
+                } else {
+                    let end = self.rest().char_indices().nth(2).map_or(self.rest().len(), |(i, _)| i);
+                    let digits = &self.text[self.pos..self.pos + end];
+                    self.pos += end;
+                    digits
+                };
+                u32::from_str_radix(digits, 16).map_err(|_| self.error(start))?
+            },
+            '^' => self.next_char().ok_or_else(|| self.error(start))? as u32 & 31,
+            ch => ch as u32
+        };
+        std::char::from_u32(code).ok_or_else(|| self.error(start))
+    }
+}
+
+fn parse_radix(digits: &str, radix: u32) -> Option<BigInt> {
+    let digits = digits.replace('_', "");
+    if digits.is_empty() {
+        return None;
+    }
+    BigInt::parse_bytes(digits.as_bytes(), radix)
+}
+
+// Containers whose closing bracket hasn't been read yet.
+enum Open {
+    Tuple(Vec<Term>),
+    List(Vec<Term>),
+    Tail(Vec<Term>),
+    Map(Vec<(Term, Term)>),
+    Value(Vec<(Term, Term)>, Term)
+}
+
+struct Parser<'a> {
+    lexer: Lexer<'a>,
+    peeked: Option<(usize, Token)>,
+    max_alloc: usize
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Result<Option<&(usize, Token)>, ParseError> {
+        if self.peeked.is_none() {
+            self.peeked = self.lexer.next()?;
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), ParseError> {
+        self.peek()?;
+        self.peeked.take().ok_or_else(|| self.lexer.error(self.lexer.pos))
+    }
+
+    fn eat(&mut self, punct: &str) -> Result<bool, ParseError> {
+        match self.peek()? {
+            Some((_, Token::Punct(p))) if *p == punct => {
+                self.peeked = None;
+                Ok(true)
+            },
+            _ => Ok(false)
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), ParseError> {
+        let (pos, token) = self.next()?;
+        match token {
+            Token::Punct(p) if p == punct => Ok(()),
+            _ => Err(self.lexer.error(pos))
+        }
+    }
+
+    // One of the given punctuation tokens.
+    fn expect_one(&mut self, puncts: &[&'static str]) -> Result<&'static str, ParseError> {
+        let (pos, token) = self.next()?;
+        match token {
+            Token::Punct(p) if puncts.contains(&p) => Ok(p),
+            _ => Err(self.lexer.error(pos))
+        }
+    }
+
+    // Builds nested terms with an explicit stack of open containers.
+    fn term(&mut self) -> Result<Term, ParseError> {
+        let mut stack: Vec<Open> = vec![];
+        loop {
+            let (pos, token) = self.next()?;
+            let mut value = match token {
+                Token::Punct("{") if !self.eat("}")? => {
+                    stack.push(Open::Tuple(vec![]));
+                    continue;
+                },
+                Token::Punct("{") => Term::Tuple(vec![]),
+                Token::Punct("[") if !self.eat("]")? => {
+                    stack.push(Open::List(vec![]));
+                    continue;
+                },
+                Token::Punct("[") => Term::nil(),
+                Token::Punct("#{") if !self.eat("}")? => {
+                    stack.push(Open::Map(vec![]));
+                    continue;
+                },
+                Token::Punct("#{") => Term::Map(vec![]),
+                Token::Punct("<<") => self.binary()?,
+                Token::Punct("fun") => self.export()?,
+                Token::Punct(sign @ ("-" | "+")) => match self.next()? {
+                    (_, Token::Int(v)) => int_term(if sign == "-" { -v } else { v }),
+                    (_, Token::Float(v)) => Term::Float(if sign == "-" { -v } else { v }),
+                    (pos, _) => return Err(self.lexer.error(pos))
+                },
+                Token::Int(v) => int_term(v),
+                Token::Float(v) => Term::Float(v),
+                Token::Atom(name) => Term::Atom(name),
+                Token::Str(mut s) => {
+                    // Adjacent string literals are one string.
+                    while let Some((_, Token::Str(_))) = self.peek()? {
+                        if let (_, Token::Str(more)) = self.next()? {
+                            s.push_str(&more);
+                        }
+                    }
+                    string_term(&s)
+                },
+                Token::Pid(id, serial) => Term::Pid(Pid{ node: LOCAL_NODE.to_string(), id, serial, creation: 0 }),
+                Token::Port(id) => Term::Port(Port{ node: LOCAL_NODE.to_string(), id, creation: 0 }),
+                Token::Ref(id) => Term::Reference(Reference{ node: LOCAL_NODE.to_string(), creation: 0, id }),
+                Token::Punct(_) => return Err(self.lexer.error(pos))
+            };
+            loop {
+                match stack.pop() {
+                    None => return Ok(value),
+                    Some(Open::Tuple(mut items)) => {
+                        items.push(value);
+                        if self.expect_one(&[",", "}"])? == "," {
+                            stack.push(Open::Tuple(items));
+                            break;
+                        }
+                        value = Term::Tuple(items);
+                    },
+                    Some(Open::List(mut items)) => {
+                        items.push(value);
+                        match self.expect_one(&[",", "|", "]"])? {
+                            "," => {
+                                stack.push(Open::List(items));
+                                break;
+                            },
+                            "|" => {
+                                stack.push(Open::Tail(items));
+                                break;
+                            },
+                            _ => value = Term::List(items, None)
+                        }
+                    },
+                    Some(Open::Tail(items)) => {
+                        self.expect("]")?;
+                        value = cons(items, value);
+                    },
+                    Some(Open::Map(pairs)) => {
+                        self.expect("=>")?;
+                        stack.push(Open::Value(pairs, value));
+                        break;
+                    },
+                    Some(Open::Value(mut pairs, key)) => {
+                        pairs.push((key, value));
+                        if self.expect_one(&[",", "}"])? == "," {
+                            stack.push(Open::Map(pairs));
+                            break;
+                        }
+                        value = Term::Map(pairs);
+                    }
+                }
+            }
+        }
+    }
+
+    // `fun M:F/A`, the only fun with a literal form.
+    fn export(&mut self) -> Result<Term, ParseError> {
+        let module = self.atom()?;
+        self.expect(":")?;
+        let function = self.atom()?;
+        self.expect("/")?;
+        match self.next()? {
+            (_, Token::Int(arity)) if arity.to_u8().is_some() => {
+                Ok(Term::Export(crate::parser::Export{ module, function, arity: arity.to_u8().unwrap_or(0) }))
+            },
+            (pos, _) => Err(self.lexer.error(pos))
+        }
+    }
+
+    fn atom(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            (_, Token::Atom(name)) => Ok(name),
+            (pos, _) => Err(self.lexer.error(pos))
+        }
+    }
+
+    fn binary(&mut self) -> Result<Term, ParseError> {
+        let mut bits = BitWriter{ data: vec![], len: 0, max_bits: self.max_alloc.saturating_mul(8) };
+        if self.eat(">>")? {
+            return Ok(Term::Binary(vec![]));
+        }
+        loop {
+            let (pos, token) = self.next()?;
+            let value = match token {
+                Token::Punct("-") => match self.next()? {
+                    (_, Token::Int(v)) => Segment::Int(-v),
+                    (_, Token::Float(v)) => Segment::Float(-v),
+                    (pos, _) => return Err(self.lexer.error(pos))
+                },
+                Token::Int(v) => Segment::Int(v),
+                Token::Float(v) => Segment::Float(v),
+                Token::Str(s) => Segment::Str(s),
+                _ => return Err(self.lexer.error(pos))
+            };
+            let size = if self.eat(":")? {
+                match self.next()? {
+                    (_, Token::Int(v)) => Some(v.to_usize().ok_or_else(|| self.lexer.error(pos))?),
+                    (pos, _) => return Err(self.lexer.error(pos))
+                }
+            } else {
+                None
+            };
+            let mut spec = SegmentSpec{ kind: SegmentKind::Integer, size, unit: None, little: false };
+            if self.eat("/")? {
+                loop {
+                    let name = self.atom()?;
+                    match name.as_str() {
+                        "integer" => spec.kind = SegmentKind::Integer,
+                        "float" => spec.kind = SegmentKind::Float,
+                        "binary" | "bytes" => spec.kind = SegmentKind::Binary,
+                        "utf8" => spec.kind = SegmentKind::Utf8,
+                        "utf16" => spec.kind = SegmentKind::Utf16,
+                        "utf32" => spec.kind = SegmentKind::Utf32,
+                        "big" | "signed" | "unsigned" => {},
+                        "little" => spec.little = true,
+                        "unit" => {
+                            self.expect(":")?;
+                            match self.next()? {
+                                (_, Token::Int(v)) => spec.unit = Some(v.to_usize().ok_or_else(|| self.lexer.error(pos))?),
+                                (pos, _) => return Err(self.lexer.error(pos))
+                            }
+                        },
+                        _ => return Err(self.lexer.error(pos))
+                    }
+                    if !self.eat("-")? {
+                        break;
+                    }
+                }
+            }
+            bits.segment(&value, &spec).ok_or_else(|| self.lexer.error(pos))?;
+            if self.expect_one(&[",", ">>"])? == ">>" {
+                return Ok(bits.into_term());
+            }
+        }
+    }
+}
+
+fn int_term(v: BigInt) -> Term {
+    match v.to_i32() {
+        Some(v) => Term::Int(v),
+        None => Term::BigInt(v)
+    }
+}
+
+// Lists of small code points encode as STRING_EXT, like `term_to_binary`.
+fn string_term(s: &str) -> Term {
+    let chars: Vec<u32> = s.chars().map(|ch| ch as u32).collect();
+    if chars.is_empty() {
+        Term::nil()
+    } else if chars.len() <= u16::MAX as usize && chars.iter().all(|&ch| ch < 256) {
+        Term::String(chars.into_iter().map(|ch| ch as u8).collect())
+    } else {
+        Term::List(chars.into_iter().map(|ch| Term::Int(ch as i32)).collect(), None)
+    }
+}
+
+// `[A, B | Tail]`, flattened when the tail is itself a list.
+fn cons(mut items: Vec<Term>, mut tail: Term) -> Term {
+    match tail {
+        Term::List(ref mut more, ref mut rest) => {
+            items.append(more);
+            Term::List(items, rest.take())
+        },
+        Term::String(ref chars) => {
+            items.extend(chars.iter().map(|&ch| Term::Int(ch as i32)));
+            Term::List(items, None)
+        },
+        _ => Term::List(items, Some(Box::new(tail)))
+    }
+}
+
+enum Segment {
+    Int(BigInt),
+    Float(f64),
+    Str(String)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SegmentKind {
+    Integer,
+    Float,
+    Binary,
+    Utf8,
+    Utf16,
+    Utf32
+}
+
+struct SegmentSpec {
+    kind: SegmentKind,
+    size: Option<usize>,
+    unit: Option<usize>,
+    little: bool
+}
+
+impl SegmentSpec {
+    // Size times unit, `default` when no size is given.
+    fn bits(&self, default: usize) -> Option<usize> {
+        self.size.unwrap_or(default).checked_mul(self.unit.unwrap_or(1))
+    }
+}
+
+struct BitWriter {
+    data: Vec<u8>,
+    len: usize,
+    // Longest binary allowed, in bits.
+    max_bits: usize
+}
+
+impl BitWriter {
+    fn push_bit(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.data.push(0);
+        }
+        if bit {
+            if let Some(last) = self.data.last_mut() {
+                *last |= 0x80 >> (self.len % 8);
+            }
+        }
+        self.len += 1;
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            for i in (0..8).rev() {
+                self.push_bit(byte >> i & 1 == 1);
+            }
+        }
+    }
+
+    // Lowest `bits` bits of `v` in two's complement, big endian.
+    fn push_int(&mut self, v: &BigInt, bits: usize, little: bool) -> Option<()> {
+        if bits > self.max_bits - self.len.min(self.max_bits) {
+            return None;
+        }
+        let modulus = BigInt::from(1) << bits;
+        let mut v = v % &modulus;
+        if v.sign() == Sign::Minus {
+            v += &modulus;
+        }
+        let (_, le) = v.to_bytes_le();
+        let bit = |i: usize| le.get(i / 8).is_some_and(|byte| byte >> (i % 8) & 1 == 1);
+        if little {
+            if !bits.is_multiple_of(8) {
+                return None;
+            }
+            for byte in 0..bits / 8 {
+                for i in (0..8).rev() {
+                    self.push_bit(bit(byte * 8 + i));
+                }
+            }
+        } else {
+            for i in (0..bits).rev() {
+                self.push_bit(bit(i));
+            }
+        }
+        Some(())
+    }
+
+    fn push_float(&mut self, v: f64, bits: usize, little: bool) -> Option<()> {
+        let mut bytes = match bits {
+            64 => v.to_be_bytes().to_vec(),
+            32 => (v as f32).to_be_bytes().to_vec(),
+            _ => return None
+        };
+        if little {
+            bytes.reverse();
+        }
+        self.push_bytes(&bytes);
+        Some(())
+    }
+
+    fn push_char(&mut self, ch: char, spec: &SegmentSpec) -> Option<()> {
+        match spec.kind {
+            SegmentKind::Utf8 => {
+                let mut buf = [0; 4];
+                self.push_bytes(ch.encode_utf8(&mut buf).as_bytes());
+            },
+            SegmentKind::Utf16 => {
+                let mut buf = [0; 2];
+                for unit in ch.encode_utf16(&mut buf) {
+                    let bytes = if spec.little { unit.to_le_bytes() } else { unit.to_be_bytes() };
+                    self.push_bytes(&bytes);
+                }
+            },
+            SegmentKind::Utf32 => {
+                let code = ch as u32;
+                let bytes = if spec.little { code.to_le_bytes() } else { code.to_be_bytes() };
+                self.push_bytes(&bytes);
+            },
+            SegmentKind::Integer => {
+                self.push_int(&BigInt::from(ch as u32), spec.bits(8)?, spec.little)?;
+            },
+            SegmentKind::Float => return None,
+            SegmentKind::Binary => {
+                if ch as u32 > 255 {
+                    return None;
+                }
+                self.push_bytes(&[ch as u8]);
+            }
+        }
+        Some(())
+    }
+
+    fn segment(&mut self, value: &Segment, spec: &SegmentSpec) -> Option<()> {
+        match (value, spec.kind) {
+            (Segment::Str(s), _) => {
+                for ch in s.chars() {
+                    self.push_char(ch, spec)?;
+                }
+                Some(())
+            },
+            (Segment::Int(v), SegmentKind::Integer) => {
+                self.push_int(v, spec.bits(8)?, spec.little)
+            },
+            (Segment::Int(v), SegmentKind::Float) => {
+                self.push_float(v.to_f64()?, spec.bits(64)?, spec.little)
+            },
+            (Segment::Float(v), SegmentKind::Float) => {
+                self.push_float(*v, spec.bits(64)?, spec.little)
+            },
+            (Segment::Int(v), SegmentKind::Utf8 | SegmentKind::Utf16 | SegmentKind::Utf32) => {
+                let ch = v.to_u32().and_then(std::char::from_u32)?;
+                self.push_char(ch, spec)
+            },
+            _ => None
+        }
+    }
+
+    fn into_term(self) -> Term {
+        match self.len % 8 {
+            0 => Term::Binary(self.data),
+            bits => Term::BitBinary(self.data, bits as u8)
+        }
+    }
+}
+
+impl FromStr for Term {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Term, ParseError> {
+        parse_erlang_term(text, &Limits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+
+    use super::{parse_erlang_term, parse_erlang_terms};
+    use crate::parser::{ErrorCode, Export, Limits, Pid, Port, Reference};
+    use crate::term::Term;
+
+    fn parse(text: &str) -> Term {
+        parse_erlang_term(text, &Limits::default()).unwrap()
+    }
+
+    fn error_code(text: &str) -> ErrorCode {
+        parse_erlang_term(text, &Limits::default()).unwrap_err().error_code
+    }
+
+    fn ints(items: &[i32]) -> Term {
+        Term::List(items.iter().map(|&v| Term::Int(v)).collect(), None)
+    }
+
+    #[test]
+    fn atoms_and_quoting() {
+        assert_eq!(parse("ok"), Term::atom("ok"));
+        assert_eq!(parse("node@host"), Term::atom("node@host"));
+        assert_eq!(parse("'Hello world'"), Term::atom("Hello world"));
+        assert_eq!(parse("'it\\'s'"), Term::atom("it's"));
+        assert_eq!(parse("'\\x{263a}\\n'"), Term::atom("\u{263a}\n"));
+        assert_eq!(error_code("Var"), ErrorCode::InvalidTermSyntax);
+        assert_eq!(error_code("'open"), ErrorCode::InvalidTermSyntax);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse("-42"), Term::Int(-42));
+        assert_eq!(parse("16#ff"), Term::Int(255));
+        assert_eq!(parse("1_000"), Term::Int(1000));
+        assert_eq!(parse("$a"), Term::Int(97));
+        assert_eq!(parse("2.5e3"), Term::Float(2500.0));
+        assert_eq!(parse("4294967296"), Term::BigInt(BigInt::from(4294967296u64)));
+        assert_eq!(error_code("1.0e400"), ErrorCode::InvalidTermSyntax);
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(parse("\"abc\""), Term::String(b"abc".to_vec()));
+        assert_eq!(parse("\"ab\" \"c\""), Term::String(b"abc".to_vec()));
+        assert_eq!(parse("\"\""), Term::nil());
+        assert_eq!(parse("\"\u{3c0}\""), ints(&[0x3c0]));
+        assert_eq!(parse("[$a, $\\n]"), ints(&[97, 10]));
+    }
+
+    #[test]
+    fn lists_tuples_and_maps() {
+        assert_eq!(parse("{}"), Term::Tuple(vec![]));
+        assert_eq!(parse("{ok, []}"), Term::Tuple(vec![Term::atom("ok"), Term::nil()]));
+        assert_eq!(parse("[1 | 2]"), Term::List(vec![Term::Int(1)], Some(Box::new(Term::Int(2)))));
+        assert_eq!(parse("[1 | [2]]"), ints(&[1, 2]));
+        assert_eq!(parse("#{}"), Term::Map(vec![]));
+        assert_eq!(
+            parse("#{a => 1, \"b\" => [x]}"),
+            Term::Map(vec![
+                (Term::atom("a"), Term::Int(1)),
+                (Term::String(b"b".to_vec()), Term::List(vec![Term::atom("x")], None))
+            ])
+        );
+        assert_eq!(error_code("#{a, b}"), ErrorCode::InvalidTermSyntax);
+        assert_eq!(error_code("{1, 2"), ErrorCode::InvalidTermSyntax);
+    }
+
+    #[test]
+    fn consults_many_terms() {
+        let terms = parse_erlang_terms("1. % one\n{two}.\n'three'", &Limits::default()).unwrap();
+        assert_eq!(terms, vec![Term::Int(1), Term::Tuple(vec![Term::atom("two")]), Term::atom("three")]);
+        assert_eq!(error_code("1. 2."), ErrorCode::InvalidTermSyntax);
+    }
+
+    #[test]
+    fn pids_ports_refs_and_funs() {
+        let node = "nonode@nohost".to_string();
+        assert_eq!(parse("<0.12.3>"), Term::Pid(Pid{ node: node.clone(), id: 12, serial: 3, creation: 0 }));
+        assert_eq!(parse("#Port<0.7>"), Term::Port(Port{ node: node.clone(), id: 7, creation: 0 }));
+        assert_eq!(parse("#Ref<0.1.2.3>"), Term::Reference(Reference{ node, creation: 0, id: vec![3, 2, 1] }));
+        assert_eq!(
+            parse("fun lists:map/2"),
+            Term::Export(Export{ module: "lists".to_string(), function: "map".to_string(), arity: 2 })
+        );
+        assert_eq!(error_code("<0.1>"), ErrorCode::InvalidTermSyntax);
+        assert_eq!(error_code("fun lists:map/256"), ErrorCode::InvalidTermSyntax);
+    }
+
+    #[test]
+    fn bit_syntax() {
+        assert_eq!(parse("<<>>"), Term::Binary(vec![]));
+        assert_eq!(parse("<<1, 2, \"ab\">>"), Term::Binary(vec![1, 2, 97, 98]));
+        assert_eq!(parse("<<256>>"), Term::Binary(vec![0]));
+        assert_eq!(parse("<<-1:16>>"), Term::Binary(vec![255, 255]));
+        assert_eq!(parse("<<1:16/little>>"), Term::Binary(vec![1, 0]));
+        assert_eq!(parse("<<1:2/unit:8>>"), Term::Binary(vec![0, 1]));
+        assert_eq!(parse("<<5:3>>"), Term::BitBinary(vec![0xa0], 3));
+        assert_eq!(parse("<<1:4, 15:4>>"), Term::Binary(vec![0x1f]));
+        assert_eq!(parse("<<1.5:32/float>>"), Term::Binary(1.5f32.to_be_bytes().to_vec()));
+        assert_eq!(parse("<<1.5/float-little>>"), Term::Binary(1.5f64.to_le_bytes().to_vec()));
+        assert_eq!(parse("<<\"\u{e9}\"/utf8>>"), Term::Binary(vec![0xc3, 0xa9]));
+        assert_eq!(parse("<<$a/utf16-little>>"), Term::Binary(vec![97, 0]));
+        assert_eq!(parse("<<\"a\":16>>"), Term::Binary(vec![0, 97]));
+        assert_eq!(error_code("<<1:12/little>>"), ErrorCode::InvalidTermSyntax);
+        assert_eq!(error_code("<<1:16/float>>"), ErrorCode::InvalidTermSyntax);
+        assert_eq!(error_code("<<1/bogus>>"), ErrorCode::InvalidTermSyntax);
+    }
+
+    #[test]
+    fn oversized_segments_are_errors() {
+        assert_eq!(error_code("<<1:18446744073709551615/unit:2>>"), ErrorCode::InvalidTermSyntax);
+        assert_eq!(error_code("<<1:99999999999>>"), ErrorCode::InvalidTermSyntax);
+        assert_eq!(error_code("<<1:1/unit:99999999999999999999>>"), ErrorCode::InvalidTermSyntax);
+        let limits = Limits{ max_alloc: 4, ..Limits::default() };
+        assert!(parse_erlang_term("<<1:32>>", &limits).is_ok());
+        assert!(parse_erlang_term("<<1:33>>", &limits).is_err());
+        assert!(parse_erlang_term("<<1:16, 1:24>>", &limits).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+use std::convert::TryFrom;
+use num_bigint::{BigInt, Sign};
+use num_traits::ToPrimitive;
+
+use crate::parser::tags::*;
+use crate::parser::{ElemCompose, ParseError, Pid, Port, Reference, Fun, NewFun, Export};
+use crate::term::Term;
+
+// Writes the external term format, the inverse of `parse`. Integers, atoms
+// and strings use the smallest encoding that fits. A length too big for its
+// field, such as an atom over 65535 bytes, fails the result.
+pub struct EtfComposer {
+    result: Vec<u8>,
+    // Per open list, whether it still needs its NIL tail.
+    lists: Vec<bool>,
+    overflow: bool
+}
+
+impl EtfComposer {
+    pub fn new() -> EtfComposer {
+        EtfComposer{
+            result: vec![VERSION],
+            lists: vec![],
+            overflow: false
+        }
+    }
+
+    pub fn result(&self) -> Result<&[u8], ParseError> {
+        match self.overflow {
+            true => Err(ParseError::length_overflow()),
+            false => Ok(&self.result)
+        }
+    }
+
+    pub fn into_result(self) -> Result<Vec<u8>, ParseError> {
+        match self.overflow {
+            true => Err(ParseError::length_overflow()),
+            false => Ok(self.result)
+        }
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.result.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.result.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn len16(&mut self, len: usize) {
+        match u16::try_from(len) {
+            Ok(len) => self.u16(len),
+            Err(_) => self.overflow = true
+        }
+    }
+
+    fn len(&mut self, len: usize) {
+        match u32::try_from(len) {
+            Ok(len) => self.u32(len),
+            Err(_) => self.overflow = true
+        }
+    }
+
+    fn pid_ext(&mut self, pid: &Pid) {
+        self.result.push(PID_EXT);
+        self.atom(&pid.node);
+        self.u32(pid.id);
+        self.u32(pid.serial);
+        self.result.push(pid.creation as u8);
+    }
+}
+
+impl Default for EtfComposer {
+    fn default() -> Self {
+        EtfComposer::new()
+    }
+}
+
+// Encodes a term in a form `parser::parse` decodes back to the same term.
+// The bytes can differ from `term_to_binary`: lists are never STRING_EXT
+// and pids, ports and references keep only a one byte creation.
+pub fn encode(term: &Term) -> Result<Vec<u8>, ParseError> {
+    let mut composer = EtfComposer::new();
+    term.compose(&mut composer);
+    composer.into_result()
+}
+
+impl ElemCompose for EtfComposer {
+    fn begin_list(&mut self, len: u32) {
+        if len == 0 {
+            self.result.push(NIL_EXT);
+        } else {
+            self.result.push(LIST_EXT);
+            self.u32(len);
+        }
+        self.lists.push(len > 0);
+    }
+    fn list_tail(&mut self) {
+        if let Some(nil) = self.lists.last_mut() {
+            *nil = false;
+        }
+    }
+    fn end_list(&mut self) {
+        if let Some(true) = self.lists.pop() {
+            self.result.push(NIL_EXT);
+        }
+    }
+    fn begin_tuple(&mut self, arity: u32) {
+        if arity < 256 {
+            self.result.push(SMALL_TUPLE_EXT);
+            self.result.push(arity as u8);
+        } else {
+            self.result.push(LARGE_TUPLE_EXT);
+            self.u32(arity);
+        }
+    }
+    fn end_tuple(&mut self) {}
+    fn begin_map(&mut self, len: u32) {
+        self.result.push(MAP_EXT);
+        self.u32(len);
+    }
+    fn map_key(&mut self) {}
+    fn map_value(&mut self) {}
+    fn end_map(&mut self) {}
+    fn atom(&mut self, name: &str) {
+        if name.len() < 256 {
+            self.result.push(SMALL_ATOM_UTF8_EXT);
+            self.result.push(name.len() as u8);
+        } else {
+            self.result.push(ATOM_UTF8_EXT);
+            self.len16(name.len());
+        }
+        self.result.extend_from_slice(name.as_bytes());
+    }
+    fn int(&mut self, v: i32) {
+        if (0..256).contains(&v) {
+            self.result.push(SMALL_INTEGER_EXT);
+            self.result.push(v as u8);
+        } else {
+            self.result.push(INTEGER_EXT);
+            self.result.extend_from_slice(&v.to_be_bytes());
+        }
+    }
+    fn bigint(&mut self, v: &BigInt) {
+        if let Some(v) = v.to_i32() {
+            return self.int(v);
+        }
+        let (sign, digits) = v.to_bytes_le();
+        if digits.len() < 256 {
+            self.result.push(SMALL_BIG_EXT);
+            self.result.push(digits.len() as u8);
+        } else {
+            self.result.push(LARGE_BIG_EXT);
+            self.len(digits.len());
+        }
+        self.result.push(if sign == Sign::Minus { 1 } else { 0 });
+        self.result.extend_from_slice(&digits);
+    }
+    fn float(&mut self, v: f64) {
+        self.result.push(NEW_FLOAT_EXT);
+        self.result.extend_from_slice(&v.to_be_bytes());
+    }
+    fn string(&mut self, chars: &[u8]) {
+        if chars.len() <= u16::MAX as usize {
+            self.result.push(STRING_EXT);
+            self.len16(chars.len());
+            self.result.extend_from_slice(chars);
+        } else {
+            self.begin_list(chars.len() as u32);
+            for &ch in chars {
+                self.int(ch as i32);
+            }
+            self.end_list();
+        }
+    }
+    fn binary(&mut self, data: &[u8]) {
+        self.result.push(BINARY_EXT);
+        self.len(data.len());
+        self.result.extend_from_slice(data);
+    }
+    fn bit_binary(&mut self, data: &[u8], bits: u8) {
+        self.result.push(BIT_BINARY_EXT);
+        self.len(data.len());
+        self.result.push(bits);
+        self.result.extend_from_slice(data);
+    }
+    fn pid(&mut self, pid: &Pid) {
+        self.pid_ext(pid);
+    }
+    fn port(&mut self, port: &Port) {
+        self.result.push(PORT_EXT);
+        self.atom(&port.node);
+        self.u32(port.id);
+        self.result.push(port.creation as u8);
+    }
+    fn reference(&mut self, reference: &Reference) {
+        self.result.push(NEW_REFERENCE_EXT);
+        self.len16(reference.id.len());
+        self.atom(&reference.node);
+        self.result.push(reference.creation as u8);
+        for &id in &reference.id {
+            self.u32(id);
+        }
+    }
+    fn atom_cache_ref(&mut self, index: u8) {
+        self.result.push(ATOM_CACHE_REF);
+        self.result.push(index);
+    }
+    fn fun(&mut self, fun: &Fun) {
+        self.result.push(FUN_EXT);
+        self.u32(0);
+        self.pid_ext(&fun.pid);
+        self.atom(&fun.module);
+        self.result.push(INTEGER_EXT);
+        self.result.extend_from_slice(&fun.index.to_be_bytes());
+        self.result.push(INTEGER_EXT);
+        self.result.extend_from_slice(&fun.uniq.to_be_bytes());
+    }
+    fn new_fun(&mut self, fun: &NewFun) {
+        let start = self.result.len();
+        self.result.push(NEW_FUN_EXT);
+        self.u32(0);
+        self.result.push(fun.arity);
+        self.result.extend_from_slice(&fun.uniq);
+        self.u32(fun.index);
+        self.u32(0);
+        self.atom(&fun.module);
+        self.result.push(INTEGER_EXT);
+        self.result.extend_from_slice(&fun.old_index.to_be_bytes());
+        self.result.push(INTEGER_EXT);
+        self.result.extend_from_slice(&fun.old_uniq.to_be_bytes());
+        self.pid_ext(&fun.pid);
+        // Size counts everything after the tag, itself included.
+        let size = (self.result.len() - start - 1) as u32;
+        self.result[start + 1..start + 5].copy_from_slice(&size.to_be_bytes());
+    }
+    fn export(&mut self, export: &Export) {
+        self.result.push(EXPORT_EXT);
+        self.atom(&export.module);
+        self.atom(&export.function);
+        self.result.push(SMALL_INTEGER_EXT);
+        self.result.push(export.arity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode;
+    use crate::parser::{ErrorCode, Reference};
+    use crate::term::Term;
+
+    #[test]
+    fn atom_lengths() {
+        assert_eq!(encode(&Term::atom("ok")).unwrap(), vec![131, 119, 2, b'o', b'k']);
+        let long = "a".repeat(300);
+        assert_eq!(&encode(&Term::atom(&long)).unwrap()[..4], &[131, 118, 1, 44]);
+        let error = encode(&Term::atom(&"a".repeat(65536))).unwrap_err();
+        assert_eq!(error.error_code, ErrorCode::LengthOverflow);
+    }
+
+    #[test]
+    fn reference_id_count() {
+        let reference = |words| Term::Reference(Reference{ node: "n".to_string(), creation: 0, id: vec![0; words] });
+        assert!(encode(&reference(3)).is_ok());
+        assert_eq!(encode(&reference(65536)).unwrap_err().error_code, ErrorCode::LengthOverflow);
+    }
+}
@@ -0,0 +1,160 @@
+use std::borrow::Cow;
+
+use crate::parser::ParseError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEncoding {
+    Raw,
+    Hex,
+    // PostgreSQL bytea output, `\x` followed by hex digits.
+    PgHex,
+    Base64,
+    Base64Url,
+    // Erlang shell binary syntax, `<<131,104,...>>`.
+    Erlang,
+    // Picks one of the above by looking at the input.
+    Auto
+}
+
+const VERSION: u8 = 131;
+
+// Turns text input into external term bytes, whitespace is ignored. Raw
+// input is passed through as it is.
+pub fn decode_input(encoding: InputEncoding, input: &[u8]) -> Result<Cow<'_, [u8]>, ParseError> {
+    let text = || -> Vec<u8> { input.iter().cloned().filter(|b| !b.is_ascii_whitespace()).collect() };
+    let bytes = match encoding {
+        InputEncoding::Raw => return Ok(Cow::Borrowed(input)),
+        InputEncoding::Hex => decode_hex(&text()),
+        InputEncoding::PgHex => match text().strip_prefix(b"\\x") {
+            Some(digits) => decode_hex(digits),
+            None => Err(ParseError::invalid_input_encoding())
+        },
+        InputEncoding::Base64 => decode_base64(&text(), base64::STANDARD),
+        InputEncoding::Base64Url => decode_base64(&text(), base64::URL_SAFE),
+        InputEncoding::Erlang => decode_erlang(&text()),
+        InputEncoding::Auto => return decode_input(detect(input, &text()), input)
+    };
+    bytes.map(Cow::Owned)
+}
+
+// Raw terms always start with the version byte, which no text encoding
+// produces as its first character. Text of hex digits alone is never taken
+// for base64, whose encoding of the version byte starts with a `g`.
+fn detect(input: &[u8], text: &[u8]) -> InputEncoding {
+    if input.first() == Some(&VERSION) {
+        InputEncoding::Raw
+    } else if text.starts_with(b"<<") {
+        InputEncoding::Erlang
+    } else if text.starts_with(b"\\x") {
+        InputEncoding::PgHex
+    } else if text.iter().all(u8::is_ascii_hexdigit) {
+        InputEncoding::Hex
+    } else if text.iter().any(|&b| b == b'-' || b == b'_') {
+        InputEncoding::Base64Url
+    } else {
+        InputEncoding::Base64
+    }
+}
+
+fn decode_hex(text: &[u8]) -> Result<Vec<u8>, ParseError> {
+    hex::decode(text).map_err(|_| ParseError::invalid_input_encoding())
+}
+
+fn decode_base64(text: &[u8], config: base64::Config) -> Result<Vec<u8>, ParseError> {
+    // Padding is often dropped, mostly from the url-safe flavour.
+    let mut text = text.to_vec();
+    while !text.len().is_multiple_of(4) {
+        text.push(b'=');
+    }
+    base64::decode_config(&text, config).map_err(|_| ParseError::invalid_input_encoding())
+}
+
+fn decode_erlang(text: &[u8]) -> Result<Vec<u8>, ParseError> {
+    let body = text.strip_prefix(b"<<")
+        .and_then(|rest| rest.strip_suffix(b">>"))
+        .ok_or_else(ParseError::invalid_input_encoding)?;
+    if body.is_empty() {
+        return Ok(vec![]);
+    }
+    body.split(|&b| b == b',')
+        .map(|byte| {
+            std::str::from_utf8(byte)
+                .ok()
+                .and_then(|byte| byte.parse::<u8>().ok())
+                .ok_or_else(ParseError::invalid_input_encoding)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{decode_input, detect, InputEncoding};
+    use crate::parser::ErrorCode;
+
+    const TERM: [u8; 6] = [131, 104, 1, 97, 255, 106];
+
+    fn decode(encoding: InputEncoding, text: &str) -> Vec<u8> {
+        decode_input(encoding, text.as_bytes()).unwrap().into_owned()
+    }
+
+    fn detected(text: &str) -> InputEncoding {
+        let stripped: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+        detect(text.as_bytes(), &stripped)
+    }
+
+    #[test]
+    fn raw_is_borrowed() {
+        assert!(matches!(decode_input(InputEncoding::Raw, &TERM), Ok(Cow::Borrowed(bytes)) if bytes == TERM));
+        assert!(matches!(decode_input(InputEncoding::Auto, &TERM), Ok(Cow::Borrowed(bytes)) if bytes == TERM));
+    }
+
+    #[test]
+    fn encodings() {
+        assert_eq!(decode(InputEncoding::Hex, "836801 61FF6a\n"), TERM);
+        assert_eq!(decode(InputEncoding::PgHex, "\\x83680161ff6a"), TERM);
+        assert_eq!(decode(InputEncoding::Base64, "g2gBYf9q"), TERM);
+        assert_eq!(decode(InputEncoding::Base64, "g2gB\nYf8="), [131, 104, 1, 97, 255]);
+        assert_eq!(decode(InputEncoding::Base64, "g2gBYf8"), [131, 104, 1, 97, 255]);
+        assert_eq!(decode(InputEncoding::Base64Url, "g2gBYf8"), [131, 104, 1, 97, 255]);
+        assert_eq!(decode(InputEncoding::Base64Url, "g2gBYf-_"), [131, 104, 1, 97, 255, 191]);
+        assert_eq!(decode(InputEncoding::Erlang, "<<131, 104,1,97,255,106>>"), TERM);
+        assert_eq!(decode(InputEncoding::Erlang, "<<>>"), b"");
+    }
+
+    #[test]
+    fn invalid() {
+        let invalid = |encoding, text: &str| decode_input(encoding, text.as_bytes()).unwrap_err().error_code;
+        assert_eq!(invalid(InputEncoding::Hex, "836"), ErrorCode::InvalidInputEncoding);
+        assert_eq!(invalid(InputEncoding::Hex, "83zz"), ErrorCode::InvalidInputEncoding);
+        assert_eq!(invalid(InputEncoding::PgHex, "8368"), ErrorCode::InvalidInputEncoding);
+        assert_eq!(invalid(InputEncoding::Base64, "g2g*"), ErrorCode::InvalidInputEncoding);
+        assert_eq!(invalid(InputEncoding::Base64, "g2g-"), ErrorCode::InvalidInputEncoding);
+        assert_eq!(invalid(InputEncoding::Erlang, "<<131,256>>"), ErrorCode::InvalidInputEncoding);
+        assert_eq!(invalid(InputEncoding::Erlang, "<<131,>>"), ErrorCode::InvalidInputEncoding);
+        assert_eq!(invalid(InputEncoding::Erlang, "131,104"), ErrorCode::InvalidInputEncoding);
+    }
+
+    #[test]
+    fn auto_detection() {
+        assert_eq!(detected("<<131,97,1>>"), InputEncoding::Erlang);
+        assert_eq!(detected(" \\x836101"), InputEncoding::PgHex);
+        assert_eq!(detected("836101"), InputEncoding::Hex);
+        assert_eq!(detected("83 61 0A\n"), InputEncoding::Hex);
+        assert_eq!(detected("g2EB"), InputEncoding::Base64);
+        assert_eq!(detected("g2gBYf+/"), InputEncoding::Base64);
+        assert_eq!(detected("g2gBYf-_"), InputEncoding::Base64Url);
+        assert_eq!(decode(InputEncoding::Auto, "\\x83680161ff6a"), TERM);
+        assert_eq!(decode(InputEncoding::Auto, "g2gBYf9q\n"), TERM);
+    }
+
+    #[test]
+    fn hex_digits_are_hex() {
+        // Valid base64 too, but a term's base64 starts with a `g`.
+        assert_eq!(decode(InputEncoding::Auto, "83610aBC"), [131, 97, 10, 188]);
+        assert_eq!(decode(InputEncoding::Base64, "83610aBC"), [243, 126, 181, 209, 160, 66]);
+        assert!(base64::encode(&[131, 0, 0]).starts_with('g'));
+        assert!(base64::encode_config(&[131, 255, 255], base64::URL_SAFE).starts_with('g'));
+    }
+}
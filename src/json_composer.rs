@@ -0,0 +1,394 @@
+use std::fmt::Write;
+use num_bigint::BigInt;
+
+use crate::json_writer::{JsonWrite, CompactWriter};
+use crate::parser::{ElemCompose, Pid, Port, Reference, Fun, NewFun, Export};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryFormat {
+    Base64,
+    Hex,
+    // Byte values as a JSON array.
+    Array,
+    // Valid UTF-8 as `{"utf8":"text"}`, anything else as base64.
+    Utf8
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BigIntFormat {
+    Number,
+    String
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JsonOptions {
+    pub binary: BinaryFormat,
+    pub bigint: BigIntFormat
+}
+
+impl Default for JsonOptions {
+    fn default() -> JsonOptions {
+        JsonOptions{
+            binary: BinaryFormat::Base64,
+            bigint: BigIntFormat::Number
+        }
+    }
+}
+
+enum Frame {
+    List,
+    // Tail of an improper list, `{"list":[..],"tail":..}`.
+    Tail,
+    // `pair` is set while a `{"key":..,"val":..}` object is open.
+    Map { pair: bool }
+}
+
+// Tagged JSON, `{"atom":"ok"}`, `{"list":[..]}` and so on, written through
+// `W`. `JsonComposer::new` writes it compact.
+pub struct JsonComposer<W = CompactWriter> {
+    writer: W,
+    stack: Vec<Frame>,
+    options: JsonOptions
+}
+
+impl JsonComposer {
+    pub fn new() -> JsonComposer {
+        JsonComposer::with_options(JsonOptions::default())
+    }
+
+    pub fn with_options(options: JsonOptions) -> JsonComposer {
+        JsonComposer::with_writer(options, CompactWriter::new())
+    }
+
+    pub fn result(&self) -> &str {
+        self.writer.result()
+    }
+}
+
+impl<W: JsonWrite> JsonComposer<W> {
+    pub fn with_writer(options: JsonOptions, writer: W) -> JsonComposer<W> {
+        JsonComposer{
+            writer,
+            stack: vec![],
+            options
+        }
+    }
+
+    pub fn writer(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_writer(self) -> W {
+        self.writer
+    }
+
+    // `{"name":` with the value to follow, then `close`.
+    pub fn open(&mut self, name: &str) {
+        self.writer.begin_object();
+        self.writer.key(name);
+    }
+
+    pub fn close(&mut self) {
+        self.writer.end_object();
+    }
+
+    // `{"name":v}` with `v` already JSON.
+    pub fn leaf<T: std::fmt::Display>(&mut self, name: &str, v: T) {
+        self.open(name);
+        self.writer.value(v);
+        self.close();
+    }
+
+    pub fn leaf_str(&mut self, name: &str, v: &str) {
+        self.open(name);
+        self.writer.string(v);
+        self.close();
+    }
+
+    // `{"name":[..]}`, the elements composed before `end_array`.
+    pub fn begin_array(&mut self, name: &str) {
+        self.open(name);
+        self.writer.begin_array();
+    }
+
+    pub fn end_array(&mut self) {
+        self.writer.end_array();
+        self.close();
+    }
+
+    // `{"kind":"name","fields":{..}}` for structs and records, each value
+    // composed after its `field`.
+    pub fn begin_fields(&mut self, kind: &str, name: &str) {
+        self.open(kind);
+        self.writer.string(name);
+        self.writer.key("fields");
+        self.writer.begin_object();
+    }
+
+    pub fn field(&mut self, key: &str) {
+        self.writer.key(key);
+    }
+
+    pub fn end_fields(&mut self) {
+        self.writer.end_object();
+        self.close();
+    }
+}
+
+impl Default for JsonComposer {
+    fn default() -> Self {
+        JsonComposer::new()
+    }
+}
+
+impl<W: JsonWrite> ElemCompose for JsonComposer<W> {
+    fn begin_list(&mut self, _len: u32) {
+        self.begin_array("list");
+        self.stack.push(Frame::List);
+    }
+    fn list_tail(&mut self) {
+        if let Some(top) = self.stack.last_mut() {
+            *top = Frame::Tail;
+        }
+        self.writer.end_array();
+        self.writer.key("tail");
+    }
+    fn end_list(&mut self) {
+        if let Some(Frame::List) = self.stack.pop() {
+            self.writer.end_array();
+        }
+        self.close();
+    }
+    fn begin_tuple(&mut self, _arity: u32) {
+        self.begin_array("tuple");
+    }
+    fn end_tuple(&mut self) {
+        self.end_array();
+    }
+    fn begin_map(&mut self, _len: u32) {
+        self.begin_array("map");
+        self.stack.push(Frame::Map{ pair: false });
+    }
+    fn map_key(&mut self) {
+        if let Some(Frame::Map{ pair }) = self.stack.last_mut() {
+            if *pair {
+                self.writer.end_object();
+            }
+            *pair = true;
+        }
+        self.writer.begin_object();
+        self.writer.key("key");
+    }
+    fn map_value(&mut self) {
+        self.writer.key("val");
+    }
+    fn end_map(&mut self) {
+        if let Some(Frame::Map{ pair: true }) = self.stack.pop() {
+            self.writer.end_object();
+        }
+        self.end_array();
+    }
+    fn atom(&mut self, name: &str) {
+        self.leaf_str("atom", name);
+    }
+    fn int(&mut self, v: i32) {
+        self.leaf("int", v);
+    }
+    fn bigint(&mut self, v: &BigInt) {
+        match self.options.bigint {
+            BigIntFormat::Number => self.leaf("bigint", v),
+            BigIntFormat::String => self.leaf_str("bigint", &v.to_string())
+        }
+    }
+    fn float(&mut self, v: f64) {
+        // Only terms built by hand can hold a non-finite float.
+        match v.is_finite() {
+            true => self.leaf("float", v),
+            false => self.leaf("float", "null")
+        }
+    }
+    fn string(&mut self, chars: &[u8]) {
+        let s: String = chars.iter().map(|&b| b as char).collect();
+        self.leaf_str("str", &s);
+    }
+    fn binary(&mut self, data: &[u8]) {
+        match self.options.binary {
+            BinaryFormat::Base64 => self.leaf_str("binary", &base64::encode(data)),
+            BinaryFormat::Hex => self.leaf_str("binary", &hex::encode(data)),
+            BinaryFormat::Array => {
+                self.open("binary");
+                push_array(&mut self.writer, data);
+                self.close();
+            },
+            BinaryFormat::Utf8 => match std::str::from_utf8(data) {
+                Ok(text) => self.leaf_str("utf8", text),
+                Err(_) => self.leaf_str("binary", &base64::encode(data))
+            }
+        }
+    }
+    fn bit_binary(&mut self, data: &[u8], bits: u8) {
+        self.open("bitstr");
+        let w = &mut self.writer;
+        w.begin_object();
+        w.key("bits");
+        w.value(bits);
+        w.key("data");
+        w.string(&base64::encode(data));
+        w.end_object();
+        self.close();
+    }
+    fn pid(&mut self, pid: &Pid) {
+        self.open("pid");
+        push_pid(&mut self.writer, pid);
+        self.close();
+    }
+    fn port(&mut self, port: &Port) {
+        self.open("port");
+        let w = &mut self.writer;
+        w.begin_object();
+        w.key("node");
+        w.string(&port.node);
+        w.key("id");
+        w.value(port.id);
+        w.key("creation");
+        w.value(port.creation);
+        w.end_object();
+        self.close();
+    }
+    fn reference(&mut self, reference: &Reference) {
+        self.open("ref");
+        let w = &mut self.writer;
+        w.begin_object();
+        w.key("node");
+        w.string(&reference.node);
+        w.key("id");
+        push_array(w, &reference.id);
+        w.key("creation");
+        w.value(reference.creation);
+        w.end_object();
+        self.close();
+    }
+    fn atom_cache_ref(&mut self, index: u8) {
+        self.leaf("acr", index);
+    }
+    fn fun(&mut self, fun: &Fun) {
+        self.open("fun");
+        let w = &mut self.writer;
+        w.begin_object();
+        w.key("pid");
+        push_pid(w, &fun.pid);
+        w.key("m");
+        w.string(&fun.module);
+        w.key("index");
+        w.value(fun.index);
+        w.key("uniq");
+        w.value(fun.uniq);
+        w.end_object();
+        self.close();
+    }
+    fn new_fun(&mut self, fun: &NewFun) {
+        self.open("newfun");
+        let w = &mut self.writer;
+        w.begin_object();
+        w.key("m");
+        w.string(&fun.module);
+        w.key("a");
+        w.value(fun.arity);
+        w.key("uniq");
+        w.string(&hex::encode(fun.uniq));
+        w.key("index");
+        w.value(fun.index);
+        w.key("old_uniq");
+        w.value(fun.old_uniq);
+        w.key("old_index");
+        w.value(fun.old_index);
+        w.key("pid");
+        push_pid(w, &fun.pid);
+        w.end_object();
+        self.close();
+    }
+    fn export(&mut self, export: &Export) {
+        self.open("expfun");
+        let w = &mut self.writer;
+        w.begin_object();
+        w.key("m");
+        w.string(&export.module);
+        w.key("f");
+        w.string(&export.function);
+        w.key("a");
+        w.value(export.arity);
+        w.end_object();
+        self.close();
+    }
+}
+
+fn push_pid<W: JsonWrite>(w: &mut W, pid: &Pid) {
+    w.begin_object();
+    w.key("node");
+    w.string(&pid.node);
+    w.key("id");
+    w.value(pid.id);
+    w.key("serial");
+    w.value(pid.serial);
+    w.key("creation");
+    w.value(pid.creation);
+    w.end_object();
+}
+
+// Numbers as a JSON array, `[1,2,3]`.
+fn push_array<W: JsonWrite, T: std::fmt::Display>(w: &mut W, items: &[T]) {
+    w.begin_array();
+    for item in items {
+        w.value(item);
+    }
+    w.end_array();
+}
+
+pub fn push_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); },
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BinaryFormat, JsonComposer, JsonOptions};
+    use crate::parser::{ElemCompose, Reference};
+
+    fn binary(format: BinaryFormat, data: &[u8]) -> String {
+        let mut c = JsonComposer::with_options(JsonOptions{ binary: format, ..JsonOptions::default() });
+        c.binary(data);
+        c.result().to_string()
+    }
+
+    #[test]
+    fn binary_formats() {
+        assert_eq!(binary(BinaryFormat::Base64, b"hi"), "{\"binary\":\"aGk=\"}");
+        assert_eq!(binary(BinaryFormat::Hex, b"hi"), "{\"binary\":\"6869\"}");
+        assert_eq!(binary(BinaryFormat::Array, &[0, 104, 255]), "{\"binary\":[0,104,255]}");
+        assert_eq!(binary(BinaryFormat::Array, &[]), "{\"binary\":[]}");
+        assert_eq!(binary(BinaryFormat::Utf8, b"hi"), "{\"utf8\":\"hi\"}");
+        assert_eq!(binary(BinaryFormat::Utf8, &[255]), "{\"binary\":\"/w==\"}");
+    }
+
+    #[test]
+    fn reference_ids() {
+        let mut c = JsonComposer::new();
+        c.reference(&Reference{ node: "n@h".to_string(), creation: 1, id: vec![1, 2, 3] });
+        assert_eq!(c.result(), "{\"ref\":{\"node\":\"n@h\",\"id\":[1,2,3],\"creation\":1}}");
+    }
+}
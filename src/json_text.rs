@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::ejson::{Dialect, EjsonOptions};
+use crate::parser::ParseError;
+use crate::term::Term;
+
+// Parses a sequence of JSON documents separated by whitespace, such as
+// NDJSON, into the terms the dialect's library would decode them to.
+// Strings become UTF-8 binaries, object keys included.
+pub fn parse_json_terms(text: &str, options: &EjsonOptions) -> Result<Vec<Term>, ParseError> {
+    let mut p = Parser{ text: text.as_bytes(), pos: 0, options };
+    let mut terms = vec![];
+    while p.skip_ws().is_some() {
+        terms.push(p.value()?);
+        if !p.text.get(p.pos).is_none_or(|b| b.is_ascii_whitespace()) {
+            return Err(p.error(p.pos));
+        }
+    }
+    Ok(terms)
+}
+
+// Parses exactly one JSON document.
+pub fn parse_json_term(text: &str, options: &EjsonOptions) -> Result<Term, ParseError> {
+    let mut terms = parse_json_terms(text, options)?;
+    match terms.len() {
+        1 => Ok(terms.remove(0)),
+        _ => Err(ParseError::invalid_json().located(0, None))
+    }
+}
+
+// Objects and arrays whose closing bracket hasn't been read yet.
+enum Open {
+    Array(Vec<Term>),
+    // Members so far, the current key, and for maps where each key is.
+    Object(Vec<(Term, Term)>, Term, HashMap<Vec<u8>, usize>)
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+    options: &'a EjsonOptions
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, pos: usize) -> ParseError {
+        ParseError::invalid_json().located(pos as u64, None)
+    }
+
+    // Skips whitespace, returning the next byte.
+    fn skip_ws(&mut self) -> Option<u8> {
+        while let Some(&b) = self.text.get(self.pos) {
+            match b {
+                b' ' | b'\t' | b'\n' | b'\r' => self.pos += 1,
+                _ => return Some(b)
+            }
+        }
+        None
+    }
+
+    fn expect(&mut self, b: u8) -> Result<(), ParseError> {
+        if self.skip_ws() != Some(b) {
+            return Err(self.error(self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    // An object key and its colon.
+    fn key(&mut self) -> Result<Term, ParseError> {
+        if self.skip_ws() != Some(b'"') {
+            return Err(self.error(self.pos));
+        }
+        let key = Term::Binary(self.string()?.into_bytes());
+        self.expect(b':')?;
+        Ok(key)
+    }
+
+    // Builds nested values with an explicit stack of open containers.
+    fn value(&mut self) -> Result<Term, ParseError> {
+        let mut stack: Vec<Open> = vec![];
+        loop {
+            let start = self.pos;
+            let mut value = match self.skip_ws() {
+                Some(b'{') => {
+                    self.pos += 1;
+                    if self.skip_ws() == Some(b'}') {
+                        self.pos += 1;
+                        self.object(vec![])
+                    } else {
+                        let key = self.key()?;
+                        stack.push(Open::Object(vec![], key, HashMap::new()));
+                        continue;
+                    }
+                },
+                Some(b'[') => {
+                    self.pos += 1;
+                    if self.skip_ws() == Some(b']') {
+                        self.pos += 1;
+                        Term::nil()
+                    } else {
+                        stack.push(Open::Array(vec![]));
+                        continue;
+                    }
+                },
+                Some(b'"') => Term::Binary(self.string()?.into_bytes()),
+                Some(b'-') | Some(b'0'..=b'9') => self.number()?,
+                Some(_) => self.literal()?,
+                None => return Err(self.error(start))
+            };
+            // Hand the value to the enclosing containers, closing each one
+            // whose last member it was.
+            loop {
+                match stack.last_mut() {
+                    None => return Ok(value),
+                    Some(Open::Array(items)) => {
+                        items.push(value);
+                        match self.skip_ws() {
+                            Some(b',') => {
+                                self.pos += 1;
+                                break;
+                            },
+                            Some(b']') => {
+                                self.pos += 1;
+                                value = match stack.pop() {
+                                    Some(Open::Array(items)) => Term::List(items, None),
+                                    _ => return Err(self.error(self.pos - 1))
+                                };
+                            },
+                            _ => return Err(self.error(self.pos))
+                        }
+                    },
+                    Some(Open::Object(members, key, index)) => {
+                        let key = std::mem::replace(key, Term::nil());
+                        member(members, index, key, value, self.options.dialect);
+                        match self.skip_ws() {
+                            Some(b',') => {
+                                self.pos += 1;
+                                let next = self.key()?;
+                                if let Some(Open::Object(_, key, _)) = stack.last_mut() {
+                                    *key = next;
+                                }
+                                break;
+                            },
+                            Some(b'}') => {
+                                self.pos += 1;
+                                value = match stack.pop() {
+                                    Some(Open::Object(members, _, _)) => self.object(members),
+                                    _ => return Err(self.error(self.pos - 1))
+                                };
+                            },
+                            _ => return Err(self.error(self.pos))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn object(&self, members: Vec<(Term, Term)>) -> Term {
+        if self.options.dialect == Dialect::Maps {
+            return Term::Map(members);
+        }
+        let empty = members.is_empty();
+        let pairs = members.into_iter().map(|(k, v)| Term::Tuple(vec![k, v])).collect();
+        match self.options.dialect {
+            Dialect::Jsx if empty => Term::List(vec![Term::Tuple(vec![])], None),
+            Dialect::Jsx => Term::List(pairs, None),
+            _ => Term::Tuple(vec![Term::List(pairs, None)])
+        }
+    }
+
+    fn literal(&mut self) -> Result<Term, ParseError> {
+        let rest = &self.text[self.pos..];
+        let (word, term) = if rest.starts_with(b"true") {
+            ("true", Term::atom("true"))
+        } else if rest.starts_with(b"false") {
+            ("false", Term::atom("false"))
+        } else if rest.starts_with(b"null") {
+            ("null", Term::atom(&self.options.null))
+        } else {
+            return Err(self.error(self.pos));
+        };
+        self.pos += word.len();
+        Ok(term)
+    }
+
+    fn number(&mut self) -> Result<Term, ParseError> {
+        let start = self.pos;
+        let mut float = false;
+        if self.text.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        match self.text.get(self.pos) {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error(self.pos))
+        }
+        if self.text.get(self.pos) == Some(&b'.') {
+            float = true;
+            self.pos += 1;
+            self.required_digits()?;
+        }
+        if let Some(b'e') | Some(b'E') = self.text.get(self.pos) {
+            float = true;
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.text.get(self.pos) {
+                self.pos += 1;
+            }
+            self.required_digits()?;
+        }
+        // Only ASCII was consumed.
+        let text = std::str::from_utf8(&self.text[start..self.pos]).unwrap_or_default();
+        if float {
+            match text.parse::<f64>() {
+                Ok(v) if v.is_finite() => Ok(Term::Float(v)),
+                _ => Err(self.error(start))
+            }
+        } else {
+            match text.parse::<BigInt>() {
+                Ok(v) => Ok(match v.to_i32() {
+                    Some(v) => Term::Int(v),
+                    None => Term::BigInt(v)
+                }),
+                Err(_) => Err(self.error(start))
+            }
+        }
+    }
+
+    fn digits(&mut self) {
+        while self.text.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+    }
+
+    fn required_digits(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        self.digits();
+        if self.pos == start {
+            return Err(self.error(self.pos));
+        }
+        Ok(())
+    }
+
+    // A string starting at the opening quote.
+    fn string(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut res = vec![];
+        loop {
+            let b = match self.text.get(self.pos) {
+                Some(&b) => b,
+                None => return Err(self.error(start))
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.text.get(self.pos).cloned();
+                    self.pos += 1;
+                    let ch = match escape {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error(self.pos - 2))
+                    };
+                    let mut buf = [0; 4];
+                    res.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                },
+                0..=0x1f => return Err(self.error(self.pos - 1)),
+                _ => res.push(b)
+            }
+        }
+        // The input is a `str` and escapes add whole characters.
+        String::from_utf8(res).map_err(|_| self.error(start))
+    }
+
+    // The code point of `\uXXXX` after the `u`, joining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, ParseError> {
+        let start = self.pos - 2;
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.text[self.pos..].starts_with(b"\\u") {
+                return Err(self.error(start));
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error(start));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        std::char::from_u32(code).ok_or_else(|| self.error(start))
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self.text.get(self.pos..self.pos + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error(self.pos))?;
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap_or_default())
+    }
+}
+
+// Adds an object member. Maps keep the last value of a repeated key, as
+// Jason and thoas do, the proplist dialects keep every member.
+fn member(members: &mut Vec<(Term, Term)>, index: &mut HashMap<Vec<u8>, usize>, key: Term, value: Term, dialect: Dialect) {
+    if dialect == Dialect::Maps {
+        if let Term::Binary(ref name) = key {
+            if let Some(&i) = index.get(name) {
+                members[i].1 = value;
+                return;
+            }
+            index.insert(name.clone(), members.len());
+        }
+    }
+    members.push((key, value));
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+
+    use super::{parse_json_term, parse_json_terms};
+    use crate::ejson::{ejson_to_json, Dialect, EjsonOptions};
+    use crate::etf_composer::encode;
+    use crate::parse_erlang_term;
+    use crate::parser::{ErrorCode, Limits};
+    use crate::term::Term;
+
+    fn parse(text: &str, dialect: Dialect) -> Term {
+        parse_json_term(text, &EjsonOptions::new(dialect)).unwrap()
+    }
+
+    fn erlang(text: &str) -> Term {
+        parse_erlang_term(text, &Limits::default()).unwrap()
+    }
+
+    // Offset of the error in `text`.
+    fn error_at(text: &str) -> u64 {
+        let error = parse_json_terms(text, &EjsonOptions::default()).unwrap_err();
+        assert_eq!(error.error_code, ErrorCode::InvalidJson);
+        error.offset.unwrap()
+    }
+
+    #[test]
+    fn dialects() {
+        let text = r#"{"a": [1, {}], "b": null}"#;
+        assert_eq!(parse(text, Dialect::Jiffy), erlang("{[{<<\"a\">>, [1, {[]}]}, {<<\"b\">>, null}]}"));
+        assert_eq!(parse(text, Dialect::Jsx), erlang("[{<<\"a\">>, [1, [{}]]}, {<<\"b\">>, null}]"));
+        assert_eq!(parse(text, Dialect::Maps), erlang("#{<<\"a\">> => [1, #{}], <<\"b\">> => nil}"));
+        let options = EjsonOptions{ null: "undefined".to_string(), ..EjsonOptions::default() };
+        assert_eq!(parse_json_term("[null, true, false]", &options).unwrap(), erlang("[undefined, true, false]"));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let text = r#"{"a": 1, "b": 2, "a": 3}"#;
+        assert_eq!(parse(text, Dialect::Maps), erlang("#{<<\"a\">> => 3, <<\"b\">> => 2}"));
+        assert_eq!(parse(text, Dialect::Jiffy), erlang("{[{<<\"a\">>, 1}, {<<\"b\">>, 2}, {<<\"a\">>, 3}]}"));
+        assert_eq!(parse(text, Dialect::Jsx), erlang("[{<<\"a\">>, 1}, {<<\"b\">>, 2}, {<<\"a\">>, 3}]"));
+        // Keys are compared after unescaping.
+        assert_eq!(parse(r#"{"a": 1, "\u0061": 2}"#, Dialect::Maps), erlang("#{<<\"a\">> => 2}"));
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse("[0, -7, 2147483647, 2.5, -1e2, 1E-2]", Dialect::Jiffy),
+            erlang("[0, -7, 2147483647, 2.5, -100.0, 0.01]"));
+        assert_eq!(parse("2147483648", Dialect::Jiffy), Term::BigInt(BigInt::from(2147483648u64)));
+        assert_eq!(parse("-123456789012345678901234567890", Dialect::Jiffy),
+            Term::BigInt("-123456789012345678901234567890".parse().unwrap()));
+        assert_eq!(error_at("01"), 1);
+        assert_eq!(error_at("[1.]"), 3);
+        assert_eq!(error_at("-"), 1);
+        assert_eq!(error_at("1e400"), 0);
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(parse(r#""a\"\\\/\b\f\n\r\t""#, Dialect::Jiffy), Term::Binary(b"a\"\\/\x08\x0c\n\r\t".to_vec()));
+        assert_eq!(parse(r#""\u00e9\ud83d\ude00""#, Dialect::Jiffy), Term::Binary("\u{e9}\u{1f600}".as_bytes().to_vec()));
+        assert_eq!(parse("\"\u{3c0}\"", Dialect::Jiffy), Term::Binary("\u{3c0}".as_bytes().to_vec()));
+        assert_eq!(error_at(r#""\ud83d""#), 1);
+        assert_eq!(error_at(r#""\ud83d\u0041""#), 1);
+        assert_eq!(error_at(r#""\x""#), 1);
+        assert_eq!(error_at("\"a\nb\""), 2);
+        assert_eq!(error_at("\"abc"), 0);
+    }
+
+    #[test]
+    fn documents() {
+        let terms = parse_json_terms("1 [2]\n{\"a\":3}\n", &EjsonOptions::default()).unwrap();
+        assert_eq!(terms, vec![erlang("1"), erlang("[2]"), erlang("{[{<<\"a\">>, 3}]}")]);
+        assert!(parse_json_terms(" \n", &EjsonOptions::default()).unwrap().is_empty());
+        assert_eq!(error_at("1 2x"), 3);
+        assert_eq!(error_at("[1 2]"), 3);
+        assert_eq!(error_at("{\"a\" 1}"), 5);
+        assert_eq!(error_at("{\"a\": 1,}"), 8);
+        assert_eq!(error_at("[1, 2"), 5);
+        assert_eq!(error_at("nul"), 0);
+        let error = parse_json_term("1 2", &EjsonOptions::default()).unwrap_err();
+        assert_eq!(error.error_code, ErrorCode::InvalidJson);
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let text = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert!(parse_json_term(&text, &EjsonOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn etf_round_trip() {
+        let terms = [
+            ("{[{<<\"a\">>, [1, 2.5, <<\"x\">>, true, null]}, {<<\"b\">>, {[]}}]}", Dialect::Jiffy),
+            ("[{<<\"a\">>, [[{}], 4294967296]}, {<<\"b\">>, false}]", Dialect::Jsx),
+            ("#{<<\"a\">> => #{<<\"\\n\">> => nil}, <<\"b\">> => [-1]}", Dialect::Maps)
+        ];
+        for &(text, dialect) in &terms {
+            let options = EjsonOptions::new(dialect);
+            let term = erlang(text);
+            let json = ejson_to_json(&term, &options).unwrap();
+            let back = parse_json_term(&json, &options).unwrap();
+            assert_eq!(encode(&back).unwrap(), encode(&term).unwrap(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt::{Display, Write};
+
+use crate::json_composer::push_json_str;
+
+// Structured JSON output. Writers put in the commas and colons, so callers
+// only say where containers, keys and values go.
+pub trait JsonWrite {
+    fn begin_object(&mut self);
+    fn end_object(&mut self);
+    fn begin_array(&mut self);
+    fn end_array(&mut self);
+    // An object key, its value follows.
+    fn key(&mut self, key: &str);
+    // A value already in JSON syntax, such as a number or `null`.
+    fn value<T: Display>(&mut self, v: T);
+    fn string(&mut self, s: &str);
+}
+
+impl<W: JsonWrite + ?Sized> JsonWrite for &mut W {
+    fn begin_object(&mut self) {
+        (**self).begin_object()
+    }
+    fn end_object(&mut self) {
+        (**self).end_object()
+    }
+    fn begin_array(&mut self) {
+        (**self).begin_array()
+    }
+    fn end_array(&mut self) {
+        (**self).end_array()
+    }
+    fn key(&mut self, key: &str) {
+        (**self).key(key)
+    }
+    fn value<T: Display>(&mut self, v: T) {
+        (**self).value(v)
+    }
+    fn string(&mut self, s: &str) {
+        (**self).string(s)
+    }
+}
+
+// Where commas go: before every element of a container but the first, and
+// never between a key and its value.
+#[derive(Default)]
+pub(crate) struct Commas {
+    first: Vec<bool>,
+    after_key: bool
+}
+
+impl Commas {
+    // Whether the element about to be written needs a comma before it.
+    pub(crate) fn element(&mut self) -> bool {
+        if self.after_key {
+            self.after_key = false;
+            return false;
+        }
+        match self.first.last_mut() {
+            Some(first) => !std::mem::replace(first, false),
+            None => false
+        }
+    }
+
+    pub(crate) fn key(&mut self) -> bool {
+        let comma = self.element();
+        self.after_key = true;
+        comma
+    }
+
+    pub(crate) fn open(&mut self) {
+        self.first.push(true);
+    }
+
+    pub(crate) fn close(&mut self) {
+        self.first.pop();
+    }
+}
+
+// JSON on a single line with no spaces.
+#[derive(Default)]
+pub struct CompactWriter {
+    out: String,
+    commas: Commas
+}
+
+impl CompactWriter {
+    pub fn new() -> CompactWriter {
+        CompactWriter::default()
+    }
+
+    pub fn result(&self) -> &str {
+        &self.out
+    }
+
+    pub fn into_result(self) -> String {
+        self.out
+    }
+
+    fn element(&mut self) {
+        if self.commas.element() {
+            self.out.push(',');
+        }
+    }
+}
+
+impl JsonWrite for CompactWriter {
+    fn begin_object(&mut self) {
+        self.element();
+        self.commas.open();
+        self.out.push('{');
+    }
+    fn end_object(&mut self) {
+        self.commas.close();
+        self.out.push('}');
+    }
+    fn begin_array(&mut self) {
+        self.element();
+        self.commas.open();
+        self.out.push('[');
+    }
+    fn end_array(&mut self) {
+        self.commas.close();
+        self.out.push(']');
+    }
+    fn key(&mut self, key: &str) {
+        if self.commas.key() {
+            self.out.push(',');
+        }
+        push_json_str(&mut self.out, key);
+        self.out.push(':');
+    }
+    fn value<T: Display>(&mut self, v: T) {
+        self.element();
+        let _ = write!(self.out, "{}", v);
+    }
+    fn string(&mut self, s: &str) {
+        self.element();
+        push_json_str(&mut self.out, s);
+    }
+}
@@ -0,0 +1,61 @@
+pub mod parser;
+pub mod json_composer;
+pub mod json_writer;
+pub mod pretty_composer;
+pub mod semantic;
+pub mod records;
+pub mod times;
+pub mod erlang_composer;
+pub mod elixir_composer;
+pub mod etf_composer;
+pub mod ejson;
+pub mod bert;
+pub mod calendar;
+pub mod erlang_text;
+pub mod json_text;
+pub mod term;
+pub mod split_composer;
+pub mod buf_read_streamer;
+pub mod packet;
+pub mod port;
+pub mod input_encoding;
+pub mod convert;
+#[cfg(feature = "tokio")]
+pub mod async_stream;
+
+pub use parser::{
+    parse, parse_next, Decoder, PushDecoder, ElemCompose, ReadStream, Limits,
+    ParseResult, ParseError, ErrorCode, PathSegment,
+    Pid, Port, Reference, Fun, NewFun, Export
+};
+pub use json_composer::{JsonComposer, JsonOptions, BinaryFormat, BigIntFormat};
+pub use json_writer::{JsonWrite, CompactWriter};
+pub use pretty_composer::{PrettyComposer, PrettyOptions, PrettyWriter};
+pub use semantic::{SemanticComposer, SemanticRenderer, SemanticOptions, semantic_json};
+pub use records::{RecordTable, RecordDef, parse_record_defs};
+pub use times::{TimeOptions, TimeRule, TimeKind};
+pub use erlang_composer::ErlangComposer;
+pub use elixir_composer::ElixirComposer;
+pub use etf_composer::{EtfComposer, encode};
+pub use erlang_text::{parse_erlang_term, parse_erlang_terms};
+pub use json_text::{parse_json_term, parse_json_terms};
+pub use bert::{BertComposer, BertRenderer, bert_to_json, bert_rpc_to_json};
+pub use ejson::{EjsonComposer, EjsonRenderer, EjsonOptions, Dialect, ejson_to_json};
+pub use term::{Term, TermBuilder, Render, RenderComposer};
+pub use split_composer::SplitComposer;
+pub use input_encoding::{InputEncoding, decode_input};
+pub use buf_read_streamer::BufReadStreamer;
+pub use packet::{Packet, read_frame, write_frame, parse_frame};
+pub use convert::{Format, InputFormat, ConvertOptions, Document, new_composer, convert, convert_terms};
+
+// Decodes a complete `term_to_binary` output into `c`. Trailing bytes after
+// the term are an error.
+pub fn decode<C: ElemCompose>(bytes: &[u8], c: &mut C, limits: &Limits) -> ParseResult {
+    parse_frame(bytes, c, limits)
+}
+
+pub fn to_json(bytes: &[u8]) -> Result<String, ParseError> {
+    let mut composer = JsonComposer::new();
+    decode(bytes, &mut composer, &Limits::default())?;
+    Ok(composer.result().to_string())
+}
@@ -1,19 +1,134 @@
-mod parser;
-mod string_composer;
-mod buf_read_streamer;
+mod batch;
+mod cli;
 
-use parser::parse;
+use eterm2json::{port, ParseResult, ParseError, convert};
+use eterm2json::{RecordTable, parse_record_defs};
 
-use buf_read_streamer::BufReadStreamer;
+use cli::{Options, ArgsError, Color, RecordSource};
 
-use std::io::{BufReader, stdin};
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Write, stdin, stdout};
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+
+const EXIT_USAGE: i32 = 64;
+const EXIT_BATCH_FAILED: i32 = 65;
 
 fn main() {
-    let mut bf = BufReader::new(stdin());
-    let mut composer = String::new();
-    let mut buf_streamer = BufReadStreamer::new(&mut bf);
-    match parse(&mut buf_streamer, &mut composer) {
-        Ok(_) => println!("{}", composer),
-        Err(error) => println!("Error: {}", error),
-    }
-}
\ No newline at end of file
+    let mut options = match cli::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(ArgsError::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        },
+        Err(ArgsError::Usage(msg)) => {
+            eprintln!("eterm2json: {}", msg);
+            eprintln!("Try 'eterm2json --help' for more information.");
+            process::exit(EXIT_USAGE);
+        }
+    };
+    match load_records(&options.records) {
+        Ok(records) => options.convert.semantic.records = Arc::new(records),
+        Err((name, error)) => fail(&name, &error)
+    }
+    let color = use_color(&options);
+    if let Some(ref mut pretty) = options.convert.pretty {
+        pretty.color = color;
+    }
+    if options.port {
+        if let Err(error) = port::run(&mut BufReader::new(stdin()), &mut stdout().lock(), &options.convert.limits) {
+            fail("port", &error);
+        }
+        return;
+    }
+    if let Some(ref out_dir) = options.out_dir {
+        run_batch(&options, out_dir);
+        return;
+    }
+    let mut out: Box<dyn Write> = match options.output {
+        Some(ref path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(error) => fail(path, &error.into())
+        },
+        None => Box::new(BufWriter::new(stdout()))
+    };
+    let mut result = Ok(());
+    for input in &options.inputs {
+        result = convert_input(input, &options, &mut out).map_err(|e| (input.as_str(), e));
+        if result.is_err() {
+            break;
+        }
+    }
+    if let Err(error) = out.flush() {
+        result = result.and(Err(("output", error.into())));
+    }
+    if let Err((name, error)) = result {
+        fail(name, &error);
+    }
+}
+
+fn fail(name: &str, error: &ParseError) -> ! {
+    eprintln!("eterm2json: {}: {}", name, error);
+    process::exit(error.error_code.code());
+}
+
+fn load_records(sources: &[RecordSource]) -> Result<RecordTable, (String, ParseError)> {
+    let mut records = RecordTable::new();
+    for source in sources {
+        let (name, defs) = match *source {
+            RecordSource::Hrl(ref path) => {
+                let text = fs::read_to_string(path).map_err(|e| (path.clone(), e.into()))?;
+                (path, parse_record_defs(&text))
+            },
+            RecordSource::Decl(ref decl) => (decl, parse_record_defs(decl))
+        };
+        let defs = defs.map_err(|e| (name.clone(), e))?;
+        if defs.is_empty() {
+            return Err((name.clone(), ParseError::invalid_term_syntax()));
+        }
+        for def in defs {
+            records.add(def);
+        }
+    }
+    Ok(records)
+}
+
+fn run_batch(options: &Options, out_dir: &str) {
+    let summary = match batch::run(options, Path::new(out_dir)) {
+        Ok(summary) => summary,
+        Err(error) => fail("batch", &error.into())
+    };
+    let written = match options.summary {
+        Some(ref path) => fs::write(path, summary.to_json() + "\n"),
+        None => writeln!(stdout(), "{}", summary.to_json())
+    };
+    if let Err(error) = written {
+        fail("summary", &error.into());
+    }
+    eprintln!("eterm2json: converted {}, failed {}", summary.converted, summary.failures.len());
+    if !summary.failures.is_empty() {
+        process::exit(EXIT_BATCH_FAILED);
+    }
+}
+
+fn convert_input(input: &str, options: &Options, out: &mut dyn Write) -> ParseResult {
+    let mut reader: Box<dyn BufRead> = if input == "-" {
+        Box::new(BufReader::new(stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(input)?))
+    };
+    convert(&mut reader, &options.convert, out)
+}
+
+fn use_color(options: &Options) -> bool {
+    match options.color {
+        Color::Always => true,
+        Color::Never => false,
+        Color::Auto => {
+            options.output.is_none() && options.out_dir.is_none()
+                && env::var_os("NO_COLOR").is_none() && stdout().is_terminal()
+        }
+    }
+}
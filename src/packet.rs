@@ -0,0 +1,167 @@
+use std::io::{self, Read, Write, ErrorKind};
+
+use crate::parser::{parse, Budget, ElemCompose, ReadStream, Limits, ParseResult, ParseError};
+use crate::buf_read_streamer::BufReadStreamer;
+
+// Length-prefixed framing as used by Erlang `{packet, 1|2|4}`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Packet {
+    One,
+    Two,
+    Four
+}
+
+impl Packet {
+    pub fn from_size(size: u8) -> Option<Packet> {
+        match size {
+            1 => Some(Packet::One),
+            2 => Some(Packet::Two),
+            4 => Some(Packet::Four),
+            _ => None
+        }
+    }
+
+    pub fn header_len(self) -> usize {
+        match self {
+            Packet::One => 1,
+            Packet::Two => 2,
+            Packet::Four => 4
+        }
+    }
+
+    fn max_len(self) -> usize {
+        match self {
+            Packet::One => u8::MAX as usize,
+            Packet::Two => u16::MAX as usize,
+            Packet::Four => u32::MAX as usize
+        }
+    }
+}
+
+// Returns Ok(None) when the stream ends cleanly before a frame header. The
+// body is read as it arrives, after `check_len` has accepted its length.
+pub fn read_frame<R: Read + ?Sized>(r: &mut R, packet: Packet, limits: &Limits) -> Result<Option<Vec<u8>>, ParseError> {
+    let len = match read_len(r, packet)? {
+        Some(len) => len,
+        None => return Ok(None)
+    };
+    check_len(len, limits)?;
+    read_body(r, len).map(Some)
+}
+
+// The length in the next frame header, Ok(None) at a clean end of stream.
+pub(crate) fn read_len<R: Read + ?Sized>(r: &mut R, packet: Packet) -> Result<Option<usize>, ParseError> {
+    let mut header: [u8; 4] = [0; 4];
+    let header = &mut header[..packet.header_len()];
+    let mut filled = 0;
+    while filled < header.len() {
+        match r.read(&mut header[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "truncated frame header").into()),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {},
+            Err(e) => return Err(e.into())
+        }
+    }
+    Ok(Some(frame_len(header)))
+}
+
+pub(crate) fn read_body<R: Read + ?Sized>(r: &mut R, len: usize) -> Result<Vec<u8>, ParseError> {
+    let mut frame = vec![];
+    r.take(len as u64).read_to_end(&mut frame)?;
+    if frame.len() < len {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "truncated frame").into());
+    }
+    Ok(frame)
+}
+
+// A frame holds one term, so one longer than that term could allocate under
+// `limits` is refused before it is read.
+pub(crate) fn check_len(len: usize, limits: &Limits) -> ParseResult {
+    Budget::new(limits).alloc(len)
+}
+
+// Length from a frame header.
+pub(crate) fn frame_len(header: &[u8]) -> usize {
+    header.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize)
+}
+
+pub fn write_frame<W: Write + ?Sized>(w: &mut W, packet: Packet, data: &[u8]) -> io::Result<()> {
+    if data.len() > packet.max_len() {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "frame too long for packet header"));
+    }
+    let header = (data.len() as u32).to_be_bytes();
+    w.write_all(&header[4 - packet.header_len()..])?;
+    w.write_all(data)
+}
+
+// Decodes a frame that must hold exactly one term.
+pub fn parse_frame<C: ElemCompose>(frame: &[u8], c: &mut C, limits: &Limits) -> ParseResult {
+    let mut bytes = frame;
+    let mut buf_streamer = BufReadStreamer::new(&mut bytes);
+    parse(&mut buf_streamer, c, limits)?;
+    if buf_streamer.at_eof()? {
+        Ok(())
+    } else {
+        Err(ParseError::trailing_frame_data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Packet, read_frame, write_frame, parse_frame};
+    use crate::parser::{ErrorCode, Limits};
+    use crate::json_composer::JsonComposer;
+
+    #[test]
+    fn frames_round_trip() {
+        for &packet in &[Packet::One, Packet::Two, Packet::Four] {
+            let mut out = vec![];
+            write_frame(&mut out, packet, &[131, 97, 1]).unwrap();
+            write_frame(&mut out, packet, &[]).unwrap();
+            assert_eq!(out.len(), 2 * packet.header_len() + 3);
+            let mut r = &out[..];
+            assert_eq!(read_frame(&mut r, packet, &Limits::default()).unwrap(), Some(vec![131, 97, 1]));
+            assert_eq!(read_frame(&mut r, packet, &Limits::default()).unwrap(), Some(vec![]));
+            assert_eq!(read_frame(&mut r, packet, &Limits::default()).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn truncated_frames() {
+        let limits = Limits::default();
+        let err = read_frame(&mut &[0, 0][..], Packet::Four, &limits).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::UnexpectedEof);
+        let err = read_frame(&mut &[0, 0, 0, 3, 131, 97][..], Packet::Four, &limits).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::UnexpectedEof);
+        // A huge declared length fails at the end of input, not allocating.
+        let err = read_frame(&mut &[255, 255, 255, 255, 131][..], Packet::Four, &Limits::unlimited()).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::UnexpectedEof);
+    }
+
+    #[test]
+    fn oversized_frame_is_refused_before_reading() {
+        let limits = Limits{ max_alloc: 2, ..Limits::default() };
+        let err = read_frame(&mut &[0, 0, 0, 3, 131, 97, 1][..], Packet::Four, &limits).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::AllocLimitExceeded);
+        let limits = Limits{ max_total: 2, ..Limits::unlimited() };
+        let err = read_frame(&mut &[255, 255, 255, 255][..], Packet::Four, &limits).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::TotalSizeLimitExceeded);
+    }
+
+    #[test]
+    fn frame_must_hold_exactly_one_term() {
+        let mut c = JsonComposer::new();
+        parse_frame(&[131, 97, 1], &mut c, &Limits::default()).unwrap();
+        assert_eq!(c.result(), "{\"int\":1}");
+        let err = parse_frame(&[131, 97, 1, 0], &mut JsonComposer::new(), &Limits::default()).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::TrailingFrameData);
+        let err = parse_frame(&[131, 97], &mut JsonComposer::new(), &Limits::default()).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::UnexpectedEof);
+    }
+
+    #[test]
+    fn frame_too_long_for_header() {
+        assert!(write_frame(&mut vec![], Packet::One, &[0; 256]).is_err());
+    }
+}
@@ -1,6 +1,104 @@
+use num_bigint::BigInt;
+
+use super::terms::{Pid, Port, Reference, Fun, NewFun, Export};
+
+// Callbacks are issued in document order. Containers are announced with their
+// element count, each map pair is introduced by `map_key` and `map_value`.
+// The tail of an improper list follows `list_tail`, after the elements.
 pub trait ElemCompose {
-    fn open(&mut self, name: &str);
-    fn push_str<T: AsRef<str>>(&mut self, elem: T);
-    fn push_char(&mut self, elem: char);
-    fn close(&mut self);
+    fn begin_list(&mut self, len: u32);
+    fn list_tail(&mut self);
+    fn end_list(&mut self);
+    fn begin_tuple(&mut self, arity: u32);
+    fn end_tuple(&mut self);
+    fn begin_map(&mut self, len: u32);
+    fn map_key(&mut self);
+    fn map_value(&mut self);
+    fn end_map(&mut self);
+    fn atom(&mut self, name: &str);
+    fn int(&mut self, v: i32);
+    fn bigint(&mut self, v: &BigInt);
+    fn float(&mut self, v: f64);
+    fn string(&mut self, chars: &[u8]);
+    fn binary(&mut self, data: &[u8]);
+    fn bit_binary(&mut self, data: &[u8], bits: u8);
+    fn pid(&mut self, pid: &Pid);
+    fn port(&mut self, port: &Port);
+    fn reference(&mut self, reference: &Reference);
+    fn atom_cache_ref(&mut self, index: u8);
+    fn fun(&mut self, fun: &Fun);
+    fn new_fun(&mut self, fun: &NewFun);
+    fn export(&mut self, export: &Export);
+}
+
+impl<T: ElemCompose + ?Sized> ElemCompose for Box<T> {
+    fn begin_list(&mut self, len: u32) {
+        (**self).begin_list(len)
+    }
+    fn list_tail(&mut self) {
+        (**self).list_tail()
+    }
+    fn end_list(&mut self) {
+        (**self).end_list()
+    }
+    fn begin_tuple(&mut self, arity: u32) {
+        (**self).begin_tuple(arity)
+    }
+    fn end_tuple(&mut self) {
+        (**self).end_tuple()
+    }
+    fn begin_map(&mut self, len: u32) {
+        (**self).begin_map(len)
+    }
+    fn map_key(&mut self) {
+        (**self).map_key()
+    }
+    fn map_value(&mut self) {
+        (**self).map_value()
+    }
+    fn end_map(&mut self) {
+        (**self).end_map()
+    }
+    fn atom(&mut self, name: &str) {
+        (**self).atom(name)
+    }
+    fn int(&mut self, v: i32) {
+        (**self).int(v)
+    }
+    fn bigint(&mut self, v: &BigInt) {
+        (**self).bigint(v)
+    }
+    fn float(&mut self, v: f64) {
+        (**self).float(v)
+    }
+    fn string(&mut self, chars: &[u8]) {
+        (**self).string(chars)
+    }
+    fn binary(&mut self, data: &[u8]) {
+        (**self).binary(data)
+    }
+    fn bit_binary(&mut self, data: &[u8], bits: u8) {
+        (**self).bit_binary(data, bits)
+    }
+    fn pid(&mut self, pid: &Pid) {
+        (**self).pid(pid)
+    }
+    fn port(&mut self, port: &Port) {
+        (**self).port(port)
+    }
+    fn reference(&mut self, reference: &Reference) {
+        (**self).reference(reference)
+    }
+    fn atom_cache_ref(&mut self, index: u8) {
+        (**self).atom_cache_ref(index)
+    }
+    fn fun(&mut self, fun: &Fun) {
+        (**self).fun(fun)
+    }
+    fn new_fun(&mut self, fun: &NewFun) {
+        (**self).new_fun(fun)
+    }
+    fn export(&mut self, export: &Export) {
+        (**self).export(export)
+    }
 }
@@ -0,0 +1,160 @@
+use super::parse_result::ParseError;
+
+// Resource limits applied while decoding untrusted input.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    // Deepest allowed nesting of lists, tuples and maps.
+    pub max_depth: usize,
+    // Largest single buffer a length field may request, in bytes.
+    pub max_alloc: usize,
+    // Sum of all buffers allocated for one term, in bytes.
+    pub max_total: u64,
+    // Number of terms, nested ones included, in one term.
+    pub max_elements: u64
+}
+
+impl Limits {
+    // No limits at all, only for input that is trusted.
+    pub fn unlimited() -> Limits {
+        Limits{
+            max_depth: usize::MAX,
+            max_alloc: usize::MAX,
+            max_total: u64::MAX,
+            max_elements: u64::MAX
+        }
+    }
+}
+
+// Generous for real data while keeping a hostile term from exhausting
+// memory: 1M levels of nesting, 64 MiB for one binary, atom or bignum,
+// 1 GiB in all and 50M terms.
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits{
+            max_depth: 1_000_000,
+            max_alloc: 64 << 20,
+            max_total: 1 << 30,
+            max_elements: 50_000_000
+        }
+    }
+}
+
+// Usage counters checked against `Limits` while one term is decoded.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    limits: Limits,
+    depth: usize,
+    total: u64,
+    elements: u64
+}
+
+impl Budget {
+    pub fn new(limits: &Limits) -> Budget {
+        Budget{
+            limits: *limits,
+            depth: 0,
+            total: 0,
+            elements: 0
+        }
+    }
+
+    pub fn enter(&mut self) -> Result<(), ParseError> {
+        if self.depth >= self.limits.max_depth {
+            return Err(ParseError::depth_limit_exceeded());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    pub fn alloc(&mut self, n: usize) -> Result<(), ParseError> {
+        if n > self.limits.max_alloc {
+            return Err(ParseError::alloc_limit_exceeded());
+        }
+        self.total = self.total.saturating_add(n as u64);
+        if self.total > self.limits.max_total {
+            return Err(ParseError::total_size_limit_exceeded());
+        }
+        Ok(())
+    }
+
+    pub fn element(&mut self) -> Result<(), ParseError> {
+        self.elements += 1;
+        if self.elements > self.limits.max_elements {
+            return Err(ParseError::element_limit_exceeded());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Limits;
+    use crate::buf_read_streamer::BufReadStreamer;
+    use crate::json_composer::JsonComposer;
+    use crate::parser::{parse, ErrorCode, ParseResult};
+
+    fn decode(bytes: &[u8], limits: &Limits) -> ParseResult {
+        parse(&mut BufReadStreamer::new(&mut &bytes[..]), &mut JsonComposer::new(), limits)
+    }
+
+    fn error_code(result: ParseResult) -> ErrorCode {
+        result.unwrap_err().error_code
+    }
+
+    #[test]
+    fn defaults_refuse_a_huge_declared_binary() {
+        let res = decode(&[131, 109, 255, 255, 255, 255], &Limits::default());
+        assert_eq!(error_code(res), ErrorCode::AllocLimitExceeded);
+    }
+
+    #[test]
+    fn declared_length_is_not_reserved_up_front() {
+        // Without limits the 4 GiB binary fails for lack of input, having
+        // allocated no more than one read chunk.
+        let res = decode(&[131, 109, 255, 255, 255, 255, 1, 2, 3], &Limits::unlimited());
+        assert_eq!(error_code(res), ErrorCode::UnexpectedEof);
+    }
+
+    #[test]
+    fn depth_limit() {
+        let limits = Limits{ max_depth: 2, ..Limits::default() };
+        // [[1]], [[[1]]] and {#{a => {1}}}
+        assert!(decode(&[131, 108, 0, 0, 0, 1, 108, 0, 0, 0, 1, 97, 1, 106, 106], &limits).is_ok());
+        let res = decode(&[131, 108, 0, 0, 0, 1, 108, 0, 0, 0, 1, 108, 0, 0, 0, 1, 97, 1, 106, 106, 106], &limits);
+        assert_eq!(error_code(res), ErrorCode::DepthLimitExceeded);
+        let res = decode(&[131, 104, 1, 116, 0, 0, 0, 1, 119, 1, b'a', 104, 1, 97, 1], &limits);
+        assert_eq!(error_code(res), ErrorCode::DepthLimitExceeded);
+    }
+
+    #[test]
+    fn alloc_limit() {
+        let limits = Limits{ max_alloc: 3, ..Limits::default() };
+        assert!(decode(&[131, 109, 0, 0, 0, 3, b'a', b'b', b'c'], &limits).is_ok());
+        let res = decode(&[131, 109, 0, 0, 0, 4, b'a', b'b', b'c', b'd'], &limits);
+        assert_eq!(error_code(res), ErrorCode::AllocLimitExceeded);
+        let res = decode(&[131, 119, 4, b'a', b'b', b'c', b'd'], &limits);
+        assert_eq!(error_code(res), ErrorCode::AllocLimitExceeded);
+    }
+
+    #[test]
+    fn total_limit() {
+        let limits = Limits{ max_total: 5, ..Limits::default() };
+        // {<<"ab">>, <<"cde">>} and {<<"abc">>, <<"def">>}
+        assert!(decode(&[131, 104, 2, 109, 0, 0, 0, 2, 1, 2, 109, 0, 0, 0, 3, 3, 4, 5], &limits).is_ok());
+        let res = decode(&[131, 104, 2, 109, 0, 0, 0, 3, 1, 2, 3, 109, 0, 0, 0, 3, 4, 5, 6], &limits);
+        assert_eq!(error_code(res), ErrorCode::TotalSizeLimitExceeded);
+    }
+
+    #[test]
+    fn element_limit() {
+        // The list counts as a term of its own.
+        let limits = Limits{ max_elements: 3, ..Limits::default() };
+        assert!(decode(&[131, 108, 0, 0, 0, 2, 97, 1, 97, 2, 106], &limits).is_ok());
+        let res = decode(&[131, 108, 0, 0, 0, 3, 97, 1, 97, 2, 97, 3, 106], &limits);
+        assert_eq!(error_code(res), ErrorCode::ElementLimitExceeded);
+    }
+}
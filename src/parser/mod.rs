@@ -1,9 +1,18 @@
 mod elem_compose;
+mod limits;
 mod parse_result;
+#[allow(clippy::module_inception)]
 mod parser;
 mod read_stream;
+mod push;
+pub mod tags;
+mod terms;
 
-pub use parser::parse;
+pub use parser::{parse, parse_next, Decoder};
+pub use push::PushDecoder;
 pub use elem_compose::ElemCompose;
 pub use read_stream::ReadStream;
-pub use parse_result::*;
\ No newline at end of file
+pub use limits::Limits;
+pub(crate) use limits::Budget;
+pub use parse_result::*;
+pub use terms::*;
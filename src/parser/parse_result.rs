@@ -1,38 +1,164 @@
-
 use std::fmt;
+use std::error::Error;
+use std::io::ErrorKind;
 
 pub type ParseResult = Result<(), ParseError>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ErrorCode {
-    IOError = 1,
-    NotImplemented = 2,
-    InvalidListTerm = 3,
-    NotErlangBinary = 4
-//    NotUtf8Atom = 5,
+    IOError,
+    UnknownTag(u8),
+    InvalidListTerm,
+    NotErlangBinary,
+    InvalidUtf8Atom,
+    TrailingFrameData,
+    UnexpectedEof,
+    InvalidFloat,
+    LengthOverflow,
+    DepthLimitExceeded,
+    AllocLimitExceeded,
+    TotalSizeLimitExceeded,
+    ElementLimitExceeded,
+    InvalidInputEncoding,
+    InvalidTermSyntax,
+    InvalidEjson,
+    InvalidJson,
+    InvalidBertRpc
+}
+
+impl ErrorCode {
+    // Stable numeric identifier of the error kind.
+    pub fn code(&self) -> i32 {
+        match *self {
+            ErrorCode::IOError => 1,
+            ErrorCode::UnknownTag(_) => 2,
+            ErrorCode::InvalidListTerm => 3,
+            ErrorCode::NotErlangBinary => 4,
+            ErrorCode::InvalidUtf8Atom => 5,
+            ErrorCode::TrailingFrameData => 6,
+            ErrorCode::UnexpectedEof => 7,
+            ErrorCode::InvalidFloat => 8,
+            ErrorCode::LengthOverflow => 9,
+            ErrorCode::DepthLimitExceeded => 10,
+            ErrorCode::AllocLimitExceeded => 11,
+            ErrorCode::TotalSizeLimitExceeded => 12,
+            ErrorCode::ElementLimitExceeded => 13,
+            ErrorCode::InvalidInputEncoding => 14,
+            ErrorCode::InvalidTermSyntax => 15,
+            ErrorCode::InvalidEjson => 16,
+            ErrorCode::InvalidJson => 17,
+            ErrorCode::InvalidBertRpc => 18
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathSegment {
+    List(u32),
+    ListTail,
+    Tuple(u32),
+    MapKey(u32),
+    MapValue(u32)
+}
+
+#[derive(Debug)]
 pub struct ParseError {
     pub error_code: ErrorCode,
     pub io_error: Option<std::io::Error>,
-    pub utf8_error: Option<std::str::Utf8Error>
+    pub utf8_error: Option<std::str::Utf8Error>,
+    pub term_offset: Option<u64>,
+    // Stream position where decoding failed.
+    pub offset: Option<u64>,
+    // Tag byte of the innermost term being decoded.
+    pub tag: Option<u8>,
+    // Container indices leading to the failed term, root first.
+    pub path: Vec<PathSegment>
 }
 
 impl ParseError {
     pub fn not_erlang_binary() -> ParseError {
         ParseError::common_error(ErrorCode::NotErlangBinary)
     }
-    pub fn not_implemented() -> ParseError {
-        ParseError::common_error(ErrorCode::NotImplemented)
+    pub fn unknown_tag(tag: u8) -> ParseError {
+        ParseError::common_error(ErrorCode::UnknownTag(tag))
+    }
+    pub fn invalid_float() -> ParseError {
+        ParseError::common_error(ErrorCode::InvalidFloat)
+    }
+    pub fn length_overflow() -> ParseError {
+        ParseError::common_error(ErrorCode::LengthOverflow)
+    }
+    pub fn depth_limit_exceeded() -> ParseError {
+        ParseError::common_error(ErrorCode::DepthLimitExceeded)
+    }
+    pub fn alloc_limit_exceeded() -> ParseError {
+        ParseError::common_error(ErrorCode::AllocLimitExceeded)
+    }
+    pub fn total_size_limit_exceeded() -> ParseError {
+        ParseError::common_error(ErrorCode::TotalSizeLimitExceeded)
+    }
+    pub fn element_limit_exceeded() -> ParseError {
+        ParseError::common_error(ErrorCode::ElementLimitExceeded)
     }
     pub fn invalid_list_item() -> ParseError {
         ParseError::common_error(ErrorCode::InvalidListTerm)
     }
+    pub fn trailing_frame_data() -> ParseError {
+        ParseError::common_error(ErrorCode::TrailingFrameData)
+    }
+    pub fn invalid_input_encoding() -> ParseError {
+        ParseError::common_error(ErrorCode::InvalidInputEncoding)
+    }
+    pub fn invalid_term_syntax() -> ParseError {
+        ParseError::common_error(ErrorCode::InvalidTermSyntax)
+    }
+    pub fn invalid_ejson() -> ParseError {
+        ParseError::common_error(ErrorCode::InvalidEjson)
+    }
+    pub fn invalid_json() -> ParseError {
+        ParseError::common_error(ErrorCode::InvalidJson)
+    }
+    pub fn invalid_bert_rpc() -> ParseError {
+        ParseError::common_error(ErrorCode::InvalidBertRpc)
+    }
+    pub fn with_term_offset(mut self, offset: u64) -> ParseError {
+        self.term_offset = Some(offset);
+        self
+    }
+    // Records where the error happened, the innermost location wins.
+    pub fn located(mut self, offset: u64, tag: Option<u8>) -> ParseError {
+        if self.offset.is_none() {
+            self.offset = Some(offset);
+            self.tag = tag;
+        }
+        self
+    }
+    pub fn with_path(mut self, path: Vec<PathSegment>) -> ParseError {
+        self.path = path;
+        self
+    }
+    pub fn path_string(&self) -> String {
+        let mut res = String::from("$");
+        for segment in &self.path {
+            match *segment {
+                PathSegment::List(i) => res.push_str(&format!("[{}]", i)),
+                PathSegment::ListTail => res.push_str(".tail"),
+                PathSegment::Tuple(i) => res.push_str(&format!(".tuple[{}]", i)),
+                PathSegment::MapKey(i) => res.push_str(&format!(".map[{}].key", i)),
+                PathSegment::MapValue(i) => res.push_str(&format!(".map[{}].val", i))
+            }
+        }
+        res
+    }
     fn common_error(code: ErrorCode) -> ParseError {
         ParseError{
             error_code: code,
             io_error: None,
-            utf8_error: None
+            utf8_error: None,
+            term_offset: None,
+            offset: None,
+            tag: None,
+            path: vec![]
         }
     }
 }
@@ -40,26 +166,57 @@ impl ParseError {
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f,"{:?}", self.error_code)
+        write!(f, "{:?}", self.error_code)?;
+        if let Some(ref err) = self.io_error {
+            write!(f, ": {}", err)?;
+        }
+        if let Some(ref err) = self.utf8_error {
+            write!(f, ": {}", err)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {}", offset)?;
+            if let Some(tag) = self.tag {
+                write!(f, ", tag {}", tag)?;
+            }
+            write!(f, ", path {}", self.path_string())?;
+        } else if !self.path.is_empty() {
+            write!(f, " at path {}", self.path_string())?;
+        }
+        if let Some(offset) = self.term_offset {
+            write!(f, " (term starting at byte {})", offset)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        if let Some(ref err) = self.io_error {
+            return Some(err);
+        }
+        if let Some(ref err) = self.utf8_error {
+            return Some(err);
+        }
+        None
     }
 }
 
 impl From<std::io::Error> for ParseError {
     fn from(err: std::io::Error) -> Self {
-        ParseError{
-            error_code: ErrorCode::IOError,
-            io_error: Some(err),
-            utf8_error: None
-        }
+        let code = match err.kind() {
+            ErrorKind::UnexpectedEof => ErrorCode::UnexpectedEof,
+            _ => ErrorCode::IOError
+        };
+        let mut res = ParseError::common_error(code);
+        res.io_error = Some(err);
+        res
     }
 }
 
 impl From<std::str::Utf8Error> for ParseError {
     fn from(err: std::str::Utf8Error) -> Self {
-        ParseError{
-            error_code: ErrorCode::IOError,
-            io_error: None,
-            utf8_error: Some(err)
-        }
+        let mut res = ParseError::common_error(ErrorCode::InvalidUtf8Atom);
+        res.utf8_error = Some(err);
+        res
     }
-}
\ No newline at end of file
+}
@@ -1,481 +1,676 @@
 use std::str;
+use std::convert::TryFrom;
 use num_bigint::Sign;
 use num_bigint::BigInt;
 use byteorder::{ByteOrder, BigEndian};
-extern crate hex;
 
 use super::read_stream::ReadStream;
 use super::elem_compose::ElemCompose;
-use super::parse_result::{ParseResult, ParseError};
-
-const ATOM_CACHE_REF: u8 = 82;
-const SMALL_INTEGER_EXT: u8 = 97;
-const INTEGER_EXT: u8 = 98;
-const FLOAT_EXT: u8 = 99;
-const REFERENCE_EXT: u8 = 101;
-const PORT_EXT: u8 = 102;
-const PID_EXT: u8 = 103;
-const SMALL_TUPLE_EXT: u8 = 104;
-const LARGE_TUPLE_EXT: u8 = 105;
-const MAP_EXT: u8 = 116;
-const NIL_EXT: u8 = 106;
-const STRING_EXT: u8 = 107;
-const LIST_EXT: u8 = 108;
-const BINARY_EXT: u8 = 109;
-const SMALL_BIG_EXT: u8 = 110;
-const LARGE_BIG_EXT: u8 = 111;
-const NEW_REFERENCE_EXT: u8 = 114;
-const FUN_EXT: u8 = 117;
-const NEW_FUN_EXT: u8 = 112;
-const EXPORT_EXT: u8 = 113;
-const BIT_BINARY_EXT: u8 = 77;
-const NEW_FLOAT_EXT: u8 = 70;
-const ATOM_UTF8_EXT: u8 = 118;
-const SMALL_ATOM_UTF8_EXT: u8 = 119;
-const ATOM_EXT: u8 = 100;
-const SMALL_ATOM_EXT: u8 = 115;
-
-
-
-pub fn parse<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    if s.read_u8()? == 131 {
-        parse_any(s, c)?;
+use super::parse_result::{ParseResult, ParseError, PathSegment};
+use super::limits::{Limits, Budget};
+use super::terms::{Pid, Port, Reference, Fun, NewFun, Export};
+use super::tags::*;
+
+
+
+pub fn parse<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C, limits: &Limits) -> ParseResult {
+    let mut decoder = Decoder::new(limits);
+    while !decoder.step(s, c)? {}
+    Ok(())
+}
+
+// Returns Ok(false) when the stream ends cleanly on a term boundary.
+pub fn parse_next<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C, limits: &Limits) -> Result<bool, ParseError> {
+    if s.at_eof()? {
+        return Ok(false);
+    }
+    let start = s.position();
+    parse(s, c, limits).map_err(|e| e.with_term_offset(start))?;
+    Ok(true)
+}
+
+// Containers still being decoded. `next` is the index of the element
+// currently decoded, map pairs are decoded key first. `tail` is set once
+// the elements of an improper list are done and its tail is decoded.
+#[derive(Debug, Clone, Copy)]
+enum Frame {
+    List { len: u32, next: u32, tail: bool },
+    Tuple { len: u32, next: u32 },
+    Map { len: u32, next: u32, value: bool },
+    Skip { len: u32, next: u32 }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Version,
+    Term,
+    Advance,
+    Done
+}
+
+// Decodes one term with an explicit stack instead of recursion, so nesting
+// is bounded by memory and `Limits::max_depth` only. Every step either
+// completes or fails before touching the decoder state.
+#[derive(Clone)]
+pub struct Decoder {
+    stack: Vec<Frame>,
+    budget: Budget,
+    skipping: usize,
+    state: State,
+    // Tag of an improper list tail, read while looking for NIL.
+    tail_tag: Option<u8>
+}
+
+impl Decoder {
+    pub fn new(limits: &Limits) -> Decoder {
+        Decoder{
+            stack: vec![],
+            budget: Budget::new(limits),
+            skipping: 0,
+            state: State::Version,
+            tail_tag: None
+        }
+    }
+
+    // Returns true once the whole term has been passed to the composer. A
+    // failed step leaves the decoder as it was, so it can be retried once
+    // more input is available.
+    pub fn step<S: ReadStream, C: ElemCompose>(&mut self, s: &mut S, c: &mut C) -> Result<bool, ParseError> {
+        let mut skip_comp = SkipComposer{};
+        let c: &mut dyn ElemCompose = if self.skipping > 0 { &mut skip_comp } else { c };
+        let budget = self.budget;
+        let res = match self.state {
+            State::Version => self.version(s),
+            State::Term => self.term(s, c),
+            State::Advance => self.advance(s, c),
+            State::Done => Ok(())
+        };
+        if let Err(e) = res {
+            self.budget = budget;
+            return Err(e);
+        }
+        Ok(self.state == State::Done)
+    }
+
+    fn version<S: ReadStream>(&mut self, s: &mut S) -> ParseResult {
+        let version = s.read_u8().map_err(|e| self.locate(e.into(), s.position(), None))?;
+        if version != VERSION {
+            return Err(self.locate(ParseError::not_erlang_binary(), s.position() - 1, None));
+        }
+        self.state = State::Term;
         Ok(())
-    } else {
-        Err(ParseError::not_erlang_binary())
+    }
+
+    fn term<S: ReadStream>(&mut self, s: &mut S, c: &mut dyn ElemCompose) -> ParseResult {
+        let ttype = match self.tail_tag {
+            Some(tag) => tag,
+            None => s.read_u8().map_err(|e| self.locate(e.into(), s.position(), None))?
+        };
+        let b = &mut self.budget;
+        let frame = b.element()
+            .and_then(|_| parse_term(ttype, s, c, b))
+            .map_err(|e| self.locate(e, s.position(), Some(ttype)))?;
+        match frame {
+            Some(frame) => {
+                if let Frame::Skip{..} = frame {
+                    self.skipping += 1;
+                }
+                self.stack.push(frame);
+            },
+            None => self.completed()
+        }
+        self.tail_tag = None;
+        self.state = State::Advance;
+        Ok(())
+    }
+
+    fn advance<S: ReadStream>(&mut self, s: &mut S, c: &mut dyn ElemCompose) -> ParseResult {
+        let top = match self.stack.last() {
+            Some(top) => *top,
+            None => {
+                self.state = State::Done;
+                return Ok(());
+            }
+        };
+        match top {
+            Frame::List{ tail: true, .. } => {
+                self.pop();
+                c.end_list();
+            },
+            Frame::List{ len, next, .. } if next < len => self.state = State::Term,
+            Frame::List{..} => {
+                let tag = s.read_u8().map_err(|e| self.locate(e.into(), s.position(), Some(LIST_EXT)))?;
+                if tag == NIL_EXT {
+                    self.pop();
+                    c.end_list();
+                } else {
+                    if let Some(Frame::List{ tail, .. }) = self.stack.last_mut() {
+                        *tail = true;
+                    }
+                    c.list_tail();
+                    self.tail_tag = Some(tag);
+                    self.state = State::Term;
+                }
+            },
+            Frame::Tuple{ len, next } if next < len => self.state = State::Term,
+            Frame::Tuple{..} => {
+                self.pop();
+                c.end_tuple();
+            },
+            Frame::Map{ len, next, value } if next < len => {
+                if value {
+                    c.map_value();
+                } else {
+                    c.map_key();
+                }
+                self.state = State::Term;
+            },
+            Frame::Map{..} => {
+                self.pop();
+                c.end_map();
+            },
+            Frame::Skip{ len, next } if next < len => self.state = State::Term,
+            Frame::Skip{..} => {
+                self.pop();
+                self.skipping -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) {
+        self.stack.pop();
+        self.budget.leave();
+        self.completed();
+    }
+
+    fn completed(&mut self) {
+        match self.stack.last_mut() {
+            Some(Frame::List{ tail: true, .. }) => {},
+            Some(Frame::List{ next, .. }) |
+            Some(Frame::Tuple{ next, .. }) |
+            Some(Frame::Skip{ next, .. }) => *next += 1,
+            Some(Frame::Map{ next, value, .. }) => {
+                if *value {
+                    *next += 1;
+                }
+                *value = !*value;
+            },
+            None => {}
+        }
+    }
+
+    fn locate(&self, e: ParseError, offset: u64, tag: Option<u8>) -> ParseError {
+        let path = self.stack.iter().filter_map(|frame| match *frame {
+            Frame::List{ tail: true, .. } => Some(PathSegment::ListTail),
+            Frame::List{ next, .. } => Some(PathSegment::List(next)),
+            Frame::Tuple{ next, .. } => Some(PathSegment::Tuple(next)),
+            Frame::Map{ next, value: false, .. } => Some(PathSegment::MapKey(next)),
+            Frame::Map{ next, value: true, .. } => Some(PathSegment::MapValue(next)),
+            Frame::Skip{..} => None
+        }).collect();
+        e.located(offset, tag).with_path(path)
     }
 }
 
-fn parse_any<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    parse_term(s.read_u8()?, s, c)
+fn parse_atom_only<S: ReadStream>(s: &mut S, b: &mut Budget) -> Result<String, ParseError> {
+    match s.read_u8()? {
+        SMALL_ATOM_UTF8_EXT => {
+            let len = s.read_u8()? as u16;
+            read_utf8_atom(len, s, b)
+        },
+        ATOM_UTF8_EXT => {
+            let len = s.read_u16()?;
+            read_utf8_atom(len, s, b)
+        },
+        SMALL_ATOM_EXT => {
+            let len = s.read_u8()? as u16;
+            read_latin1_atom(len, s, b)
+        },
+        ATOM_EXT => {
+            let len = s.read_u16()?;
+            read_latin1_atom(len, s, b)
+        },
+        // Distribution-encoded node names and modules refer to the atom
+        // cache, which isn't available here, so they keep the index.
+        ATOM_CACHE_REF => Ok(s.read_u8()?.to_string()),
+        _ => Err(ParseError::not_erlang_binary())
+    }
 }
 
-fn parse_skip<S: ReadStream>(s: &mut S, n_skip: u32) -> ParseResult {
-    let mut skip_comp = SkipComposer{};
-    for _ in 0..n_skip {
-        parse_any(s, &mut skip_comp)?
+fn parse_int_only<S: ReadStream>(s: &mut S) -> Result<i32, ParseError> {
+    match s.read_u8()? {
+        SMALL_INTEGER_EXT => Ok(s.read_u8()? as i32),
+        INTEGER_EXT => Ok(s.read_i32()?),
+        _ => Err(ParseError::not_erlang_binary())
     }
-    Ok(())
 }
 
-fn parse_atom_only<S: ReadStream>(s: &mut S, res: &mut String) -> ParseResult {
-    let filter_atoms: [u8; 5] = [
-        SMALL_ATOM_UTF8_EXT,
-        ATOM_UTF8_EXT,
-        ATOM_CACHE_REF,
-        ATOM_EXT,
-        SMALL_ATOM_EXT
-    ];
-    parse_filtered(&filter_atoms, s, res)
-}
-
-fn parse_filtered<S: ReadStream>(filter: &[u8], s: &mut S, res: &mut String) -> ParseResult {
-    let term_type = s.read_u8()?;
-    if filter.contains(&term_type) {
-        let mut str_comp = RawStringComposer::new(res);
-        parse_term(term_type, s, &mut str_comp)
-    } else {
-        Err(ParseError::not_erlang_binary())
+fn parse_small_int_only<S: ReadStream>(s: &mut S) -> Result<u8, ParseError> {
+    match s.read_u8()? {
+        SMALL_INTEGER_EXT => Ok(s.read_u8()?),
+        _ => Err(ParseError::not_erlang_binary())
     }
 }
 
-fn parse_term<S: ReadStream, C: ElemCompose>(ttype: u8, s: &mut S, c: &mut C) -> ParseResult {
+fn parse_pid_only<S: ReadStream>(s: &mut S, b: &mut Budget) -> Result<Pid, ParseError> {
+    match s.read_u8()? {
+        PID_EXT => read_pid(s, b),
+        _ => Err(ParseError::not_erlang_binary())
+    }
+}
+
+fn to_len(n: u32) -> Result<usize, ParseError> {
+    usize::try_from(n).map_err(|_| ParseError::length_overflow())
+}
+
+fn parse_term<S: ReadStream, C: ElemCompose + ?Sized>(ttype: u8, s: &mut S, c: &mut C, b: &mut Budget) -> Result<Option<Frame>, ParseError> {
     match ttype {
-        LIST_EXT            => list_ext(s, c),
-        STRING_EXT          => string_ext(s, c),
+        LIST_EXT            => list_ext(s, c, b),
+        SMALL_TUPLE_EXT     => small_tuple_ext(s, c, b),
+        LARGE_TUPLE_EXT     => large_tuple_ext(s, c, b),
+        MAP_EXT             => map_ext(s, c, b),
+        FUN_EXT             => fun_ext(s, c, b),
+        NEW_FUN_EXT         => new_fun_ext(s, c, b),
+        _ => parse_leaf(ttype, s, c, b).map(|_| None)
+    }
+}
+
+fn parse_leaf<S: ReadStream, C: ElemCompose + ?Sized>(ttype: u8, s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    match ttype {
+        STRING_EXT          => string_ext(s, c, b),
         INTEGER_EXT         => integer_ext(s, c),
         SMALL_INTEGER_EXT   => small_integer_ext(s, c),
-        ATOM_EXT            => atom_ext(s, c),
-        SMALL_ATOM_EXT      => small_atom_ext(s, c),
-        SMALL_TUPLE_EXT     => small_tuple_ext(s, c),
-        LARGE_TUPLE_EXT     => large_tuple_ext(s, c),
-        BINARY_EXT          => binary_ext(s, c),
+        ATOM_EXT            => atom_ext(s, c, b),
+        SMALL_ATOM_EXT      => small_atom_ext(s, c, b),
+        BINARY_EXT          => binary_ext(s, c, b),
         FLOAT_EXT           => float_ext(s, c),
-        SMALL_ATOM_UTF8_EXT => small_atom_utf8_ext(s, c),
-        ATOM_UTF8_EXT       => atom_utf8_ext(s, c),
-        REFERENCE_EXT       => reference_ext(s, c),
-        PORT_EXT            => port_ext(s, c),
+        SMALL_ATOM_UTF8_EXT => small_atom_utf8_ext(s, c, b),
+        ATOM_UTF8_EXT       => atom_utf8_ext(s, c, b),
+        REFERENCE_EXT       => reference_ext(s, c, b),
+        PORT_EXT            => port_ext(s, c, b),
         ATOM_CACHE_REF      => atom_cache_ref(s, c),
-        PID_EXT             => pid_ext(s, c),
-        MAP_EXT             => map_ext(s, c),
-        FUN_EXT             => fun_ext(s, c),
-        SMALL_BIG_EXT       => small_big_ext(s, c),
-        LARGE_BIG_EXT       => large_big_ext(s, c),
-        NEW_REFERENCE_EXT   => new_reference_ext(s, c),
-        EXPORT_EXT          => export_ext(s, c),
-        BIT_BINARY_EXT      => bit_binary_ext(s, c),
+        PID_EXT             => pid_ext(s, c, b),
+        SMALL_BIG_EXT       => small_big_ext(s, c, b),
+        LARGE_BIG_EXT       => large_big_ext(s, c, b),
+        NEW_REFERENCE_EXT   => new_reference_ext(s, c, b),
+        EXPORT_EXT          => export_ext(s, c, b),
+        BIT_BINARY_EXT      => bit_binary_ext(s, c, b),
         NEW_FLOAT_EXT       => new_float_ext(s, c),
-        NEW_FUN_EXT         => new_fun_ext(s, c),
-        NIL_EXT             => Ok(()),
-        _ => Err(ParseError::not_implemented()),
+        NIL_EXT             => nil_ext(c),
+        _ => Err(ParseError::unknown_tag(ttype).located(s.position() - 1, Some(ttype))),
     }
 }
 
 
 
-fn list_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("list");
-    let l = s.read_u32()?;    
-    c.push_char('[');
-    for i in 0..l {
-        parse_any(s, c)?;
-        if i + 1 < l {
-            c.push_char(',');
-        }
-    };
-    c.push_char(']');
-    if s.read_u8()? == NIL_EXT {
-        c.close();
-        Ok(())
-    } else {
-        Err(ParseError::invalid_list_item())
-    }
+fn list_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> Result<Option<Frame>, ParseError> {
+    let l = s.read_u32()?;
+    b.enter()?;
+    c.begin_list(l);
+    Ok(Some(Frame::List{ len: l, next: 0, tail: false }))
 }
 
-fn integer_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("int");
-    c.push_str(s.read_i32()?.to_string());
-    c.close();
+fn nil_ext<C: ElemCompose + ?Sized>(c: &mut C) -> ParseResult {
+    c.begin_list(0);
+    c.end_list();
     Ok(())
 }
 
-fn small_integer_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("int");
-    c.push_str(s.read_u8()?.to_string());
-    c.close();
+fn integer_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C) -> ParseResult {
+    c.int(s.read_i32()?);
     Ok(())
 }
 
-fn string_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("str");
-    let l = s.read_u16()?;
-    c.push_char('[');
-    for i in 0..l {
-        c.push_char(s.read_u8()? as char);
-        if i + 1 < l {
-            c.push_char(',');
-        }
-    };
-    c.push_char(']');
-    c.close();
+fn small_integer_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C) -> ParseResult {
+    c.int(s.read_u8()? as i32);
     Ok(())
 }
-fn atom_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C)-> ParseResult {
-    deprecated_atom(s.read_u16()?, s, c)
+
+fn string_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let l = s.read_u16()?;
+    let v = read_bytes(l as usize, s, b)?;
+    c.string(&v);
+    Ok(())
 }
-fn small_atom_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C)-> ParseResult {
-    deprecated_atom(s.read_u8()? as u16, s, c)
+fn atom_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let len = s.read_u16()?;
+    c.atom(&read_latin1_atom(len, s, b)?);
+    Ok(())
 }
-fn deprecated_atom<S: ReadStream, C: ElemCompose>(n: u16, s: &mut S, c: &mut C) -> ParseResult {
-    c.open("atom");
-    c.push_char('\"');
-    for _ in 0..n {
-        c.push_char(s.read_u8()? as char);
-    };
-    c.push_char('\"');
-    c.close();
+fn small_atom_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let len = s.read_u8()? as u16;
+    c.atom(&read_latin1_atom(len, s, b)?);
     Ok(())
 }
+fn read_latin1_atom<S: ReadStream>(n: u16, s: &mut S, b: &mut Budget) -> Result<String, ParseError> {
+    let v = read_bytes(n as usize, s, b)?;
+    Ok(v.into_iter().map(|ch| ch as char).collect())
+}
+
+// Bytes are read in chunks, so memory grows with the data that actually
+// arrives rather than with the length the input claims.
+const READ_CHUNK: usize = 64 * 1024;
 
-fn small_tuple_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C)-> ParseResult {
-    tuple(s.read_u8()? as u32, s, c)
+fn read_bytes<S: ReadStream>(n: usize, s: &mut S, b: &mut Budget) -> Result<Vec<u8>, ParseError> {
+    b.alloc(n)?;
+    s.check_available(n)?;
+    let mut v: Vec<u8> = Vec::with_capacity(n.min(READ_CHUNK));
+    while v.len() < n {
+        let start = v.len();
+        v.resize(start + (n - start).min(READ_CHUNK), 0);
+        s.read_exact(&mut v[start..])?;
+    }
+    Ok(v)
 }
 
-fn large_tuple_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C)-> ParseResult {
-    tuple(s.read_u32()?, s, c)
+fn small_tuple_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> Result<Option<Frame>, ParseError> {
+    tuple(s.read_u8()? as u32, c, b)
 }
 
-fn tuple<S: ReadStream, C: ElemCompose>(n: u32, s: &mut S, c: &mut C) -> ParseResult {
-    c.open("tuple");
-    c.push_char('[');
-    for i in 0..n {
-        parse_any(s, c)?;
-        if i + 1 < n {
-            c.push_char(',');
-        }
-    };
-    c.push_char(']');
-    c.close();
-    Ok(()) 
+fn large_tuple_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> Result<Option<Frame>, ParseError> {
+    tuple(s.read_u32()?, c, b)
 }
 
-fn float_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C)-> ParseResult {
-    c.open("float");
-    for _ in 0..31 {
-        c.push_char(s.read_u8()? as char);
-    };
-    c.close();
+fn tuple<C: ElemCompose + ?Sized>(n: u32, c: &mut C, b: &mut Budget) -> Result<Option<Frame>, ParseError> {
+    b.enter()?;
+    c.begin_tuple(n);
+    Ok(Some(Frame::Tuple{ len: n, next: 0 }))
+}
+
+fn float_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C) -> ParseResult {
+    let mut text: [u8; 31] = [0; 31];
+    s.read_exact(&mut text)?;
+    let fl = str::from_utf8(&text)
+        .ok()
+        .and_then(|t| t.trim_end_matches('\0').parse::<f64>().ok())
+        .filter(|fl| fl.is_finite())
+        .ok_or_else(ParseError::invalid_float)?;
+    c.float(fl);
     Ok(())
 }
 
-fn binary_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C)-> ParseResult {
-    c.open("float");
-    let len = s.read_u32()?;
-    let mut v: Vec<u8> = Vec::with_capacity(len as usize);
-    for _ in 0..len {
-        v.push(s.read_u8()?);
-    };
-    c.push_char('\"');
-    c.push_str(base64::encode(&v));
-    c.push_char('\"');
-    c.close();
+fn binary_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let len = to_len(s.read_u32()?)?;
+    let v = read_bytes(len, s, b)?;
+    c.binary(&v);
     Ok(())
 }
-fn small_atom_utf8_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    atom_utf8(s.read_u8()? as u16, s, c)
+fn small_atom_utf8_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let len = s.read_u8()? as u16;
+    c.atom(&read_utf8_atom(len, s, b)?);
+    Ok(())
 }
-fn atom_utf8_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    atom_utf8(s.read_u16()? as u16, s, c)
+fn atom_utf8_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let len = s.read_u16()?;
+    c.atom(&read_utf8_atom(len, s, b)?);
+    Ok(())
 }
 
-fn atom_utf8<S: ReadStream, C: ElemCompose>(len: u16, s: &mut S, c: &mut C) -> ParseResult {
-    c.open("atom");
-    let mut v: Vec<u8> = vec![];
-    for _ in 0..len {
-        v.push(s.read_u8()?);
-    };
-    c.push_char('\"');
-    c.push_str(str::from_utf8(&v)?);
-    c.push_char('\"');
-    c.close();
-    Ok(())
+fn read_utf8_atom<S: ReadStream>(len: u16, s: &mut S, b: &mut Budget) -> Result<String, ParseError> {
+    let v = read_bytes(len as usize, s, b)?;
+    Ok(str::from_utf8(&v)?.to_string())
 }
 
-fn reference_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("ref");
-    let mut node: String = String::new();
-    parse_atom_only(s, &mut node)?;
-    c.push_str("{\"node\":");
-    c.push_str(node);
-    c.push_str(",\"id\":");
-    c.push_str(s.read_u32()?.to_string());
-    c.push_str(",\"creation\":");
-    c.push_str(s.read_u8()?.to_string());
-    c.push_char('}');
-    c.close();
+fn reference_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let node = parse_atom_only(s, b)?;
+    let id = s.read_u32()?;
+    let creation = s.read_u8()? as u32;
+    c.reference(&Reference{ node, creation, id: vec![id] });
     Ok(())
 }
 
-fn port_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("port");
-    let mut node: String = String::new();
-    parse_atom_only(s, &mut node)?;
-    c.push_str("{\"node\":");
-    c.push_str(node);
-    c.push_str(",\"id\":");
-    c.push_str(s.read_u32()?.to_string());
-    c.push_str(",\"creation\":");
-    c.push_str(s.read_u8()?.to_string());
-    c.push_char('}');
-    c.close();
+fn port_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let node = parse_atom_only(s, b)?;
+    let id = s.read_u32()?;
+    let creation = s.read_u8()? as u32;
+    c.port(&Port{ node, id, creation });
     Ok(())
 }
-fn pid_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("pid");
-    let mut node: String = String::new();
-    parse_atom_only(s, &mut node)?;
-    c.push_str("{\"node\":");
-    c.push_str(node);
-    c.push_str(",\"id\":");
-    c.push_str(s.read_u32()?.to_string());
-    c.push_str(",\"serial\":");
-    c.push_str(s.read_u32()?.to_string());
-    c.push_str(",\"creation\":");
-    c.push_str(s.read_u8()?.to_string());
-    c.push_char('}');
-    c.close();
+fn pid_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    c.pid(&read_pid(s, b)?);
     Ok(())
 }
+fn read_pid<S: ReadStream>(s: &mut S, b: &mut Budget) -> Result<Pid, ParseError> {
+    let node = parse_atom_only(s, b)?;
+    let id = s.read_u32()?;
+    let serial = s.read_u32()?;
+    let creation = s.read_u8()? as u32;
+    Ok(Pid{ node, id, serial, creation })
+}
 
-fn atom_cache_ref<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("acr");
-    c.push_str(s.read_u8()?.to_string());
-    c.close();
+fn atom_cache_ref<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C) -> ParseResult {
+    c.atom_cache_ref(s.read_u8()?);
     Ok(())
 }
 
-fn map_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("map");
+fn map_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> Result<Option<Frame>, ParseError> {
     let n = s.read_u32()?;
-    c.push_char('[');
-    for i in 0..n {
-        c.push_str("{\"key\":");
-        parse_any(s, c)?;
-        c.push_str(",\"val\":");
-        parse_any(s, c)?;
-        c.push_str("}");
-        if i + 1 < n {
-            c.push_char(',');
-        }
-    };
-    c.push_char(']');
-    c.close();
-    Ok(())
+    b.enter()?;
+    c.begin_map(n);
+    Ok(Some(Frame::Map{ len: n, next: 0, value: false }))
 }
 
 
-fn fun_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("fun");
+fn fun_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> Result<Option<Frame>, ParseError> {
     let num_free = s.read_u32()?;
-    let mut pid = String::new();
-    parse_any(s, &mut pid)?;
-    let mut module = String::new();
-    parse_atom_only(s, &mut module)?;
-    let mut index = String::new();
-    parse_any(s, &mut index)?;
-    let mut uniq = String::new();
-    parse_any(s, &mut uniq)?;
-    parse_skip(s, num_free)?;
-    c.push_str(format!(
-        "{{\"pid\":{},\"m\":{},\"index\":{},\"uniq\":{}}}", 
-        pid, module,  index, uniq
-    ));
-    c.close();
-    Ok(())
+    let pid = parse_pid_only(s, b)?;
+    let module = parse_atom_only(s, b)?;
+    let index = parse_int_only(s)?;
+    let uniq = parse_int_only(s)?;
+    c.fun(&Fun{ pid, module, index, uniq });
+    skip_free_vars(num_free, b)
 }
 
-fn small_big_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    big(s.read_u8()? as usize, s, c)
+fn small_big_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    big(s.read_u8()? as usize, s, c, b)
 }
 
-fn large_big_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    big(s.read_u32()? as usize, s, c)
+fn large_big_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    big(to_len(s.read_u32()?)?, s, c, b)
 }
-// TODO: deside best type
-fn big<S: ReadStream, C: ElemCompose>(n: usize, s: &mut S, c: &mut C) -> ParseResult {
-    c.open("bigint");
+
+fn big<S: ReadStream, C: ElemCompose + ?Sized>(n: usize, s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
     let sign: Sign = if s.read_u8()? > 0 { Sign::Minus } else { Sign::Plus};
-    let mut digits: Vec<u8> = Vec::with_capacity(n);
-    for _ in 0..n {
-        digits.push(s.read_u8()?);
-    };
-    let r = BigInt::from_bytes_le(sign, &digits);
-    c.push_str(r.to_string());
+    let digits = read_bytes(n, s, b)?;
+    c.bigint(&BigInt::from_bytes_le(sign, &digits));
     Ok(())
 }
 
-fn new_reference_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
+fn new_reference_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
     let len = s.read_u16()?;
-    let mut node: String = String::new();
-    parse_atom_only(s, &mut node)?;
-    let creation = s.read_u8()?;
+    let node = parse_atom_only(s, b)?;
+    let creation = s.read_u8()? as u32;
+    b.alloc(len as usize * 4)?;
+    let mut id: Vec<u32> = Vec::with_capacity(len as usize);
     for _ in 0..len {
-        s.read_u32()?;
+        id.push(s.read_u32()?);
     };
-    c.push_str(format!(
-        "{{\"node\":{},\"creation\":{}}}", 
-        node, 
-        creation
-    ));
-    c.close();
+    c.reference(&Reference{ node, creation, id });
     Ok(())
 }
 
 
-fn bit_binary_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("bitstr");
-    let len = s.read_u32()?;
+fn bit_binary_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let len = to_len(s.read_u32()?)?;
     let bits = s.read_u8()?;
-    let mut v: Vec<u8> = vec![];
-    for _ in 0..len {
-        v.push(s.read_u8()?);
-    };
-    c.push_str("{\"bits\":");
-    c.push_str(bits.to_string());
-    c.push_str(",\"data\":\"");
-    c.push_str(base64::encode(&v));
-    c.push_str("\"}");
-    c.close();
+    // Bits used in the last byte.
+    if !(1..=8).contains(&bits) {
+        return Err(ParseError::length_overflow());
+    }
+    let v = read_bytes(len, s, b)?;
+    c.bit_binary(&v, bits);
     Ok(())
 }
 
-fn export_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("expfun");
-    let mut module = String::new();
-    parse_atom_only(s, &mut module)?;
-    let mut func = String::new();
-    parse_atom_only(s, &mut func)?;
-    let mut arity = String::new();
-    parse_filtered(&[SMALL_INTEGER_EXT], s, &mut arity)?;
-    c.push_str("{\"m\":"); c.push_str(module);
-    c.push_str(",\"f\":"); c.push_str(func);
-    c.push_str(",\"a\":"); c.push_str(arity);
-    c.push_char('}');
-    c.close();
+fn export_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> ParseResult {
+    let module = parse_atom_only(s, b)?;
+    let function = parse_atom_only(s, b)?;
+    let arity = parse_small_int_only(s)?;
+    c.export(&Export{ module, function, arity });
     Ok(())
 }
-fn new_float_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("float");
+fn new_float_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C) -> ParseResult {
     let mut ieee_float: [u8; 8] = [0; 8];
     s.read_exact(&mut ieee_float)?;
-    let fl = BigEndian::read_f64(&ieee_float).to_string();
-    c.push_str(fl);
-    c.close();
+    // Erlang has no infinities or NaN, `binary_to_term` rejects them too.
+    let fl = BigEndian::read_f64(&ieee_float);
+    if !fl.is_finite() {
+        return Err(ParseError::invalid_float());
+    }
+    c.float(fl);
     Ok(())
 }
 
 
-fn new_fun_ext<S: ReadStream, C: ElemCompose>(s: &mut S, c: &mut C) -> ParseResult {
-    c.open("newfun");
+fn new_fun_ext<S: ReadStream, C: ElemCompose + ?Sized>(s: &mut S, c: &mut C, b: &mut Budget) -> Result<Option<Frame>, ParseError> {
     let _size = s.read_u32()?;
     let arity = s.read_u8()?;
     let mut uniq: [u8; 16] = [0; 16];
     s.read_exact(&mut uniq)?;
-    let uniq_hex = hex::encode(uniq.to_vec());
     let index = s.read_u32()?;
     let num_free = s.read_u32()?;
 
-    let mut module = String::new();
-    parse_atom_only(s, &mut module)?;
-    let mut old_index = String::new();
-    parse_filtered(&[INTEGER_EXT, SMALL_INTEGER_EXT], s, &mut old_index)?;
-    let mut old_uniq = String::new();
-    parse_filtered(&[INTEGER_EXT, SMALL_INTEGER_EXT], s, &mut old_uniq)?; 
-    let mut pid = String::new();
-    parse_filtered(&[PID_EXT], s, &mut pid)?;
-    parse_skip(s, num_free)?;
-    c.push_str(format!(
-        "{{\"m\":{},\"a\":{},\"uniq\":\"{}\",\"index\":{},\"old_uniq\":{},\"old_index\":{},\"pid\":{}}}", 
-        module, 
-        arity,
-        uniq_hex,
-        index, 
-        old_uniq,
-        old_index,
-        pid
-    ));
-    c.close();
-    Ok(())
+    let module = parse_atom_only(s, b)?;
+    let old_index = parse_int_only(s)?;
+    let old_uniq = parse_int_only(s)?;
+    let pid = parse_pid_only(s, b)?;
+    c.new_fun(&NewFun{ module, arity, uniq, index, old_index, old_uniq, pid });
+    skip_free_vars(num_free, b)
+}
+
+fn skip_free_vars(n: u32, b: &mut Budget) -> Result<Option<Frame>, ParseError> {
+    if n == 0 {
+        return Ok(None);
+    }
+    b.enter()?;
+    Ok(Some(Frame::Skip{ len: n, next: 0 }))
 }
 
 struct SkipComposer;
 
 impl ElemCompose for SkipComposer {
-    fn open(&mut self, _name: &str) {}
-    fn push_str<T: AsRef<str>>(&mut self, _elem: T) {}
-    fn push_char(&mut self, _elem: char) {}
-    fn close(&mut self) {}
-}
+    fn begin_list(&mut self, _len: u32) {}
+    fn list_tail(&mut self) {}
+    fn end_list(&mut self) {}
+    fn begin_tuple(&mut self, _arity: u32) {}
+    fn end_tuple(&mut self) {}
+    fn begin_map(&mut self, _len: u32) {}
+    fn map_key(&mut self) {}
+    fn map_value(&mut self) {}
+    fn end_map(&mut self) {}
+    fn atom(&mut self, _name: &str) {}
+    fn int(&mut self, _v: i32) {}
+    fn bigint(&mut self, _v: &BigInt) {}
+    fn float(&mut self, _v: f64) {}
+    fn string(&mut self, _chars: &[u8]) {}
+    fn binary(&mut self, _data: &[u8]) {}
+    fn bit_binary(&mut self, _data: &[u8], _bits: u8) {}
+    fn pid(&mut self, _pid: &Pid) {}
+    fn port(&mut self, _port: &Port) {}
+    fn reference(&mut self, _reference: &Reference) {}
+    fn atom_cache_ref(&mut self, _index: u8) {}
+    fn fun(&mut self, _fun: &Fun) {}
+    fn new_fun(&mut self, _fun: &NewFun) {}
+    fn export(&mut self, _export: &Export) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Decoder};
+    use crate::buf_read_streamer::BufReadStreamer;
+    use crate::json_composer::JsonComposer;
+    use crate::parser::{ErrorCode, Limits, ParseError, PathSegment};
+
+    fn to_json(bytes: &[u8], limits: &Limits) -> Result<String, ParseError> {
+        let mut c = JsonComposer::new();
+        parse(&mut BufReadStreamer::new(&mut &bytes[..]), &mut c, limits)?;
+        Ok(c.result().to_string())
+    }
 
-struct RawStringComposer<'a> {
-    result: &'a mut String
-}
+    // `depth` lists, each holding the next, around an empty one.
+    fn nested_lists(depth: usize) -> Vec<u8> {
+        let mut bytes = vec![131];
+        for _ in 0..depth {
+            bytes.extend_from_slice(&[108, 0, 0, 0, 1]);
+        }
+        bytes.extend(std::iter::repeat_n(106, depth + 1));
+        bytes
+    }
 
-impl <'a>RawStringComposer<'a> {
-    fn new(res_str: &'a mut String) -> RawStringComposer {
-        RawStringComposer{
-            result: res_str
+    #[test]
+    fn output_matches_the_recursive_decoder() {
+        // Recorded from the recursive decoder this one replaced, with every
+        // tag the decoder knows. Reference ids have since lost the spaces
+        // `Debug` put between them.
+        let bytes = include_bytes!("../../tests/fixtures/all_tags.etf");
+        let expected = include_str!("../../tests/fixtures/all_tags.json");
+        assert_eq!(to_json(bytes, &Limits::default()).unwrap(), expected.trim_end());
+    }
+
+    #[test]
+    fn decodes_100k_nested_lists() {
+        let depth = 100_000;
+        let json = to_json(&nested_lists(depth), &Limits::default()).unwrap();
+        let expected = "{\"list\":[".repeat(depth) + "{\"list\":[]}" + &"]}".repeat(depth);
+        assert!(json == expected);
+    }
+
+    #[test]
+    fn nesting_stops_at_the_depth_limit() {
+        let limits = Limits{ max_depth: 1000, ..Limits::default() };
+        assert!(to_json(&nested_lists(1000), &limits).is_ok());
+        let err = to_json(&nested_lists(1001), &limits).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::DepthLimitExceeded);
+        assert_eq!(err.path.len(), 1000);
+    }
+
+    #[test]
+    fn error_names_the_path() {
+        // {a, #{1 => [2, <unknown tag 0>]}}
+        let bytes = [131, 104, 2, 119, 1, b'a', 116, 0, 0, 0, 1, 97, 1, 108, 0, 0, 0, 2, 97, 2, 0];
+        let err = to_json(&bytes, &Limits::default()).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::UnknownTag(0));
+        assert_eq!(err.offset, Some(20));
+        assert_eq!(err.path, vec![PathSegment::Tuple(1), PathSegment::MapValue(0), PathSegment::List(1)]);
+    }
+
+    #[test]
+    fn floats_must_be_finite() {
+        let mut old_float = [0; 32];
+        old_float[0] = 99;
+        old_float[1..4].copy_from_slice(b"inf");
+        let infinity = 0x7ff0_0000_0000_0000u64.to_be_bytes();
+        let nan = 0x7ff8_0000_0000_0000u64.to_be_bytes();
+        for float in [&old_float[..], &[&[70][..], &infinity].concat(), &[&[70][..], &nan].concat()] {
+            let err = to_json(&[&[131][..], float].concat(), &Limits::default()).unwrap_err();
+            assert_eq!(err.error_code, ErrorCode::InvalidFloat);
         }
+        assert_eq!(to_json(&[131, 70, 63, 248, 0, 0, 0, 0, 0, 0], &Limits::default()).unwrap(), "{\"float\":1.5}");
     }
-}
 
-impl<'a> ElemCompose for RawStringComposer<'a> {
-    fn open(&mut self, _name: &str) {}
-    fn push_str<T: AsRef<str>>(&mut self, elem: T) {
-        self.result.push_str(elem.as_ref())
+    #[test]
+    fn bit_binary_needs_1_to_8_bits() {
+        assert!(to_json(&[131, 77, 0, 0, 0, 1, 8, 255], &Limits::default()).is_ok());
+        for bits in [0, 9] {
+            let err = to_json(&[131, 77, 0, 0, 0, 1, bits, 255], &Limits::default()).unwrap_err();
+            assert_eq!(err.error_code, ErrorCode::LengthOverflow);
+            assert_eq!(err.tag, Some(77));
+        }
     }
-    fn push_char(&mut self, elem: char) {
-        self.result.push(elem);
+
+    #[test]
+    fn stepping_gives_the_same_output() {
+        let bytes = include_bytes!("../../tests/fixtures/all_tags.etf");
+        let mut r = &bytes[..];
+        let mut s = BufReadStreamer::new(&mut r);
+        let mut decoder = Decoder::new(&Limits::default());
+        let mut c = JsonComposer::new();
+        let mut steps = 0;
+        while !decoder.step(&mut s, &mut c).unwrap() {
+            steps += 1;
+        }
+        assert!(steps > 25);
+        assert_eq!(c.result(), include_str!("../../tests/fixtures/all_tags.json").trim_end());
     }
-    fn close(&mut self) {}
 }
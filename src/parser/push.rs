@@ -0,0 +1,252 @@
+use std::io::{Error, ErrorKind};
+
+use super::read_stream::ReadStream;
+use super::elem_compose::ElemCompose;
+use super::parse_result::{ParseResult, ParseError, ErrorCode};
+use super::limits::Limits;
+use super::parser::Decoder;
+
+// Incremental decoder for input that arrives in arbitrary chunks. Bytes are
+// buffered until the pending decoding step can complete, finished steps are
+// never repeated.
+pub struct PushDecoder {
+    limits: Limits,
+    buffer: Vec<u8>,
+    // Bytes of `buffer` already decoded.
+    pos: usize,
+    // Stream offset of `buffer[0]`.
+    base: u64,
+    decoder: Option<Decoder>,
+    term_start: u64
+}
+
+impl PushDecoder {
+    pub fn new(limits: &Limits) -> PushDecoder {
+        PushDecoder{
+            limits: *limits,
+            buffer: vec![],
+            pos: 0,
+            base: 0,
+            decoder: None,
+            term_start: 0
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) {
+        if self.pos > 0 {
+            self.buffer.drain(..self.pos);
+            self.base += self.pos as u64;
+            self.pos = 0;
+        }
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    // Decodes buffered input into `c`. Returns Ok(true) when a term is
+    // complete and Ok(false) when more input is needed, in which case the
+    // same composer must be passed again after the next `feed`. After an
+    // error the rest of the buffered input is discarded, decoding restarts
+    // with the next `feed` and offsets keep counting the discarded bytes.
+    pub fn next_term<C: ElemCompose>(&mut self, c: &mut C) -> Result<bool, ParseError> {
+        if self.decoder.is_none() {
+            if self.pos == self.buffer.len() {
+                return Ok(false);
+            }
+            self.decoder = Some(Decoder::new(&self.limits));
+            self.term_start = self.base + self.pos as u64;
+        }
+        let mut s = SliceStreamer{
+            data: &self.buffer,
+            pos: self.pos,
+            base: self.base
+        };
+        let decoder = self.decoder.as_mut().unwrap();
+        loop {
+            let checkpoint = s.pos;
+            match decoder.step(&mut s, c) {
+                Ok(true) => {
+                    self.pos = s.pos;
+                    self.decoder = None;
+                    return Ok(true);
+                },
+                Ok(false) => {},
+                Err(ref e) if e.error_code == ErrorCode::UnexpectedEof => {
+                    self.pos = checkpoint;
+                    return Ok(false);
+                },
+                Err(e) => {
+                    self.decoder = None;
+                    self.base += self.buffer.len() as u64;
+                    self.buffer.clear();
+                    self.pos = 0;
+                    return Err(e.with_term_offset(self.term_start));
+                }
+            }
+        }
+    }
+
+    // Fails if the input stopped inside a term.
+    pub fn finish(&self) -> ParseResult {
+        if self.decoder.is_none() && self.pos == self.buffer.len() {
+            return Ok(());
+        }
+        let offset = self.base + self.buffer.len() as u64;
+        let err: ParseError = Error::new(ErrorKind::UnexpectedEof, "input ended inside a term").into();
+        Err(err.located(offset, None).with_term_offset(self.term_start))
+    }
+}
+
+struct SliceStreamer<'a> {
+    data: &'a [u8],
+    pos: usize,
+    base: u64
+}
+
+impl<'a> SliceStreamer<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut b: [u8; N] = [0; N];
+        self.read_exact(&mut b)?;
+        Ok(b)
+    }
+}
+
+impl<'a> ReadStream for SliceStreamer<'a> {
+    fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(u8::from_be_bytes(self.take()?))
+    }
+    fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.take()?))
+    }
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.take()?))
+    }
+    fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_be_bytes(self.take()?))
+    }
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        let end = self.pos + buf.len();
+        if end > self.data.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "need more input"));
+        }
+        buf.copy_from_slice(&self.data[self.pos..end]);
+        self.pos = end;
+        Ok(())
+    }
+    fn position(&self) -> u64 {
+        self.base + self.pos as u64
+    }
+    fn at_eof(&mut self) -> Result<bool, Error> {
+        Ok(self.pos == self.data.len())
+    }
+    // A leaf still arriving is retried once it is complete, instead of being
+    // allocated and copied again on every chunk.
+    fn check_available(&mut self, n: usize) -> Result<(), Error> {
+        if self.data.len() - self.pos < n {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "need more input"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PushDecoder;
+    use crate::json_composer::JsonComposer;
+    use crate::parser::{parse_next, ErrorCode, Limits};
+    use crate::buf_read_streamer::BufReadStreamer;
+
+    const FIXTURE: &[u8] = include_bytes!("../../tests/fixtures/all_tags.etf");
+    const FIXTURE_JSON: &str = include_str!("../../tests/fixtures/all_tags.json");
+
+    // Feeds `bytes` in chunks of `size`, collecting every finished term.
+    fn decode_chunked(bytes: &[u8], size: usize) -> Vec<String> {
+        let mut decoder = PushDecoder::new(&Limits::default());
+        let mut c = JsonComposer::new();
+        let mut terms = vec![];
+        for chunk in bytes.chunks(size) {
+            decoder.feed(chunk);
+            while decoder.next_term(&mut c).unwrap() {
+                terms.push(c.result().to_string());
+                c = JsonComposer::new();
+            }
+        }
+        decoder.finish().unwrap();
+        terms
+    }
+
+    #[test]
+    fn one_byte_at_a_time() {
+        let bytes = [FIXTURE, &[131, 97, 7][..], FIXTURE].concat();
+        let terms = decode_chunked(&bytes, 1);
+        assert_eq!(terms, vec![FIXTURE_JSON.trim_end(), "{\"int\":7}", FIXTURE_JSON.trim_end()]);
+        assert_eq!(decode_chunked(&bytes, 7), terms);
+        assert_eq!(decode_chunked(&bytes, bytes.len()), terms);
+    }
+
+    #[test]
+    fn large_leaf_in_small_chunks() {
+        // Would copy the partial binary again on every chunk if the leaf
+        // were retried before it is complete.
+        let len = 1 << 20;
+        let mut bytes = vec![131, 109];
+        bytes.extend_from_slice(&(len as u32).to_be_bytes());
+        bytes.extend(std::iter::repeat_n(0, len));
+        let terms = decode_chunked(&bytes, 16);
+        assert_eq!(terms.len(), 1);
+        assert!(terms[0].starts_with("{\"binary\":\"AAAA"));
+    }
+
+    #[test]
+    fn unfinished_term_fails_at_finish() {
+        let mut decoder = PushDecoder::new(&Limits::unlimited());
+        decoder.feed(&[131, 97, 1, 131, 109, 255, 255, 255, 255, 0]);
+        let mut c = JsonComposer::new();
+        assert!(decoder.next_term(&mut c).unwrap());
+        assert!(!decoder.next_term(&mut JsonComposer::new()).unwrap());
+        let err = decoder.finish().unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::UnexpectedEof);
+        assert_eq!(err.term_offset, Some(3));
+    }
+
+    #[test]
+    fn errors_give_the_term_offset() {
+        let mut decoder = PushDecoder::new(&Limits::default());
+        decoder.feed(&[131, 97, 1, 131, 0]);
+        assert!(decoder.next_term(&mut JsonComposer::new()).unwrap());
+        let err = decoder.next_term(&mut JsonComposer::new()).unwrap_err();
+        assert_eq!(err.error_code, ErrorCode::UnknownTag(0));
+        assert_eq!(err.term_offset, Some(3));
+    }
+
+    #[test]
+    fn errors_discard_the_buffer() {
+        let mut decoder = PushDecoder::new(&Limits::default());
+        decoder.feed(&[131, 0, 131, 97, 1]);
+        let err = decoder.next_term(&mut JsonComposer::new()).unwrap_err();
+        assert_eq!((err.offset, err.term_offset), (Some(1), Some(0)));
+        // The term buffered behind the bad one is gone.
+        assert!(!decoder.next_term(&mut JsonComposer::new()).unwrap());
+        decoder.finish().unwrap();
+        decoder.feed(&[131, 97, 2, 131, 0]);
+        let mut c = JsonComposer::new();
+        assert!(decoder.next_term(&mut c).unwrap());
+        assert_eq!(c.result(), "{\"int\":2}");
+        let err = decoder.next_term(&mut JsonComposer::new()).unwrap_err();
+        assert_eq!((err.offset, err.term_offset), (Some(9), Some(8)));
+    }
+
+    #[test]
+    fn parse_next_stops_on_a_term_boundary() {
+        let bytes = [131, 97, 1, 131, 97, 2];
+        let mut r = &bytes[..];
+        let mut s = BufReadStreamer::new(&mut r);
+        let mut terms = vec![];
+        loop {
+            let mut c = JsonComposer::new();
+            if !parse_next(&mut s, &mut c, &Limits::default()).unwrap() {
+                break;
+            }
+            terms.push(c.result().to_string());
+        }
+        assert_eq!(terms, vec!["{\"int\":1}", "{\"int\":2}"]);
+    }
+}
@@ -6,4 +6,14 @@ pub trait ReadStream {
     fn read_u32(&mut self) -> Result<u32, std::io::Error>;
     fn read_i32(&mut self) -> Result<i32, std::io::Error>;
     fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), std::io::Error>;
+    // Number of bytes consumed so far.
+    fn position(&self) -> u64;
+    // True when no more bytes can be read, without consuming anything.
+    fn at_eof(&mut self) -> Result<bool, std::io::Error>;
+    // Fails with UnexpectedEof when the stream knows that fewer than `n`
+    // bytes are left, so a leaf is refused before its buffer is allocated.
+    fn check_available(&mut self, _n: usize) -> Result<(), std::io::Error> {
+        Ok(())
+    }
 }
+
@@ -0,0 +1,47 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pid {
+    pub node: String,
+    pub id: u32,
+    pub serial: u32,
+    pub creation: u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Port {
+    pub node: String,
+    pub id: u32,
+    pub creation: u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub node: String,
+    pub creation: u32,
+    pub id: Vec<u32>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fun {
+    pub pid: Pid,
+    pub module: String,
+    pub index: i32,
+    pub uniq: i32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewFun {
+    pub module: String,
+    pub arity: u8,
+    pub uniq: [u8; 16],
+    pub index: u32,
+    pub old_index: i32,
+    pub old_uniq: i32,
+    pub pid: Pid
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Export {
+    pub module: String,
+    pub function: String,
+    pub arity: u8
+}
@@ -0,0 +1,123 @@
+use std::io::{self, Read, Write, ErrorKind};
+
+use crate::parser::{ElemCompose, Limits, ParseError, ParseResult};
+use crate::json_composer::JsonComposer;
+use crate::etf_composer::EtfComposer;
+use crate::packet::{Packet, read_len, read_body, check_len, write_frame, parse_frame};
+
+// Serves an Erlang port opened with `{packet, 4}`: every request frame holds
+// one external term, every reply frame holds either the JSON text or the
+// external term `{error, Reason}` with the reason as a binary. Replies are
+// told apart by their first byte, 131 only starts the error term. A frame
+// longer than `limits` allow is skipped and answered with an error, only
+// the end of `r` or a broken stream ends the port.
+pub fn run<R: Read, W: Write>(r: &mut R, w: &mut W, limits: &Limits) -> ParseResult {
+    while let Some(len) = read_len(r, Packet::Four)? {
+        let reply = match check_len(len, limits) {
+            Ok(()) => reply(&read_body(r, len)?, limits)?,
+            Err(error) => {
+                skip(r, len)?;
+                error_term(&error.to_string())?
+            }
+        };
+        write_frame(w, Packet::Four, &reply)?;
+        w.flush()?;
+    }
+    Ok(())
+}
+
+fn reply(frame: &[u8], limits: &Limits) -> Result<Vec<u8>, ParseError> {
+    let mut composer = JsonComposer::new();
+    match parse_frame(frame, &mut composer, limits) {
+        Ok(_) => Ok(composer.result().as_bytes().to_vec()),
+        Err(error) => error_term(&error.to_string())
+    }
+}
+
+fn skip<R: Read>(r: &mut R, len: usize) -> ParseResult {
+    if io::copy(&mut r.take(len as u64), &mut io::sink())? < len as u64 {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "truncated frame").into());
+    }
+    Ok(())
+}
+
+fn error_term(reason: &str) -> Result<Vec<u8>, ParseError> {
+    let mut term = EtfComposer::new();
+    term.begin_tuple(2);
+    term.atom("error");
+    term.binary(reason.as_bytes());
+    term.end_tuple();
+    term.into_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run;
+    use crate::packet::{Packet, read_frame, write_frame};
+    use crate::parser::{ErrorCode, Limits};
+
+    fn serve(requests: &[&[u8]], limits: &Limits) -> (Vec<Vec<u8>>, Result<(), ErrorCode>) {
+        let mut input = vec![];
+        for request in requests {
+            write_frame(&mut input, Packet::Four, request).unwrap();
+        }
+        serve_bytes(&input, limits)
+    }
+
+    fn serve_bytes(input: &[u8], limits: &Limits) -> (Vec<Vec<u8>>, Result<(), ErrorCode>) {
+        let mut out = vec![];
+        let result = run(&mut &input[..], &mut out, limits).map_err(|e| e.error_code);
+        let mut r = &out[..];
+        let mut replies = vec![];
+        while let Some(frame) = read_frame(&mut r, Packet::Four, &Limits::unlimited()).unwrap() {
+            replies.push(frame);
+        }
+        (replies, result)
+    }
+
+    fn is_error(reply: &[u8]) -> bool {
+        reply.starts_with(&[131, 104, 2, 119, 5, b'e', b'r', b'r', b'o', b'r', 109])
+    }
+
+    #[test]
+    fn json_replies() {
+        let (replies, result) = serve(&[&[131, 97, 1], &[131, 100, 0, 2, b'o', b'k']], &Limits::default());
+        assert_eq!(replies, [&b"{\"int\":1}"[..], &b"{\"atom\":\"ok\"}"[..]]);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn errors_reply_and_continue() {
+        let (replies, result) = serve(&[&[131, 0], &[131, 97, 1, 0], &[], &[131, 97, 2]], &Limits::default());
+        assert_eq!(replies.len(), 4);
+        assert!(replies[..3].iter().all(|reply| is_error(reply)));
+        assert_eq!(replies[3], b"{\"int\":2}");
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn oversized_frames_are_skipped() {
+        let limits = Limits{ max_alloc: 8, ..Limits::default() };
+        let big = [&[131, 109, 0, 0, 0, 10][..], b"0123456789"].concat();
+        let (replies, result) = serve(&[&big, &[131, 97, 3]], &limits);
+        assert_eq!(replies.len(), 2);
+        assert!(is_error(&replies[0]));
+        assert_eq!(replies[1], b"{\"int\":3}");
+        assert_eq!(result, Ok(()));
+        // Cut short inside the skipped body.
+        let (replies, result) = serve_bytes(&[0, 0, 0, 20, 131, 97], &limits);
+        assert!(replies.is_empty());
+        assert_eq!(result, Err(ErrorCode::UnexpectedEof));
+    }
+
+    #[test]
+    fn end_of_input() {
+        assert_eq!(serve_bytes(&[], &Limits::default()), (vec![], Ok(())));
+        let (replies, result) = serve_bytes(&[0, 0, 0, 3, 131, 97, 1, 0, 0], &Limits::default());
+        assert_eq!(replies, [b"{\"int\":1}"]);
+        assert_eq!(result, Err(ErrorCode::UnexpectedEof));
+        let (replies, result) = serve_bytes(&[0, 0, 0, 3, 131], &Limits::default());
+        assert!(replies.is_empty());
+        assert_eq!(result, Err(ErrorCode::UnexpectedEof));
+    }
+}
@@ -0,0 +1,452 @@
+use std::fmt::Display;
+use num_bigint::BigInt;
+
+use crate::json_composer::{JsonComposer, JsonOptions, push_json_str};
+use crate::json_writer::{Commas, JsonWrite};
+use crate::parser::{ElemCompose, Pid, Port, Reference, Fun, NewFun, Export};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrettyOptions {
+    // Spaces per nesting level.
+    pub indent: usize,
+    // Containers that fit in this many columns stay on one line, 0 breaks
+    // every non-empty container.
+    pub width: usize,
+    // ANSI colours by term type.
+    pub color: bool
+}
+
+impl Default for PrettyOptions {
+    fn default() -> PrettyOptions {
+        PrettyOptions{
+            indent: 2,
+            width: 80,
+            color: false
+        }
+    }
+}
+
+// Indented JSON of the same shape as `JsonComposer` produces, laid out as
+// it is composed.
+pub struct PrettyComposer {
+    json: JsonComposer<PrettyWriter>
+}
+
+impl PrettyComposer {
+    pub fn new() -> PrettyComposer {
+        PrettyComposer::with_options(JsonOptions::default(), PrettyOptions::default())
+    }
+
+    pub fn with_options(json: JsonOptions, options: PrettyOptions) -> PrettyComposer {
+        PrettyComposer{
+            json: JsonComposer::with_writer(json, PrettyWriter::new(options))
+        }
+    }
+
+    pub fn result(&self) -> &str {
+        self.json.writer().result()
+    }
+}
+
+impl Default for PrettyComposer {
+    fn default() -> Self {
+        PrettyComposer::new()
+    }
+}
+
+impl ElemCompose for PrettyComposer {
+    fn begin_list(&mut self, len: u32) {
+        self.json.begin_list(len);
+    }
+    fn list_tail(&mut self) {
+        self.json.list_tail();
+    }
+    fn end_list(&mut self) {
+        self.json.end_list();
+    }
+    fn begin_tuple(&mut self, arity: u32) {
+        self.json.begin_tuple(arity);
+    }
+    fn end_tuple(&mut self) {
+        self.json.end_tuple();
+    }
+    fn begin_map(&mut self, len: u32) {
+        self.json.begin_map(len);
+    }
+    fn map_key(&mut self) {
+        self.json.map_key();
+    }
+    fn map_value(&mut self) {
+        self.json.map_value();
+    }
+    fn end_map(&mut self) {
+        self.json.end_map();
+    }
+    fn atom(&mut self, name: &str) {
+        self.json.atom(name);
+    }
+    fn int(&mut self, v: i32) {
+        self.json.int(v);
+    }
+    fn bigint(&mut self, v: &BigInt) {
+        self.json.bigint(v);
+    }
+    fn float(&mut self, v: f64) {
+        self.json.float(v);
+    }
+    fn string(&mut self, chars: &[u8]) {
+        self.json.string(chars);
+    }
+    fn binary(&mut self, data: &[u8]) {
+        self.json.binary(data);
+    }
+    fn bit_binary(&mut self, data: &[u8], bits: u8) {
+        self.json.bit_binary(data, bits);
+    }
+    fn pid(&mut self, pid: &Pid) {
+        self.json.pid(pid);
+    }
+    fn port(&mut self, port: &Port) {
+        self.json.port(port);
+    }
+    fn reference(&mut self, reference: &Reference) {
+        self.json.reference(reference);
+    }
+    fn atom_cache_ref(&mut self, index: u8) {
+        self.json.atom_cache_ref(index);
+    }
+    fn fun(&mut self, fun: &Fun) {
+        self.json.fun(fun);
+    }
+    fn new_fun(&mut self, fun: &NewFun) {
+        self.json.new_fun(fun);
+    }
+    fn export(&mut self, export: &Export) {
+        self.json.export(export);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open(char),
+    Close(char),
+    Colon,
+    Comma,
+    // JSON text of a key and the colour it gives the scalars below it.
+    Key(String, Option<Option<Kind>>),
+    Value(String)
+}
+
+impl Token {
+    // Columns taken when printed on a single line.
+    fn flat_width(&self) -> usize {
+        match *self {
+            Token::Open(_) | Token::Close(_) => 1,
+            Token::Colon | Token::Comma => 2,
+            Token::Key(ref s, _) | Token::Value(ref s) => s.chars().count()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Atom,
+    Binary,
+    Number,
+    Pid,
+    Fun
+}
+
+// Colour of scalars below a wrapper key. Outer None keeps the colour of the
+// enclosing wrapper, inner None clears it.
+fn key_kind(key: &str) -> Option<Option<Kind>> {
+    match key {
+        "atom" => Some(Some(Kind::Atom)),
+        "binary" | "utf8" | "str" | "bitstr" => Some(Some(Kind::Binary)),
+        "int" | "bigint" | "float" | "acr" => Some(Some(Kind::Number)),
+        "pid" | "port" | "ref" => Some(Some(Kind::Pid)),
+        "fun" | "newfun" | "expfun" => Some(Some(Kind::Fun)),
+        "struct" | "record" => Some(Some(Kind::Atom)),
+        "date" | "time" | "naive_datetime" | "datetime" | "decimal" => Some(Some(Kind::Number)),
+        "uri" => Some(Some(Kind::Binary)),
+        "list" | "tail" | "tuple" | "map" | "key" | "val" => Some(None),
+        "fields" | "mapset" | "range" => Some(None),
+        _ => None
+    }
+}
+
+fn color_code(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Atom => "\x1b[36m",
+        Kind::Binary => "\x1b[32m",
+        Kind::Number => "\x1b[33m",
+        Kind::Pid => "\x1b[35m",
+        Kind::Fun => "\x1b[34m"
+    }
+}
+
+// Indented JSON. A container stays on one line when it fits in
+// `options.width` columns, so the tokens of the outermost container not yet
+// known to fit are held back, never more than a line's worth. Works without
+// recursion, so the nesting depth doesn't matter.
+pub struct PrettyWriter {
+    out: String,
+    col: usize,
+    options: PrettyOptions,
+    commas: Commas,
+    // Containers broken over several lines.
+    level: usize,
+    kind: Option<Kind>,
+    kinds: Vec<Option<Kind>>,
+    // Held back tokens, their width on one line and their open containers.
+    pending: Vec<Token>,
+    pending_width: usize,
+    pending_depth: usize,
+    // Held back tokens to lay out again after their container broke, last
+    // one first.
+    replay: Vec<Token>
+}
+
+impl PrettyWriter {
+    pub fn new(options: PrettyOptions) -> PrettyWriter {
+        PrettyWriter{
+            out: String::new(),
+            col: 0,
+            options,
+            commas: Commas::default(),
+            level: 0,
+            kind: None,
+            kinds: vec![],
+            pending: vec![],
+            pending_width: 0,
+            pending_depth: 0,
+            replay: vec![]
+        }
+    }
+
+    pub fn result(&self) -> &str {
+        &self.out
+    }
+
+    pub fn into_result(self) -> String {
+        self.out
+    }
+
+    fn element(&mut self) {
+        if self.commas.element() {
+            self.push(Token::Comma);
+        }
+    }
+
+    fn push(&mut self, token: Token) {
+        self.feed(token);
+        while let Some(token) = self.replay.pop() {
+            self.feed(token);
+        }
+    }
+
+    fn feed(&mut self, token: Token) {
+        if self.pending.is_empty() {
+            match token {
+                Token::Open(_) => {
+                    self.pending_width = 1;
+                    self.pending_depth = 1;
+                    self.pending.push(token);
+                },
+                _ => self.emit(token, false)
+            }
+            return;
+        }
+        self.pending_width += token.flat_width();
+        match token {
+            Token::Open(_) => self.pending_depth += 1,
+            Token::Close(_) => self.pending_depth -= 1,
+            _ => {}
+        }
+        self.pending.push(token);
+        let fits = self.options.width > 0 && self.col + self.pending_width <= self.options.width;
+        if self.pending_depth == 0 && (fits || self.pending.len() == 2) {
+            for token in std::mem::take(&mut self.pending) {
+                self.emit(token, true);
+            }
+        } else if !fits {
+            // The outermost held back container breaks, what it holds gets
+            // laid out again.
+            let mut tokens = std::mem::take(&mut self.pending).into_iter();
+            if let Some(open) = tokens.next() {
+                self.emit(open, false);
+            }
+            self.replay.extend(tokens.rev());
+        }
+    }
+
+    fn emit(&mut self, token: Token, flat: bool) {
+        match token {
+            Token::Open(ch) => {
+                self.write(&ch.to_string(), None);
+                self.kinds.push(self.kind);
+                if !flat {
+                    self.level += 1;
+                    self.newline();
+                }
+            },
+            Token::Close(ch) => {
+                if !flat {
+                    self.level -= 1;
+                    self.newline();
+                }
+                self.write(&ch.to_string(), None);
+                self.kind = self.kinds.pop().unwrap_or(None);
+            },
+            Token::Colon => self.write(": ", None),
+            Token::Comma if flat => self.write(", ", None),
+            Token::Comma => {
+                self.write(",", None);
+                self.newline();
+            },
+            Token::Key(text, kind) => {
+                if let Some(kind) = kind {
+                    self.kind = kind;
+                }
+                self.write(&text, None);
+            },
+            Token::Value(text) => self.write(&text, self.kind)
+        }
+    }
+
+    fn write(&mut self, s: &str, kind: Option<Kind>) {
+        match kind {
+            Some(kind) if self.options.color => {
+                self.out.push_str(color_code(kind));
+                self.out.push_str(s);
+                self.out.push_str("\x1b[0m");
+            },
+            _ => self.out.push_str(s)
+        }
+        self.col += s.chars().count();
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.col = self.level * self.options.indent;
+        self.out.extend(std::iter::repeat_n(' ', self.col));
+    }
+}
+
+impl JsonWrite for PrettyWriter {
+    fn begin_object(&mut self) {
+        self.element();
+        self.commas.open();
+        self.push(Token::Open('{'));
+    }
+    fn end_object(&mut self) {
+        self.commas.close();
+        self.push(Token::Close('}'));
+    }
+    fn begin_array(&mut self) {
+        self.element();
+        self.commas.open();
+        self.push(Token::Open('['));
+    }
+    fn end_array(&mut self) {
+        self.commas.close();
+        self.push(Token::Close(']'));
+    }
+    fn key(&mut self, key: &str) {
+        if self.commas.key() {
+            self.push(Token::Comma);
+        }
+        let mut text = String::new();
+        push_json_str(&mut text, key);
+        self.push(Token::Key(text, key_kind(key)));
+        self.push(Token::Colon);
+    }
+    fn value<T: Display>(&mut self, v: T) {
+        self.element();
+        self.push(Token::Value(v.to_string()));
+    }
+    fn string(&mut self, s: &str) {
+        self.element();
+        let mut text = String::new();
+        push_json_str(&mut text, s);
+        self.push(Token::Value(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PrettyComposer, PrettyOptions};
+    use crate::json_composer::JsonOptions;
+    use crate::parse_erlang_term;
+    use crate::parser::Limits;
+
+    fn render(text: &str, indent: usize, width: usize, color: bool) -> String {
+        let term = parse_erlang_term(text, &Limits::default()).unwrap();
+        let mut c = PrettyComposer::with_options(JsonOptions::default(), PrettyOptions{ indent, width, color });
+        term.compose(&mut c);
+        c.result().to_string()
+    }
+
+    #[test]
+    fn fitting_containers_stay_on_one_line() {
+        assert_eq!(render("[1, ok]", 2, 80, false), r#"{"list": [{"int": 1}, {"atom": "ok"}]}"#);
+        assert_eq!(render("#{a => 1}", 2, 80, false),
+            r#"{"map": [{"key": {"atom": "a"}, "val": {"int": 1}}]}"#);
+    }
+
+    #[test]
+    fn indentation() {
+        assert_eq!(render("{ok, [1]}", 4, 0, false), concat!(
+            "{\n",
+            "    \"tuple\": [\n",
+            "        {\n",
+            "            \"atom\": \"ok\"\n",
+            "        },\n",
+            "        {\n",
+            "            \"list\": [\n",
+            "                {\n",
+            "                    \"int\": 1\n",
+            "                }\n",
+            "            ]\n",
+            "        }\n",
+            "    ]\n",
+            "}"));
+    }
+
+    #[test]
+    fn only_containers_too_wide_break() {
+        assert_eq!(render("{ok, [1, 2]}", 2, 40, false), concat!(
+            "{\n",
+            "  \"tuple\": [\n",
+            "    {\"atom\": \"ok\"},\n",
+            "    {\"list\": [{\"int\": 1}, {\"int\": 2}]}\n",
+            "  ]\n",
+            "}"));
+    }
+
+    #[test]
+    fn empty_containers() {
+        assert_eq!(render("[]", 2, 0, false), "{\n  \"list\": []\n}");
+        assert_eq!(render("{[], #{}}", 2, 80, false), r#"{"tuple": [{"list": []}, {"map": []}]}"#);
+    }
+
+    #[test]
+    fn deep_nesting() {
+        // Past column 80 every container breaks, four lines a level.
+        let text = format!("{}{}", "[".repeat(500), "]".repeat(500));
+        let term = parse_erlang_term(&text, &Limits::default()).unwrap();
+        let mut c = PrettyComposer::new();
+        term.compose(&mut c);
+        assert_eq!(c.result().matches('\n').count(), 4 * 500 - 2);
+        assert!(c.result().ends_with("\n}"));
+    }
+
+    #[test]
+    fn colour_escapes() {
+        assert_eq!(render("{ok, 1, <<\"a\">>}", 2, 80, true), concat!(
+            "{\"tuple\": [{\"atom\": \x1b[36m\"ok\"\x1b[0m}, ",
+            "{\"int\": \x1b[33m1\x1b[0m}, ",
+            "{\"binary\": \x1b[32m\"YQ==\"\x1b[0m}]}"));
+        assert!(!render("{ok, 1}", 2, 80, false).contains('\x1b'));
+    }
+}
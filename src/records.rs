@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+
+use crate::parser::ParseError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordDef {
+    pub name: String,
+    pub fields: Vec<String>
+}
+
+// Known records by name and tuple arity, later definitions replacing earlier
+// ones.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordTable {
+    defs: HashMap<(String, usize), Vec<String>>
+}
+
+impl RecordTable {
+    pub fn new() -> RecordTable {
+        RecordTable::default()
+    }
+
+    pub fn add(&mut self, def: RecordDef) {
+        self.defs.insert((def.name, def.fields.len() + 1), def.fields);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.defs.is_empty()
+    }
+
+    // Field names of the record a tuple of `arity` tagged `name` would be.
+    pub fn fields(&self, name: &str, arity: usize) -> Option<&[String]> {
+        self.defs.get(&(name.to_string(), arity)).map(|fields| &fields[..])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Atom(String),
+    // Variables, numbers, strings and other text records don't look into.
+    Other,
+    Punct(&'static str)
+}
+
+const PUNCTS: [&str; 13] = ["<<", ">>", "(", ")", "{", "}", "[", "]", "-", ",", ".", "=", "::"];
+
+// Keywords whose block runs to a matching `end`.
+const BLOCKS: [&str; 7] = ["begin", "case", "if", "receive", "try", "maybe", "fun"];
+
+// Finds every `-record(Name, {Field, ..}).` declaration in Erlang source such
+// as a `.hrl` file. Defaults and types of fields are skipped, other forms
+// are ignored.
+pub fn parse_record_defs(text: &str) -> Result<Vec<RecordDef>, ParseError> {
+    let tokens = tokenize(text)?;
+    let mut defs = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        let form_start = i == 0 || tokens[i - 1].1 == Token::Punct(".");
+        let is_record = tokens.get(i + 1).is_some_and(|t| t.1 == Token::Atom("record".to_string()));
+        if form_start && tokens[i].1 == Token::Punct("-") && is_record {
+            let (def, next) = record(&tokens, i + 2, text.len())?;
+            defs.push(def);
+            i = next;
+        } else {
+            i += 1;
+        }
+    }
+    Ok(defs)
+}
+
+// The declaration from the paren after `-record`, and the index after its
+// closing paren.
+fn record(tokens: &[(usize, Token)], mut i: usize, end: usize) -> Result<(RecordDef, usize), ParseError> {
+    let error = |i: usize| ParseError::invalid_term_syntax().located(tokens.get(i).map_or(end, |t| t.0) as u64, None);
+    let token = |i: usize| tokens.get(i).map(|t| &t.1);
+    let name = match (token(i), token(i + 1), token(i + 2), token(i + 3)) {
+        (Some(Token::Punct("(")), Some(Token::Atom(name)), Some(Token::Punct(",")), Some(Token::Punct("{"))) => name.clone(),
+        _ => return Err(error(i))
+    };
+    i += 4;
+    let mut fields = vec![];
+    if token(i) == Some(&Token::Punct("}")) {
+        i += 1;
+    } else {
+        loop {
+            match token(i) {
+                Some(Token::Atom(field)) => fields.push(field.clone()),
+                _ => return Err(error(i))
+            }
+            i += 1;
+            match token(i) {
+                Some(Token::Punct("=")) | Some(Token::Punct("::")) | Some(Token::Punct(",")) | Some(Token::Punct("}")) => {},
+                _ => return Err(error(i))
+            }
+            // Skip `= Default` and `:: Type` up to the next field.
+            let mut depth = 0;
+            loop {
+                match token(i) {
+                    None => return Err(error(i)),
+                    Some(Token::Punct(p)) if depth == 0 && (*p == "," || *p == "}") => break,
+                    Some(Token::Punct(p)) if ["(", "{", "[", "<<"].contains(p) => depth += 1,
+                    Some(Token::Punct(p)) if [")", "}", "]", ">>"].contains(p) => depth -= 1,
+                    // `fun m:f/1` has no `end`.
+                    Some(Token::Atom(word)) if word == "fun" && token(i + 1) != Some(&Token::Punct("(")) => {},
+                    Some(Token::Atom(word)) if BLOCKS.contains(&word.as_str()) => depth += 1,
+                    Some(Token::Atom(word)) if word == "end" => depth -= 1,
+                    _ => {}
+                }
+                if depth < 0 {
+                    return Err(error(i));
+                }
+                i += 1;
+            }
+            i += 1;
+            if token(i - 1) == Some(&Token::Punct("}")) {
+                break;
+            }
+        }
+    }
+    if token(i) != Some(&Token::Punct(")")) {
+        return Err(error(i));
+    }
+    Ok((RecordDef{ name, fields }, i + 1))
+}
+
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let bytes = text.as_bytes();
+    let error = |pos: usize| ParseError::invalid_term_syntax().located(pos as u64, None);
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let start = pos;
+        let b = bytes[pos];
+        let token = match b {
+            b'%' => {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            },
+            _ if b.is_ascii_whitespace() => {
+                pos += 1;
+                continue;
+            },
+            b'"' | b'\'' => {
+                pos += 1;
+                let mut name = String::new();
+                loop {
+                    let ch = text[pos..].chars().next().ok_or_else(|| error(start))?;
+                    pos += ch.len_utf8();
+                    if ch == b as char {
+                        break;
+                    }
+                    // Escapes only matter for finding the closing quote.
+                    if ch == '\\' {
+                        let escaped = text[pos..].chars().next().ok_or_else(|| error(start))?;
+                        pos += escaped.len_utf8();
+                        name.push(escaped);
+                    } else {
+                        name.push(ch);
+                    }
+                }
+                if b == b'"' { Token::Other } else { Token::Atom(name) }
+            },
+            b'$' => {
+                // A character literal, `$\n` included.
+                pos += 1;
+                if bytes.get(pos) == Some(&b'\\') {
+                    pos += 1;
+                }
+                let ch = text[pos..].chars().next().ok_or_else(|| error(start))?;
+                pos += ch.len_utf8();
+                Token::Other
+            },
+            _ if b.is_ascii_alphanumeric() || b == b'_' || b == b'?' || b >= 0x80 => {
+                pos += 1;
+                while pos < bytes.len() {
+                    let b = bytes[pos];
+                    let fraction = b == b'.' && bytes[start].is_ascii_digit() && bytes.get(pos + 1).is_some_and(u8::is_ascii_digit);
+                    if b.is_ascii_alphanumeric() || b == b'_' || b == b'@' || b == b'#' || b >= 0x80 || fraction {
+                        pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                if bytes[start].is_ascii_lowercase() {
+                    Token::Atom(text[start..pos].to_string())
+                } else {
+                    Token::Other
+                }
+            },
+            _ => match PUNCTS.iter().find(|p| text[pos..].starts_with(**p)) {
+                Some(p) => {
+                    pos += p.len();
+                    Token::Punct(p)
+                },
+                None => {
+                    pos += text[pos..].chars().next().map_or(1, char::len_utf8);
+                    Token::Other
+                }
+            }
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{parse_record_defs, RecordDef, RecordTable};
+    use crate::json_composer::JsonOptions;
+    use crate::parse_erlang_term;
+    use crate::parser::{ErrorCode, Limits};
+    use crate::semantic::{semantic_json, SemanticOptions};
+
+    fn def(name: &str, fields: &[&str]) -> RecordDef {
+        RecordDef{ name: name.to_string(), fields: fields.iter().map(|f| f.to_string()).collect() }
+    }
+
+    fn defs(text: &str) -> Vec<RecordDef> {
+        parse_record_defs(text).unwrap()
+    }
+
+    fn table(text: &str) -> RecordTable {
+        let mut table = RecordTable::new();
+        for def in defs(text) {
+            table.add(def);
+        }
+        table
+    }
+
+    fn render(records: &RecordTable, text: &str) -> String {
+        let term = parse_erlang_term(text, &Limits::default()).unwrap();
+        let options = SemanticOptions{ records: Arc::new(records.clone()), ..SemanticOptions::default() };
+        semantic_json(&term, JsonOptions::default(), &options)
+    }
+
+    #[test]
+    fn plain_fields() {
+        assert_eq!(defs("-record(user, {id, name})."), vec![def("user", &["id", "name"])]);
+        assert_eq!(defs("-record(empty, {})."), vec![def("empty", &[])]);
+        assert_eq!(defs("-record('Quoted rec', {'a b', c})."), vec![def("Quoted rec", &["a b", "c"])]);
+    }
+
+    #[test]
+    fn defaults_and_types() {
+        let text = "-record(conn, {
+            host = \"localhost\" :: string(),
+            port = 5432 :: 1..65535,
+            opts = [{ssl, false}, {'timeout', 1.5}] :: [{atom(), term()}],
+            bin = <<\"x\", 1:8>>,
+            pid :: pid() | undefined,
+            cb = fun(X) -> case X of {a, _} -> ok; _ -> error end end,
+            ref = fun lists:reverse/1,
+            sum = begin 1 + 2 end,
+            map = #{a => 1} :: #{atom() => integer()},
+            ch = $}
+        }).";
+        assert_eq!(defs(text), vec![def("conn", &["host", "port", "opts", "bin", "pid", "cb", "ref", "sum", "map", "ch"])]);
+    }
+
+    #[test]
+    fn comments_and_other_forms() {
+        let text = "%% -record(commented, {x}).
+            -module(m).
+            -define(R, -record).
+            -type t() :: #user{}.
+            -record(user, { % the id
+                id,   % another comment, with a } in it
+                name = \"-record(fake, {y}).\"
+            }).
+            f() -> #user{}.
+            -record(point, {x = 0, y = 0}).";
+        assert_eq!(defs(text), vec![def("user", &["id", "name"]), def("point", &["x", "y"])]);
+    }
+
+    #[test]
+    fn invalid_declarations() {
+        for text in ["-record(user, {id, }).", "-record(user, {1}).", "-record(user).", "-record(user, {id = (}).",
+                "-record(user, {id = end}).", "-record(user, {id = \"open})."] {
+            let error = parse_record_defs(text).unwrap_err();
+            assert_eq!(error.error_code, ErrorCode::InvalidTermSyntax, "{}", text);
+            assert!(error.offset.is_some(), "{}", text);
+        }
+        assert_eq!(parse_record_defs("-record(user, {id, 1}).").unwrap_err().offset, Some(19));
+    }
+
+    #[test]
+    fn arity() {
+        let records = table("-record(pair, {a, b}). -record(pair, {a, b, c}).");
+        assert_eq!(records.fields("pair", 3), Some(&["a".to_string(), "b".to_string()][..]));
+        assert_eq!(records.fields("pair", 4).map(|f| f.len()), Some(3));
+        assert_eq!(records.fields("pair", 2), None);
+        assert_eq!(records.fields("other", 3), None);
+        assert_eq!(render(&records, "{pair, 1, 2}"),
+            r#"{"record":"pair","fields":{"a":{"int":1},"b":{"int":2}}}"#);
+        // No definition of arity 5, an ordinary tuple.
+        assert!(render(&records, "{pair, 1, 2, 3, 4}").starts_with(r#"{"tuple":["#));
+        // A later definition of the same size replaces an earlier one.
+        let records = table("-record(pair, {a, b}). -record(pair, {x, y}).");
+        assert_eq!(records.fields("pair", 3), Some(&["x".to_string(), "y".to_string()][..]));
+    }
+
+    #[test]
+    fn nested_records() {
+        let records = table("-record(point, {x, y}). -record(line, {from, to}).");
+        assert_eq!(render(&records, "[{line, {point, 0, 0}, {point, 1, 2}}, {point, a}]"), concat!(
+            r#"{"list":[{"record":"line","fields":{"#,
+            r#""from":{"record":"point","fields":{"x":{"int":0},"y":{"int":0}}},"#,
+            r#""to":{"record":"point","fields":{"x":{"int":1},"y":{"int":2}}}}},"#,
+            r#"{"tuple":[{"atom":"point"},{"atom":"a"}]}]}"#));
+    }
+}
@@ -0,0 +1,560 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use num_bigint::Sign;
+use num_traits::ToPrimitive;
+
+use crate::json_composer::{JsonComposer, JsonOptions};
+use crate::json_writer::JsonWrite;
+use crate::parser::{ElemCompose, ParseError, PathSegment};
+use crate::pretty_composer::{PrettyOptions, PrettyWriter};
+use crate::records::RecordTable;
+use crate::times::TimeOptions;
+use crate::term::{Term, Render, RenderComposer};
+
+// Conversions on top of the tagged JSON of `JsonComposer`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SemanticOptions {
+    // Maps with a `__struct__` key as `{"struct":"Name","fields":{..}}`, and
+    // the Elixir standard library types in their canonical text form.
+    pub structs: bool,
+    // Tuples tagged and sized like a known record as
+    // `{"record":"name","fields":{..}}`. Shared, since every record of a
+    // split term gets its own options.
+    pub records: Arc<RecordTable>,
+    // Calendar and system time terms as ISO 8601 text.
+    pub times: TimeOptions
+}
+
+impl SemanticOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.structs || !self.records.is_empty() || self.times.is_enabled()
+    }
+}
+
+// A path segment with the map key or record field name it stands for.
+type Step<'a> = (PathSegment, Option<&'a str>);
+
+enum Work<'a> {
+    Term(&'a Term),
+    Enter(Step<'a>),
+    Leave,
+    ListTail,
+    EndList,
+    EndTuple,
+    MapKey,
+    MapValue,
+    EndMap,
+    Field(&'a str),
+    EndFields,
+    EndArray
+}
+
+// Renders a term as `JsonComposer` would, with the conversions `options`
+// turns on applied to every term they recognise.
+pub fn semantic_json(term: &Term, json: JsonOptions, options: &SemanticOptions) -> String {
+    let mut c = JsonComposer::with_options(json);
+    compose_semantic(term, &mut c, options);
+    c.result().to_string()
+}
+
+// `semantic_json` into any `JsonComposer`.
+pub fn compose_semantic<W: JsonWrite>(term: &Term, c: &mut JsonComposer<W>, options: &SemanticOptions) {
+    let mut path: Vec<Step> = vec![];
+    let mut work = vec![Work::Term(term)];
+    while let Some(next) = work.pop() {
+        let term = match next {
+            Work::Term(term) => term,
+            Work::Enter(step) => { path.push(step); continue; },
+            Work::Leave => { path.pop(); continue; },
+            Work::ListTail => { c.list_tail(); continue; },
+            Work::EndList => { c.end_list(); continue; },
+            Work::EndTuple => { c.end_tuple(); continue; },
+            Work::MapKey => { c.map_key(); continue; },
+            Work::MapValue => { c.map_value(); continue; },
+            Work::EndMap => { c.end_map(); continue; },
+            Work::Field(key) => { c.field(key); continue; },
+            Work::EndFields => { c.end_fields(); continue; },
+            Work::EndArray => { c.end_array(); continue; }
+        };
+        if let Some((kind, text)) = options.times.convert(term, &path) {
+            c.leaf_str(kind, &text);
+            continue;
+        }
+        if options.structs && push_struct(c, &mut work, term) {
+            continue;
+        }
+        if push_record(c, &mut work, term, &options.records) {
+            continue;
+        }
+        match *term {
+            Term::List(ref items, ref tail) => {
+                c.begin_list(items.len() as u32);
+                work.push(Work::EndList);
+                if let Some(ref tail) = *tail {
+                    visit(&mut work, tail, (PathSegment::ListTail, None));
+                    work.push(Work::ListTail);
+                }
+                for (i, item) in items.iter().enumerate().rev() {
+                    visit(&mut work, item, (PathSegment::List(i as u32), None));
+                }
+            },
+            Term::Tuple(ref items) => {
+                c.begin_tuple(items.len() as u32);
+                work.push(Work::EndTuple);
+                for (i, item) in items.iter().enumerate().rev() {
+                    visit(&mut work, item, (PathSegment::Tuple(i as u32), None));
+                }
+            },
+            Term::Map(ref pairs) => {
+                c.begin_map(pairs.len() as u32);
+                work.push(Work::EndMap);
+                for (i, (key, value)) in pairs.iter().enumerate().rev() {
+                    visit(&mut work, value, (PathSegment::MapValue(i as u32), key_name(key)));
+                    work.push(Work::MapValue);
+                    visit(&mut work, key, (PathSegment::MapKey(i as u32), None));
+                    work.push(Work::MapKey);
+                }
+            },
+            // Scalars have nothing to walk into.
+            _ => term.compose(c)
+        }
+    }
+}
+
+// Pushes `term` to be rendered one step below the current path.
+fn visit<'a>(work: &mut Vec<Work<'a>>, term: &'a Term, step: Step<'a>) {
+    work.push(Work::Leave);
+    work.push(Work::Term(term));
+    work.push(Work::Enter(step));
+}
+
+// The name a map key gives its value in rule paths.
+fn key_name(key: &Term) -> Option<&str> {
+    match *key {
+        Term::Atom(ref name) => Some(name),
+        Term::Binary(ref data) => std::str::from_utf8(data).ok(),
+        _ => None
+    }
+}
+
+// A tuple of a known record, false when the term isn't one.
+fn push_record<'a, W: JsonWrite>(c: &mut JsonComposer<W>, work: &mut Vec<Work<'a>>, term: &'a Term, records: &'a RecordTable) -> bool {
+    let items = match *term {
+        Term::Tuple(ref items) => items,
+        _ => return false
+    };
+    let name = match items.first().and_then(Term::as_atom) {
+        Some(name) => name,
+        None => return false
+    };
+    let fields = match records.fields(name, items.len()) {
+        Some(fields) => fields,
+        None => return false
+    };
+    c.begin_fields("record", name);
+    work.push(Work::EndFields);
+    for (i, (field, value)) in fields.iter().zip(&items[1..]).enumerate().rev() {
+        visit(work, value, (PathSegment::Tuple(i as u32 + 1), Some(field)));
+        work.push(Work::Field(field));
+    }
+    true
+}
+
+// An Elixir struct, false when the term isn't one.
+fn push_struct<'a, W: JsonWrite>(c: &mut JsonComposer<W>, work: &mut Vec<Work<'a>>, term: &'a Term) -> bool {
+    let pairs = match *term {
+        Term::Map(ref pairs) => pairs,
+        _ => return false
+    };
+    let mut module = None;
+    let mut fields = vec![];
+    let mut indices = vec![];
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        match (key.as_atom(), value.as_atom()) {
+            (Some("__struct__"), Some(name)) => module = Some(name),
+            (Some(key), _) => {
+                fields.push((key, value));
+                indices.push(i as u32);
+            },
+            (None, _) => return false
+        }
+    }
+    let name = match module {
+        Some(module) => module.strip_prefix("Elixir.").unwrap_or(module),
+        None => return false
+    };
+    let text = match name {
+        "Date" => date(&fields).map(|text| ("date", text)),
+        "Time" => time(&fields).map(|text| ("time", text)),
+        "NaiveDateTime" => naive_datetime(&fields).map(|text| ("naive_datetime", text)),
+        "DateTime" => datetime(&fields).map(|text| ("datetime", text)),
+        "Decimal" => decimal(&fields).map(|text| ("decimal", text)),
+        "URI" => uri(&fields).map(|text| ("uri", text)),
+        _ => None
+    };
+    if let Some((kind, text)) = text {
+        c.leaf_str(kind, &text);
+        return true;
+    }
+    match (name, fields.iter().position(|&(key, _)| key == "map")) {
+        ("MapSet", Some(i)) => if let Term::Map(ref members) = *fields[i].1 {
+            c.begin_array("mapset");
+            work.push(Work::EndArray);
+            work.push(Work::Leave);
+            for (j, (member, _)) in members.iter().enumerate().rev() {
+                visit(work, member, (PathSegment::MapKey(j as u32), None));
+            }
+            work.push(Work::Enter((PathSegment::MapValue(indices[i]), Some("map"))));
+            return true;
+        },
+        ("Range", _) => {
+            if let Some((first, last, step)) = range(&fields) {
+                c.open("range");
+                let w = c.writer_mut();
+                w.begin_object();
+                for (key, v) in [("first", first), ("last", last), ("step", step)] {
+                    w.key(key);
+                    w.value(v);
+                }
+                w.end_object();
+                c.close();
+                return true;
+            }
+        },
+        _ => {}
+    }
+    c.begin_fields("struct", name);
+    work.push(Work::EndFields);
+    for (&(key, value), &i) in fields.iter().zip(&indices).rev() {
+        visit(work, value, (PathSegment::MapValue(i), Some(key)));
+        work.push(Work::Field(key));
+    }
+    true
+}
+
+fn get<'a>(fields: &[(&str, &'a Term)], key: &str) -> Option<&'a Term> {
+    fields.iter().find(|(k, _)| *k == key).map(|&(_, v)| v)
+}
+
+fn int(fields: &[(&str, &Term)], key: &str) -> Option<i64> {
+    match get(fields, key)? {
+        Term::Int(v) => Some(*v as i64),
+        Term::BigInt(ref v) => v.to_i64(),
+        _ => None
+    }
+}
+
+fn int_in(fields: &[(&str, &Term)], key: &str, min: i64, max: i64) -> Option<i64> {
+    int(fields, key).filter(|v| (min..=max).contains(v))
+}
+
+// A binary field as text, `Some(None)` for `nil`.
+fn text<'a>(fields: &[(&str, &'a Term)], key: &str) -> Option<Option<&'a str>> {
+    match get(fields, key)? {
+        Term::Binary(ref data) => std::str::from_utf8(data).ok().map(Some),
+        Term::Atom(ref name) if name == "nil" => Some(None),
+        _ => None
+    }
+}
+
+// Only the ISO calendar has a canonical text form.
+fn iso_calendar(fields: &[(&str, &Term)]) -> bool {
+    get(fields, "calendar").and_then(Term::as_atom).is_none_or(|name| name == "Elixir.Calendar.ISO")
+}
+
+fn date(fields: &[(&str, &Term)]) -> Option<String> {
+    if !iso_calendar(fields) {
+        return None;
+    }
+    // What four digits hold, as in Elixir.
+    let year = int_in(fields, "year", -9999, 9999)?;
+    let month = int_in(fields, "month", 1, 12)?;
+    let day = int_in(fields, "day", 1, 31)?;
+    let sign = if year < 0 { "-" } else { "" };
+    Some(format!("{}{:04}-{:02}-{:02}", sign, year.unsigned_abs(), month, day))
+}
+
+fn time(fields: &[(&str, &Term)]) -> Option<String> {
+    if !iso_calendar(fields) {
+        return None;
+    }
+    let hour = int_in(fields, "hour", 0, 23)?;
+    let minute = int_in(fields, "minute", 0, 59)?;
+    let second = int_in(fields, "second", 0, 60)?;
+    let mut res = format!("{:02}:{:02}:{:02}", hour, minute, second);
+    // `{Microseconds, Precision}`, the precision in digits to show.
+    match get(fields, "microsecond")? {
+        Term::Tuple(ref us) if us.len() == 2 => match (&us[0], &us[1]) {
+            (&Term::Int(us), &Term::Int(0)) if (0..1_000_000).contains(&us) => {},
+            (&Term::Int(us), &Term::Int(precision)) if (0..1_000_000).contains(&us) && (1..=6).contains(&precision) => {
+                let digits = format!("{:06}", us);
+                res.push('.');
+                res.push_str(&digits[..precision as usize]);
+            },
+            _ => return None
+        },
+        _ => return None
+    }
+    Some(res)
+}
+
+fn naive_datetime(fields: &[(&str, &Term)]) -> Option<String> {
+    Some(format!("{}T{}", date(fields)?, time(fields)?))
+}
+
+fn datetime(fields: &[(&str, &Term)]) -> Option<String> {
+    let offset = int(fields, "utc_offset")?.checked_add(int(fields, "std_offset")?)?;
+    let zone = text(fields, "time_zone")??;
+    let mut res = naive_datetime(fields)?;
+    if zone == "Etc/UTC" && offset == 0 {
+        res.push('Z');
+    } else if offset.unsigned_abs() < 86400 {
+        let sign = if offset < 0 { '-' } else { '+' };
+        let _ = write!(res, "{}{:02}:{:02}", sign, offset.unsigned_abs() / 3600, offset.unsigned_abs() / 60 % 60);
+    } else {
+        return None;
+    }
+    Some(res)
+}
+
+// `Decimal.to_string(d, :normal)`, in exponent form when the exponent would
+// spell out more than a thousand zeros.
+fn decimal(fields: &[(&str, &Term)]) -> Option<String> {
+    let sign = match int(fields, "sign")? {
+        1 => "",
+        -1 => "-",
+        _ => return None
+    };
+    let exp = int(fields, "exp")?;
+    let coef = match get(fields, "coef")? {
+        Term::Int(v) if *v >= 0 => v.to_string(),
+        Term::BigInt(ref v) if v.sign() != Sign::Minus => v.to_string(),
+        Term::Atom(ref name) if name == "inf" => return Some(format!("{}Infinity", sign)),
+        Term::Atom(ref name) if name == "NaN" => return Some("NaN".to_string()),
+        _ => return None
+    };
+    let digits = coef.len() as i64;
+    let res = if !(-1000..=1000).contains(&exp) {
+        format!("{}E{}", coef, exp)
+    } else if exp >= 0 {
+        format!("{}{}", coef, "0".repeat(exp as usize))
+    } else if digits + exp > 0 {
+        let point = (digits + exp) as usize;
+        format!("{}.{}", &coef[..point], &coef[point..])
+    } else {
+        format!("0.{}{}", "0".repeat((-(digits + exp)) as usize), coef)
+    };
+    Some(format!("{}{}", sign, res))
+}
+
+// `URI.to_string`, leaving out the port when it is the scheme's default.
+fn uri(fields: &[(&str, &Term)]) -> Option<String> {
+    let scheme = text(fields, "scheme")?;
+    let userinfo = text(fields, "userinfo")?;
+    let host = text(fields, "host")?;
+    let port = match get(fields, "port")? {
+        Term::Int(port) => Some(*port),
+        Term::Atom(ref name) if name == "nil" => None,
+        _ => return None
+    };
+    let mut res = String::new();
+    if let Some(scheme) = scheme {
+        res.push_str(scheme);
+        res.push(':');
+    }
+    if let Some(host) = host {
+        res.push_str("//");
+        if let Some(userinfo) = userinfo {
+            res.push_str(userinfo);
+            res.push('@');
+        }
+        if host.contains(':') {
+            let _ = write!(res, "[{}]", host);
+        } else {
+            res.push_str(host);
+        }
+        if let Some(port) = port.filter(|&port| Some(port) != scheme.and_then(default_port)) {
+            let _ = write!(res, ":{}", port);
+        }
+    }
+    for (key, prefix) in [("path", ""), ("query", "?"), ("fragment", "#")] {
+        if let Some(part) = text(fields, key)? {
+            res.push_str(prefix);
+            res.push_str(part);
+        }
+    }
+    Some(res)
+}
+
+fn default_port(scheme: &str) -> Option<i32> {
+    match scheme {
+        "ftp" => Some(21),
+        "sftp" => Some(22),
+        "tftp" => Some(69),
+        "http" | "ws" => Some(80),
+        "ldap" => Some(389),
+        "https" | "wss" => Some(443),
+        _ => None
+    }
+}
+
+// Ranges from before steps existed count up or down by one.
+fn range(fields: &[(&str, &Term)]) -> Option<(i64, i64, i64)> {
+    let first = int(fields, "first")?;
+    let last = int(fields, "last")?;
+    let step = match get(fields, "step") {
+        Some(_) => int(fields, "step")?,
+        None if first <= last => 1,
+        None => -1
+    };
+    Some((first, last, step))
+}
+
+// Renders whole terms as `semantic_json` does, indented when `pretty` is set.
+pub struct SemanticRenderer {
+    json: JsonOptions,
+    options: SemanticOptions,
+    pretty: Option<PrettyOptions>
+}
+
+impl Render for SemanticRenderer {
+    fn render(&self, term: &Term) -> Result<String, ParseError> {
+        Ok(match self.pretty {
+            Some(pretty) => {
+                let mut c = JsonComposer::with_writer(self.json, PrettyWriter::new(pretty));
+                compose_semantic(term, &mut c, &self.options);
+                c.into_writer().into_result()
+            },
+            None => semantic_json(term, self.json, &self.options)
+        })
+    }
+}
+
+pub type SemanticComposer = RenderComposer<SemanticRenderer>;
+
+impl SemanticComposer {
+    pub fn with_options(json: JsonOptions, options: SemanticOptions, pretty: Option<PrettyOptions>) -> SemanticComposer {
+        RenderComposer::new(SemanticRenderer{ json, options, pretty })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{semantic_json, SemanticOptions};
+    use crate::json_composer::JsonOptions;
+    use crate::parse_erlang_term;
+    use crate::parser::Limits;
+
+    fn structs(text: &str) -> String {
+        let term = parse_erlang_term(text, &Limits::default()).unwrap();
+        let options = SemanticOptions{ structs: true, ..SemanticOptions::default() };
+        semantic_json(&term, JsonOptions::default(), &options)
+    }
+
+    // An Elixir struct of `module` with the given fields.
+    fn elixir(module: &str, fields: &str) -> String {
+        structs(&format!("#{{'__struct__' => 'Elixir.{}', {}}}", module, fields))
+    }
+
+    const ISO: &str = "calendar => 'Elixir.Calendar.ISO'";
+
+    #[test]
+    fn dates() {
+        assert_eq!(elixir("Date", &format!("{}, year => 2024, month => 2, day => 29", ISO)), r#"{"date":"2024-02-29"}"#);
+        assert_eq!(elixir("Date", &format!("{}, year => -5, month => 1, day => 1", ISO)), r#"{"date":"-0005-01-01"}"#);
+        assert_eq!(elixir("Date", "year => 1, month => 12, day => 31"), r#"{"date":"0001-12-31"}"#);
+    }
+
+    #[test]
+    fn times() {
+        let time = |us| elixir("Time", &format!("{}, hour => 23, minute => 5, second => 9, microsecond => {}", ISO, us));
+        assert_eq!(time("{0, 0}"), r#"{"time":"23:05:09"}"#);
+        assert_eq!(time("{120000, 3}"), r#"{"time":"23:05:09.120"}"#);
+        assert_eq!(time("{7, 6}"), r#"{"time":"23:05:09.000007"}"#);
+    }
+
+    #[test]
+    fn datetimes() {
+        let fields = format!("{}, year => 2000, month => 1, day => 2, hour => 3, minute => 4, second => 5, \
+            microsecond => {{0, 0}}", ISO);
+        assert_eq!(elixir("NaiveDateTime", &fields), r#"{"naive_datetime":"2000-01-02T03:04:05"}"#);
+        let zoned = |zone: &str, utc: i32, std: i32| elixir("DateTime", &format!(
+            "{}, time_zone => <<\"{}\">>, utc_offset => {}, std_offset => {}, zone_abbr => <<\"X\">>",
+            fields, zone, utc, std));
+        assert_eq!(zoned("Etc/UTC", 0, 0), r#"{"datetime":"2000-01-02T03:04:05Z"}"#);
+        assert_eq!(zoned("Europe/Berlin", 3600, 3600), r#"{"datetime":"2000-01-02T03:04:05+02:00"}"#);
+        assert_eq!(zoned("America/St_Johns", -12600, 0), r#"{"datetime":"2000-01-02T03:04:05-03:30"}"#);
+        assert_eq!(zoned("Etc/GMT", 0, 0), r#"{"datetime":"2000-01-02T03:04:05+00:00"}"#);
+    }
+
+    #[test]
+    fn decimals() {
+        let decimal = |sign: i32, coef: &str, exp: i32| elixir("Decimal", &format!("sign => {}, coef => {}, exp => {}", sign, coef, exp));
+        assert_eq!(decimal(1, "12345", -2), r#"{"decimal":"123.45"}"#);
+        assert_eq!(decimal(-1, "5", -3), r#"{"decimal":"-0.005"}"#);
+        assert_eq!(decimal(1, "12", 3), r#"{"decimal":"12000"}"#);
+        assert_eq!(decimal(1, "0", 0), r#"{"decimal":"0"}"#);
+        assert_eq!(decimal(1, "123456789012345678901234567890", -30), r#"{"decimal":"0.123456789012345678901234567890"}"#);
+        assert_eq!(decimal(1, "7", 2000), r#"{"decimal":"7E2000"}"#);
+        assert_eq!(decimal(-1, "inf", 0), r#"{"decimal":"-Infinity"}"#);
+        assert_eq!(decimal(1, "'NaN'", 0), r#"{"decimal":"NaN"}"#);
+    }
+
+    #[test]
+    fn uris() {
+        let uri = |scheme: &str, host: &str, port: &str, rest: &str| elixir("URI", &format!(
+            "scheme => {}, userinfo => nil, host => {}, port => {}, path => {}, query => nil, fragment => nil",
+            scheme, host, port, rest));
+        assert_eq!(uri("<<\"https\">>", "<<\"example.com\">>", "443", "<<\"/a\">>"), r#"{"uri":"https://example.com/a"}"#);
+        assert_eq!(uri("<<\"http\">>", "<<\"::1\">>", "8080", "nil"), r#"{"uri":"http://[::1]:8080"}"#);
+        assert_eq!(uri("<<\"mailto\">>", "nil", "nil", "<<\"a@b\">>"), r#"{"uri":"mailto:a@b"}"#);
+        let full = elixir("URI", "scheme => <<\"ftp\">>, userinfo => <<\"u:p\">>, host => <<\"h\">>, port => 21, \
+            path => <<\"/\">>, query => <<\"q=1\">>, fragment => <<\"f\">>");
+        assert_eq!(full, r#"{"uri":"ftp://u:p@h/?q=1#f"}"#);
+    }
+
+    #[test]
+    fn mapsets_and_ranges() {
+        assert_eq!(elixir("MapSet", "map => #{1 => [], a => []}, version => 2"),
+            r#"{"mapset":[{"int":1},{"atom":"a"}]}"#);
+        assert_eq!(elixir("Range", "first => 1, last => 10, step => 3"), r#"{"range":{"first":1,"last":10,"step":3}}"#);
+        assert_eq!(elixir("Range", "first => 5, last => 1"), r#"{"range":{"first":5,"last":1,"step":-1}}"#);
+    }
+
+    #[test]
+    fn fallbacks() {
+        // Out of range or of another calendar, the struct's fields.
+        let bad_month = elixir("Date", &format!("{}, year => 2024, month => 13, day => 1", ISO));
+        assert!(bad_month.starts_with(r#"{"struct":"Date","fields":{"#), "{}", bad_month);
+        let other = elixir("Date", "calendar => 'Elixir.Cldr.Calendar', year => 2024, month => 1, day => 1");
+        assert!(other.starts_with(r#"{"struct":"Date","#), "{}", other);
+        let bad_us = elixir("Time", &format!("{}, hour => 1, minute => 2, second => 3, microsecond => {{5, 9}}", ISO));
+        assert!(bad_us.starts_with(r#"{"struct":"Time","#), "{}", bad_us);
+        assert!(elixir("Decimal", "sign => 0, coef => 1, exp => 0").starts_with(r#"{"struct":"Decimal","#));
+        assert!(elixir("URI", "scheme => 1").starts_with(r#"{"struct":"URI","#));
+        assert!(elixir("MapSet", "map => []").starts_with(r#"{"struct":"MapSet","#));
+        assert!(elixir("Range", "first => a, last => 1").starts_with(r#"{"struct":"Range","#));
+        // Extremes that must not overflow.
+        let min = i64::MIN;
+        assert!(elixir("Date", &format!("{}, year => {}, month => 1, day => 1", ISO, min)).starts_with(r#"{"struct":"Date","#));
+        assert!(elixir("Date", &format!("{}, year => 10000, month => 1, day => 1", ISO)).starts_with(r#"{"struct":"Date","#));
+        let offset = |utc: i64| elixir("DateTime", &format!("{}, year => 2024, month => 1, day => 1, hour => 0, minute => 0, \
+            second => 0, microsecond => {{0, 0}}, time_zone => <<\"X\">>, utc_offset => {}, std_offset => 0", ISO, utc));
+        assert!(offset(min).starts_with(r#"{"struct":"DateTime","#));
+        assert!(offset(86400).starts_with(r#"{"struct":"DateTime","#));
+        assert_eq!(elixir("Decimal", &format!("sign => 1, coef => 12, exp => {}", min)), format!(r#"{{"decimal":"12E{}"}}"#, min));
+        assert_eq!(elixir("Decimal", &format!("sign => -1, coef => 1, exp => {}", i64::MAX)),
+            format!(r#"{{"decimal":"-1E{}"}}"#, i64::MAX));
+        assert_eq!(elixir("User", "name => <<\"jo\">>"), r#"{"struct":"User","fields":{"name":{"binary":"am8="}}}"#);
+        // Not a struct at all.
+        assert_eq!(structs("#{'__struct__' => 1}"), r#"{"map":[{"key":{"atom":"__struct__"},"val":{"int":1}}]}"#);
+        assert_eq!(structs("#{<<\"a\">> => 1, '__struct__' => 'Elixir.X'}").get(..7), Some(r#"{"map":"#));
+    }
+
+    #[test]
+    fn nested() {
+        let text = format!("[#{{'__struct__' => 'Elixir.Wrap', date => #{{'__struct__' => 'Elixir.Date', {}, \
+            year => 1999, month => 12, day => 31}}}}]", ISO);
+        assert_eq!(structs(&text), r#"{"list":[{"struct":"Wrap","fields":{"date":{"date":"1999-12-31"}}}]}"#);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::VecDeque;
+use num_bigint::BigInt;
+
+use crate::parser::{ElemCompose, Pid, Port, Reference, Fun, NewFun, Export};
+
+// Hands out finished values as separate records, each composed by a fresh
+// composer from `new_composer`. With `split` set the elements of a root list
+// or tuple become records as soon as they are decoded, otherwise every
+// complete term is one record.
+pub struct SplitComposer<C, F> {
+    new_composer: F,
+    current: C,
+    records: VecDeque<C>,
+    split: bool,
+    // Open containers, the split root included.
+    depth: usize,
+    split_root: bool
+}
+
+impl<C: ElemCompose, F: FnMut() -> C> SplitComposer<C, F> {
+    pub fn new(split: bool, mut new_composer: F) -> SplitComposer<C, F> {
+        let current = new_composer();
+        SplitComposer{
+            new_composer,
+            current,
+            records: VecDeque::new(),
+            split,
+            depth: 0,
+            split_root: false
+        }
+    }
+
+    pub fn next_record(&mut self) -> Option<C> {
+        self.records.pop_front()
+    }
+
+    fn record_depth(&self) -> usize {
+        if self.split_root { 1 } else { 0 }
+    }
+
+    fn value_done(&mut self) {
+        if self.depth == self.record_depth() {
+            let record = std::mem::replace(&mut self.current, (self.new_composer)());
+            self.records.push_back(record);
+        }
+    }
+
+    // True when the container being opened is the root one to split.
+    fn begin(&mut self) -> bool {
+        if self.split && self.depth == 0 {
+            self.split_root = true;
+            self.depth = 1;
+            return true;
+        }
+        self.depth += 1;
+        false
+    }
+
+    // True when the container being closed is the split root.
+    fn end(&mut self) -> bool {
+        self.depth -= 1;
+        if self.split_root && self.depth == 0 {
+            self.split_root = false;
+            return true;
+        }
+        false
+    }
+}
+
+impl<C: ElemCompose, F: FnMut() -> C> ElemCompose for SplitComposer<C, F> {
+    fn begin_list(&mut self, len: u32) {
+        if !self.begin() {
+            self.current.begin_list(len);
+        }
+    }
+    fn list_tail(&mut self) {
+        // The tail of a split root list becomes one more record.
+        if !(self.split_root && self.depth == 1) {
+            self.current.list_tail();
+        }
+    }
+    fn end_list(&mut self) {
+        if !self.end() {
+            self.current.end_list();
+            self.value_done();
+        }
+    }
+    fn begin_tuple(&mut self, arity: u32) {
+        if !self.begin() {
+            self.current.begin_tuple(arity);
+        }
+    }
+    fn end_tuple(&mut self) {
+        if !self.end() {
+            self.current.end_tuple();
+            self.value_done();
+        }
+    }
+    fn begin_map(&mut self, len: u32) {
+        // Maps are never split, only lists and tuples.
+        self.depth += 1;
+        self.current.begin_map(len);
+    }
+    fn map_key(&mut self) {
+        self.current.map_key();
+    }
+    fn map_value(&mut self) {
+        self.current.map_value();
+    }
+    fn end_map(&mut self) {
+        self.depth -= 1;
+        self.current.end_map();
+        self.value_done();
+    }
+    fn atom(&mut self, name: &str) {
+        self.current.atom(name);
+        self.value_done();
+    }
+    fn int(&mut self, v: i32) {
+        self.current.int(v);
+        self.value_done();
+    }
+    fn bigint(&mut self, v: &BigInt) {
+        self.current.bigint(v);
+        self.value_done();
+    }
+    fn float(&mut self, v: f64) {
+        self.current.float(v);
+        self.value_done();
+    }
+    fn string(&mut self, chars: &[u8]) {
+        // A root STRING_EXT is a list of small integers like any other.
+        if self.split && self.depth == 0 {
+            for &ch in chars {
+                self.current.int(ch as i32);
+                self.value_done();
+            }
+            return;
+        }
+        self.current.string(chars);
+        self.value_done();
+    }
+    fn binary(&mut self, data: &[u8]) {
+        self.current.binary(data);
+        self.value_done();
+    }
+    fn bit_binary(&mut self, data: &[u8], bits: u8) {
+        self.current.bit_binary(data, bits);
+        self.value_done();
+    }
+    fn pid(&mut self, pid: &Pid) {
+        self.current.pid(pid);
+        self.value_done();
+    }
+    fn port(&mut self, port: &Port) {
+        self.current.port(port);
+        self.value_done();
+    }
+    fn reference(&mut self, reference: &Reference) {
+        self.current.reference(reference);
+        self.value_done();
+    }
+    fn atom_cache_ref(&mut self, index: u8) {
+        self.current.atom_cache_ref(index);
+        self.value_done();
+    }
+    fn fun(&mut self, fun: &Fun) {
+        self.current.fun(fun);
+        self.value_done();
+    }
+    fn new_fun(&mut self, fun: &NewFun) {
+        self.current.new_fun(fun);
+        self.value_done();
+    }
+    fn export(&mut self, export: &Export) {
+        self.current.export(export);
+        self.value_done();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SplitComposer;
+    use crate::convert::{convert, ConvertOptions, InputFormat};
+    use crate::etf_composer::encode;
+    use crate::json_composer::JsonComposer;
+    use crate::packet::Packet;
+    use crate::parse_erlang_term;
+    use crate::parser::{ElemCompose, Limits};
+
+    type Split = SplitComposer<JsonComposer, fn() -> JsonComposer>;
+
+    fn new(split: bool) -> Split {
+        SplitComposer::new(split, JsonComposer::new)
+    }
+
+    fn records(c: &mut Split) -> Vec<String> {
+        std::iter::from_fn(|| c.next_record()).map(|r| r.result().to_string()).collect()
+    }
+
+    fn split(text: &str) -> Vec<String> {
+        let mut c = new(true);
+        parse_erlang_term(text, &Limits::default()).unwrap().compose(&mut c);
+        records(&mut c)
+    }
+
+    fn etf(text: &str) -> Vec<u8> {
+        encode(&parse_erlang_term(text, &Limits::default()).unwrap()).unwrap()
+    }
+
+    fn run(input: &[u8], options: &ConvertOptions) -> String {
+        let mut out = vec![];
+        convert(&mut &input[..], options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn whole_terms() {
+        let mut c = new(false);
+        parse_erlang_term("[1, {a}]", &Limits::default()).unwrap().compose(&mut c);
+        c.int(2);
+        assert_eq!(records(&mut c), [r#"{"list":[{"int":1},{"tuple":[{"atom":"a"}]}]}"#, r#"{"int":2}"#]);
+        assert_eq!(c.next_record().map(|r| r.result().to_string()), None);
+    }
+
+    #[test]
+    fn root_elements() {
+        assert_eq!(split("[1, a]"), [r#"{"int":1}"#, r#"{"atom":"a"}"#]);
+        assert_eq!(split("{1, [2]}"), [r#"{"int":1}"#, r#"{"list":[{"int":2}]}"#]);
+        // The tail of an improper list is one more record.
+        assert_eq!(split("[1 | b]"), [r#"{"int":1}"#, r#"{"atom":"b"}"#]);
+        assert!(split("[]").is_empty());
+        // `term_to_binary([1, 2, 3])` is a STRING_EXT.
+        let mut c = new(true);
+        crate::decode(&[131, 107, 0, 3, 1, 2, 3], &mut c, &Limits::default()).unwrap();
+        assert_eq!(records(&mut c), [r#"{"int":1}"#, r#"{"int":2}"#, r#"{"int":3}"#]);
+        let mut c = new(true);
+        crate::decode(&[131, 108, 0, 0, 0, 1, 107, 0, 2, 1, 2, 106], &mut c, &Limits::default()).unwrap();
+        assert_eq!(records(&mut c).len(), 1);
+        assert!(split("{}").is_empty());
+        // Scalars and maps are records of their own.
+        assert_eq!(split("7"), [r#"{"int":7}"#]);
+        assert_eq!(split("#{a => [1]}").len(), 1);
+    }
+
+    #[test]
+    fn only_the_root_splits() {
+        assert_eq!(split("[[1, 2], {3, [4]}, #{k => {5}}]"), [
+            r#"{"list":[{"int":1},{"int":2}]}"#,
+            r#"{"tuple":[{"int":3},{"list":[{"int":4}]}]}"#,
+            r#"{"map":[{"key":{"atom":"k"},"val":{"tuple":[{"int":5}]}}]}"#
+        ]);
+        assert_eq!(split("[[[]]]"), [r#"{"list":[{"list":[]}]}"#]);
+    }
+
+    #[test]
+    fn records_before_the_root_ends() {
+        let mut c = new(true);
+        c.begin_list(3);
+        c.int(1);
+        assert_eq!(records(&mut c), [r#"{"int":1}"#]);
+        c.begin_tuple(1);
+        c.atom("a");
+        assert!(records(&mut c).is_empty());
+        c.end_tuple();
+        assert_eq!(records(&mut c), [r#"{"tuple":[{"atom":"a"}]}"#]);
+        c.int(3);
+        c.end_list();
+        assert_eq!(records(&mut c), [r#"{"int":3}"#]);
+        // The next term splits again.
+        c.begin_tuple(1);
+        c.int(4);
+        c.end_tuple();
+        assert_eq!(records(&mut c), [r#"{"int":4}"#]);
+    }
+
+    #[test]
+    fn split_with_ndjson() {
+        let mut input = etf("[1, 2]");
+        input.extend(etf("{a}"));
+        input.extend(etf("3"));
+        let options = ConvertOptions{ split: true, ndjson: true, ..ConvertOptions::default() };
+        assert_eq!(run(&input, &options), "{\"int\":1}\n{\"int\":2}\n{\"atom\":\"a\"}\n{\"int\":3}\n");
+        let options = ConvertOptions{ split: true, input_format: InputFormat::Erlang, ..ConvertOptions::default() };
+        assert_eq!(run(b"[1]. [2, 3].", &options), "{\"int\":1}\n{\"int\":2}\n{\"int\":3}\n");
+    }
+
+    #[test]
+    fn split_with_packets() {
+        let mut input = vec![];
+        for text in ["[1, 2]", "[]", "{a}"] {
+            let bytes = etf(text);
+            input.push(bytes.len() as u8);
+            input.extend(bytes);
+        }
+        let options = ConvertOptions{ split: true, packet: Some(Packet::One), ..ConvertOptions::default() };
+        assert_eq!(run(&input, &options), "{\"int\":1}\n{\"int\":2}\n{\"atom\":\"a\"}\n");
+        // Every record is a frame of its own.
+        let options = ConvertOptions{ out_packet: Some(Packet::One), ..options };
+        assert_eq!(run(&input, &options), "\x09{\"int\":1}\x09{\"int\":2}\x0c{\"atom\":\"a\"}");
+    }
+}
@@ -0,0 +1,364 @@
+use num_bigint::BigInt;
+
+use crate::parser::{ElemCompose, ParseError, Pid, Port, Reference, Fun, NewFun, Export};
+
+// A decoded term held in memory, one variant per composer callback.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Atom(String),
+    Int(i32),
+    BigInt(BigInt),
+    Float(f64),
+    // STRING_EXT, a list of bytes.
+    String(Vec<u8>),
+    Binary(Vec<u8>),
+    BitBinary(Vec<u8>, u8),
+    // Elements and the tail of an improper list, `[]` is an empty list.
+    List(Vec<Term>, Option<Box<Term>>),
+    Tuple(Vec<Term>),
+    Map(Vec<(Term, Term)>),
+    Pid(Pid),
+    Port(Port),
+    Reference(Reference),
+    AtomCacheRef(u8),
+    Fun(Fun),
+    NewFun(NewFun),
+    Export(Export)
+}
+
+enum Work<'a> {
+    Term(&'a Term),
+    ListTail,
+    EndList,
+    EndTuple,
+    MapKey,
+    MapValue,
+    EndMap
+}
+
+impl Term {
+    pub fn nil() -> Term {
+        Term::List(vec![], None)
+    }
+
+    pub fn atom(name: &str) -> Term {
+        Term::Atom(name.to_string())
+    }
+
+    pub fn as_atom(&self) -> Option<&str> {
+        match *self {
+            Term::Atom(ref name) => Some(name),
+            _ => None
+        }
+    }
+
+    // Replays the term into `c` in the order the decoder would.
+    pub fn compose<C: ElemCompose + ?Sized>(&self, c: &mut C) {
+        let mut work = vec![Work::Term(self)];
+        while let Some(next) = work.pop() {
+            let term = match next {
+                Work::Term(term) => term,
+                Work::ListTail => { c.list_tail(); continue; },
+                Work::EndList => { c.end_list(); continue; },
+                Work::EndTuple => { c.end_tuple(); continue; },
+                Work::MapKey => { c.map_key(); continue; },
+                Work::MapValue => { c.map_value(); continue; },
+                Work::EndMap => { c.end_map(); continue; }
+            };
+            match *term {
+                Term::List(ref items, ref tail) => {
+                    c.begin_list(items.len() as u32);
+                    work.push(Work::EndList);
+                    if let Some(ref tail) = *tail {
+                        work.push(Work::Term(tail));
+                        work.push(Work::ListTail);
+                    }
+                    work.extend(items.iter().rev().map(Work::Term));
+                },
+                Term::Tuple(ref items) => {
+                    c.begin_tuple(items.len() as u32);
+                    work.push(Work::EndTuple);
+                    work.extend(items.iter().rev().map(Work::Term));
+                },
+                Term::Map(ref pairs) => {
+                    c.begin_map(pairs.len() as u32);
+                    work.push(Work::EndMap);
+                    for (key, value) in pairs.iter().rev() {
+                        work.push(Work::Term(value));
+                        work.push(Work::MapValue);
+                        work.push(Work::Term(key));
+                        work.push(Work::MapKey);
+                    }
+                },
+                Term::Atom(ref name) => c.atom(name),
+                Term::Int(v) => c.int(v),
+                Term::BigInt(ref v) => c.bigint(v),
+                Term::Float(v) => c.float(v),
+                Term::String(ref chars) => c.string(chars),
+                Term::Binary(ref data) => c.binary(data),
+                Term::BitBinary(ref data, bits) => c.bit_binary(data, bits),
+                Term::Pid(ref pid) => c.pid(pid),
+                Term::Port(ref port) => c.port(port),
+                Term::Reference(ref reference) => c.reference(reference),
+                Term::AtomCacheRef(index) => c.atom_cache_ref(index),
+                Term::Fun(ref fun) => c.fun(fun),
+                Term::NewFun(ref fun) => c.new_fun(fun),
+                Term::Export(ref export) => c.export(export)
+            }
+        }
+    }
+
+    fn take_children(&mut self, into: &mut Vec<Term>) {
+        match *self {
+            Term::List(ref mut items, ref mut tail) => {
+                into.append(items);
+                if let Some(tail) = tail.take() {
+                    into.push(*tail);
+                }
+            },
+            Term::Tuple(ref mut items) => into.append(items),
+            Term::Map(ref mut pairs) => {
+                for (key, value) in pairs.drain(..) {
+                    into.push(key);
+                    into.push(value);
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+// Dropping children one level at a time keeps deeply nested terms from
+// overflowing the stack.
+impl Drop for Term {
+    fn drop(&mut self) {
+        let mut children = vec![];
+        self.take_children(&mut children);
+        while let Some(mut child) = children.pop() {
+            child.take_children(&mut children);
+        }
+    }
+}
+
+enum Open {
+    List(Vec<Term>, bool),
+    Tuple(Vec<Term>),
+    Map(Vec<(Term, Term)>, Option<Term>)
+}
+
+// Collects composer callbacks into a `Term`.
+pub struct TermBuilder {
+    stack: Vec<Open>,
+    result: Option<Term>
+}
+
+impl TermBuilder {
+    pub fn new() -> TermBuilder {
+        TermBuilder{
+            stack: vec![],
+            result: None
+        }
+    }
+
+    pub fn term(&self) -> Option<&Term> {
+        self.result.as_ref()
+    }
+
+    pub fn into_term(self) -> Option<Term> {
+        self.result
+    }
+
+    fn push(&mut self, term: Term) {
+        match self.stack.last_mut() {
+            Some(Open::List(items, _)) | Some(Open::Tuple(items)) => items.push(term),
+            Some(Open::Map(pairs, key)) => match key.take() {
+                Some(key) => pairs.push((key, term)),
+                None => *key = Some(term)
+            },
+            None => self.result = Some(term)
+        }
+    }
+}
+
+impl Default for TermBuilder {
+    fn default() -> Self {
+        TermBuilder::new()
+    }
+}
+
+impl ElemCompose for TermBuilder {
+    fn begin_list(&mut self, len: u32) {
+        self.stack.push(Open::List(Vec::with_capacity(len.min(1024) as usize), false));
+    }
+    fn list_tail(&mut self) {
+        if let Some(Open::List(_, tail)) = self.stack.last_mut() {
+            *tail = true;
+        }
+    }
+    fn end_list(&mut self) {
+        if let Some(Open::List(mut items, tail)) = self.stack.pop() {
+            let tail = if tail { items.pop().map(Box::new) } else { None };
+            self.push(Term::List(items, tail));
+        }
+    }
+    fn begin_tuple(&mut self, arity: u32) {
+        self.stack.push(Open::Tuple(Vec::with_capacity(arity.min(1024) as usize)));
+    }
+    fn end_tuple(&mut self) {
+        if let Some(Open::Tuple(items)) = self.stack.pop() {
+            self.push(Term::Tuple(items));
+        }
+    }
+    fn begin_map(&mut self, len: u32) {
+        self.stack.push(Open::Map(Vec::with_capacity(len.min(1024) as usize), None));
+    }
+    fn map_key(&mut self) {}
+    fn map_value(&mut self) {}
+    fn end_map(&mut self) {
+        if let Some(Open::Map(pairs, _)) = self.stack.pop() {
+            self.push(Term::Map(pairs));
+        }
+    }
+    fn atom(&mut self, name: &str) {
+        self.push(Term::Atom(name.to_string()));
+    }
+    fn int(&mut self, v: i32) {
+        self.push(Term::Int(v));
+    }
+    fn bigint(&mut self, v: &BigInt) {
+        self.push(Term::BigInt(v.clone()));
+    }
+    fn float(&mut self, v: f64) {
+        self.push(Term::Float(v));
+    }
+    fn string(&mut self, chars: &[u8]) {
+        self.push(Term::String(chars.to_vec()));
+    }
+    fn binary(&mut self, data: &[u8]) {
+        self.push(Term::Binary(data.to_vec()));
+    }
+    fn bit_binary(&mut self, data: &[u8], bits: u8) {
+        self.push(Term::BitBinary(data.to_vec(), bits));
+    }
+    fn pid(&mut self, pid: &Pid) {
+        self.push(Term::Pid(pid.clone()));
+    }
+    fn port(&mut self, port: &Port) {
+        self.push(Term::Port(port.clone()));
+    }
+    fn reference(&mut self, reference: &Reference) {
+        self.push(Term::Reference(reference.clone()));
+    }
+    fn atom_cache_ref(&mut self, index: u8) {
+        self.push(Term::AtomCacheRef(index));
+    }
+    fn fun(&mut self, fun: &Fun) {
+        self.push(Term::Fun(fun.clone()));
+    }
+    fn new_fun(&mut self, fun: &NewFun) {
+        self.push(Term::NewFun(fun.clone()));
+    }
+    fn export(&mut self, export: &Export) {
+        self.push(Term::Export(export.clone()));
+    }
+}
+
+// Turns a whole term into output text, for output that depends on more than
+// one composer callback at a time.
+pub trait Render {
+    fn render(&self, term: &Term) -> Result<String, ParseError>;
+}
+
+// Collects each term with a `TermBuilder` and renders it once complete.
+pub struct RenderComposer<R> {
+    builder: TermBuilder,
+    renderer: R
+}
+
+impl<R: Render> RenderComposer<R> {
+    pub fn new(renderer: R) -> RenderComposer<R> {
+        RenderComposer{
+            builder: TermBuilder::new(),
+            renderer
+        }
+    }
+
+    pub fn result(&self) -> Result<String, ParseError> {
+        match self.builder.term() {
+            Some(term) => self.renderer.render(term),
+            None => Ok(String::new())
+        }
+    }
+}
+
+impl<R> ElemCompose for RenderComposer<R> {
+    fn begin_list(&mut self, len: u32) {
+        self.builder.begin_list(len);
+    }
+    fn list_tail(&mut self) {
+        self.builder.list_tail();
+    }
+    fn end_list(&mut self) {
+        self.builder.end_list();
+    }
+    fn begin_tuple(&mut self, arity: u32) {
+        self.builder.begin_tuple(arity);
+    }
+    fn end_tuple(&mut self) {
+        self.builder.end_tuple();
+    }
+    fn begin_map(&mut self, len: u32) {
+        self.builder.begin_map(len);
+    }
+    fn map_key(&mut self) {
+        self.builder.map_key();
+    }
+    fn map_value(&mut self) {
+        self.builder.map_value();
+    }
+    fn end_map(&mut self) {
+        self.builder.end_map();
+    }
+    fn atom(&mut self, name: &str) {
+        self.builder.atom(name);
+    }
+    fn int(&mut self, v: i32) {
+        self.builder.int(v);
+    }
+    fn bigint(&mut self, v: &BigInt) {
+        self.builder.bigint(v);
+    }
+    fn float(&mut self, v: f64) {
+        self.builder.float(v);
+    }
+    fn string(&mut self, chars: &[u8]) {
+        self.builder.string(chars);
+    }
+    fn binary(&mut self, data: &[u8]) {
+        self.builder.binary(data);
+    }
+    fn bit_binary(&mut self, data: &[u8], bits: u8) {
+        self.builder.bit_binary(data, bits);
+    }
+    fn pid(&mut self, pid: &Pid) {
+        self.builder.pid(pid);
+    }
+    fn port(&mut self, port: &Port) {
+        self.builder.port(port);
+    }
+    fn reference(&mut self, reference: &Reference) {
+        self.builder.reference(reference);
+    }
+    fn atom_cache_ref(&mut self, index: u8) {
+        self.builder.atom_cache_ref(index);
+    }
+    fn fun(&mut self, fun: &Fun) {
+        self.builder.fun(fun);
+    }
+    fn new_fun(&mut self, fun: &NewFun) {
+        self.builder.new_fun(fun);
+    }
+    fn export(&mut self, export: &Export) {
+        self.builder.export(export);
+    }
+}
@@ -0,0 +1,346 @@
+use num_traits::ToPrimitive;
+
+use crate::calendar::{days_in_month, push_utc};
+use crate::parser::PathSegment;
+use crate::term::Term;
+
+// What a time term is read as.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeKind {
+    // `{{Year, Month, Day}, {Hour, Minute, Second}}` from `calendar`.
+    DateTime,
+    // `{MegaSecs, Secs, MicroSecs}` from `os:timestamp()`.
+    Timestamp,
+    // Integer system time in the given unit.
+    Seconds,
+    Millis,
+    Micros,
+    Nanos
+}
+
+// One step of a rule path. `None` indices match any index.
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    List(Option<u32>),
+    ListTail,
+    Tuple(Option<u32>),
+    MapKey(Option<u32>),
+    MapValue(Option<u32>),
+    // The value of a map key, atom or binary, or a field of a known record.
+    Name(String)
+}
+
+// Forces terms at a path to be read as a kind, or with `None` keeps them as
+// they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeRule {
+    path: Vec<Pattern>,
+    kind: Option<TimeKind>
+}
+
+impl TimeRule {
+    // `PATH=KIND`, where PATH is written like the paths of errors, such as
+    // `$[*].tuple[2]` or `$.users[*].created_at`, and KIND is one of
+    // datetime, timestamp, s, ms, us, ns or none. With `split` the path
+    // starts at each record, not at the root list or tuple.
+    pub fn parse(rule: &str) -> Option<TimeRule> {
+        let (path, kind) = rule.rsplit_once('=')?;
+        let kind = match kind {
+            "datetime" => Some(TimeKind::DateTime),
+            "timestamp" => Some(TimeKind::Timestamp),
+            "s" => Some(TimeKind::Seconds),
+            "ms" => Some(TimeKind::Millis),
+            "us" => Some(TimeKind::Micros),
+            "ns" => Some(TimeKind::Nanos),
+            "none" => None,
+            _ => return None
+        };
+        let mut rest = path.strip_prefix('$')?;
+        let mut patterns = vec![];
+        while !rest.is_empty() {
+            let pattern = if let Some(after) = rest.strip_prefix('[') {
+                let (index, after) = index(after)?;
+                rest = after;
+                Pattern::List(index)
+            } else if let Some(after) = rest.strip_prefix(".tuple[") {
+                let (index, after) = index(after)?;
+                rest = after;
+                Pattern::Tuple(index)
+            } else if let Some(after) = rest.strip_prefix(".map[") {
+                let (index, after) = index(after)?;
+                if let Some(after) = after.strip_prefix(".key") {
+                    rest = after;
+                    Pattern::MapKey(index)
+                } else {
+                    rest = after.strip_prefix(".val")?;
+                    Pattern::MapValue(index)
+                }
+            } else if let Some(after) = rest.strip_prefix(".tail").filter(|after| after.is_empty() || after.starts_with(['.', '['])) {
+                rest = after;
+                Pattern::ListTail
+            } else {
+                let after = rest.strip_prefix('.')?;
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return None;
+                }
+                rest = &after[end..];
+                Pattern::Name(after[..end].to_string())
+            };
+            patterns.push(pattern);
+        }
+        Some(TimeRule{ path: patterns, kind })
+    }
+
+    fn matches(&self, path: &[(PathSegment, Option<&str>)]) -> bool {
+        self.path.len() == path.len() && self.path.iter().zip(path).all(|(pattern, &(segment, name))| {
+            match (pattern, segment) {
+                (Pattern::List(i), PathSegment::List(j))
+                    | (Pattern::Tuple(i), PathSegment::Tuple(j))
+                    | (Pattern::MapKey(i), PathSegment::MapKey(j))
+                    | (Pattern::MapValue(i), PathSegment::MapValue(j)) => i.is_none_or(|i| i == j),
+                (Pattern::ListTail, PathSegment::ListTail) => true,
+                (Pattern::Name(expected), _) => name == Some(expected),
+                _ => false
+            }
+        })
+    }
+}
+
+// `N]` or `*]`.
+fn index(text: &str) -> Option<(Option<u32>, &str)> {
+    let (index, rest) = text.split_once(']')?;
+    match index {
+        "*" => Some((None, rest)),
+        _ => Some((Some(index.parse().ok()?), rest))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeOptions {
+    // Detect calendar datetimes and `os:timestamp()` triples.
+    pub tuples: bool,
+    // Detect integers that can only be system time in some unit.
+    pub integers: bool,
+    // Checked in order, the last matching rule wins.
+    pub rules: Vec<TimeRule>
+}
+
+impl TimeOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.tuples || self.integers || !self.rules.is_empty()
+    }
+
+    // The tag and ISO 8601 text of a time term at `path`. Calendar datetimes
+    // have no zone and come out as `naive_datetime`, the rest as UTC
+    // `datetime`.
+    pub fn convert(&self, term: &Term, path: &[(PathSegment, Option<&str>)]) -> Option<(&'static str, String)> {
+        match self.rules.iter().rev().find(|rule| rule.matches(path)) {
+            Some(rule) => forced(term, rule.kind?),
+            None => self.detect(term)
+        }
+    }
+
+    // Detection sticks to years 2000 to 2099 for system time, 1900 to 2199
+    // for calendar datetimes, so small counters and sizes don't qualify.
+    fn detect(&self, term: &Term) -> Option<(&'static str, String)> {
+        match *term {
+            Term::Tuple(_) if self.tuples => {
+                match datetime(term, 1900, 2199) {
+                    Some(text) => Some(("naive_datetime", text)),
+                    // `{Year, Month, Day}` is a date more likely than a
+                    // timestamp a few seconds into its megasecond.
+                    None if is_date(term) => None,
+                    None => timestamp(term, DETECT_SECS).map(|text| ("datetime", text))
+                }
+            },
+            Term::Int(_) | Term::BigInt(_) if self.integers => {
+                let v = int(term)?;
+                [1, 1_000, 1_000_000, 1_000_000_000].iter()
+                    .find(|&&scale| v / scale >= DETECT_SECS.0 && v / scale < DETECT_SECS.1)
+                    .and_then(|&scale| system_time(v, scale, DETECT_SECS))
+                    .map(|text| ("datetime", text))
+            },
+            _ => None
+        }
+    }
+}
+
+// Seconds from 2000-01-01 up to 2100-01-01.
+const DETECT_SECS: (i64, i64) = (946_684_800, 4_102_444_800);
+// Seconds from 0000-01-01 up to 10000-01-01, what four year digits hold.
+const ANY_SECS: (i64, i64) = (-62_167_219_200, 253_402_300_800);
+
+fn forced(term: &Term, kind: TimeKind) -> Option<(&'static str, String)> {
+    let text = match kind {
+        TimeKind::DateTime => return datetime(term, 0, 9999).map(|text| ("naive_datetime", text)),
+        TimeKind::Timestamp => timestamp(term, ANY_SECS),
+        TimeKind::Seconds => system_time(int(term)?, 1, ANY_SECS),
+        TimeKind::Millis => system_time(int(term)?, 1_000, ANY_SECS),
+        TimeKind::Micros => system_time(int(term)?, 1_000_000, ANY_SECS),
+        TimeKind::Nanos => system_time(int(term)?, 1_000_000_000, ANY_SECS)
+    };
+    text.map(|text| ("datetime", text))
+}
+
+fn int(term: &Term) -> Option<i64> {
+    match *term {
+        Term::Int(v) => Some(v as i64),
+        Term::BigInt(ref v) => v.to_i64(),
+        _ => None
+    }
+}
+
+fn ints<const N: usize>(term: &Term) -> Option<[i64; N]> {
+    match *term {
+        Term::Tuple(ref items) if items.len() == N => {
+            let mut res = [0; N];
+            for (v, item) in res.iter_mut().zip(items) {
+                *v = int(item)?;
+            }
+            Some(res)
+        },
+        _ => None
+    }
+}
+
+fn datetime(term: &Term, min_year: i64, max_year: i64) -> Option<String> {
+    let (date, time) = match *term {
+        Term::Tuple(ref items) if items.len() == 2 => (ints::<3>(&items[0])?, ints::<3>(&items[1])?),
+        _ => return None
+    };
+    let [year, month, day] = date;
+    let [hour, minute, second] = time;
+    let valid = (min_year..=max_year).contains(&year) && (1..=12).contains(&month)
+        && day >= 1 && day <= days_in_month(year, month as u32) as i64
+        && (0..24).contains(&hour) && (0..60).contains(&minute) && (0..60).contains(&second);
+    if !valid {
+        return None;
+    }
+    Some(format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, hour, minute, second))
+}
+
+fn is_date(term: &Term) -> bool {
+    matches!(ints::<3>(term), Some([_, 1..=12, 1..=31]))
+}
+
+fn timestamp(term: &Term, secs: (i64, i64)) -> Option<String> {
+    let [mega, s, micro] = ints::<3>(term)?;
+    if !(0..1_000_000).contains(&s) || !(0..1_000_000).contains(&micro) {
+        return None;
+    }
+    let total = mega.checked_mul(1_000_000)?.checked_add(s)?;
+    if total < secs.0 || total >= secs.1 {
+        return None;
+    }
+    let mut res = String::new();
+    push_utc(&mut res, total, micro as u32);
+    Some(res)
+}
+
+// `v` in units of 1/`scale` seconds.
+fn system_time(v: i64, scale: i64, secs: (i64, i64)) -> Option<String> {
+    let total = v.div_euclid(scale);
+    if total < secs.0 || total >= secs.1 {
+        return None;
+    }
+    let micros = (v.rem_euclid(scale) as i128 * 1_000_000 / scale as i128) as u32;
+    let mut res = String::new();
+    push_utc(&mut res, total, micros);
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TimeKind, TimeOptions, TimeRule};
+    use crate::parse_erlang_term;
+    use crate::parser::{Limits, PathSegment};
+
+    fn detect(options: &TimeOptions, text: &str) -> Option<(&'static str, String)> {
+        let term = parse_erlang_term(text, &Limits::default()).unwrap();
+        options.convert(&term, &[])
+    }
+
+    fn forced(kind: &str, text: &str) -> Option<String> {
+        let options = TimeOptions{ rules: vec![TimeRule::parse(&format!("$={}", kind)).unwrap()], ..TimeOptions::default() };
+        detect(&options, text).map(|(_, text)| text)
+    }
+
+    #[test]
+    fn rules() {
+        let rule = TimeRule::parse("$[*].tuple[2]=ms").unwrap();
+        assert_eq!(rule.kind, Some(TimeKind::Millis));
+        assert!(rule.matches(&[(PathSegment::List(4), None), (PathSegment::Tuple(2), None)]));
+        assert!(!rule.matches(&[(PathSegment::List(4), None), (PathSegment::Tuple(1), None)]));
+        assert!(!rule.matches(&[(PathSegment::List(4), None)]));
+        let rule = TimeRule::parse("$.users[*].created_at=none").unwrap();
+        assert_eq!(rule.kind, None);
+        assert!(rule.matches(&[(PathSegment::MapValue(0), Some("users")), (PathSegment::List(1), None),
+            (PathSegment::Tuple(3), Some("created_at"))]));
+        assert!(TimeRule::parse("$.map[0].key.tail=s").is_some());
+        for bad in ["$=week", "$", "x=s", "$[x]=s", "$.=s", "$.map[0].oops=s", "$[1=s"] {
+            assert_eq!(TimeRule::parse(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn datetimes() {
+        let options = TimeOptions{ tuples: true, ..TimeOptions::default() };
+        assert_eq!(detect(&options, "{{1970,1,1},{0,0,0}}"), Some(("naive_datetime", "1970-01-01T00:00:00".to_string())));
+        assert_eq!(detect(&options, "{{2000,2,29},{23,59,59}}").unwrap().1, "2000-02-29T23:59:59");
+        assert_eq!(detect(&options, "{{1900,2,28},{12,0,0}}").unwrap().1, "1900-02-28T12:00:00");
+        // Detection stops at 2199, a rule goes up to 9999.
+        assert_eq!(detect(&options, "{{9999,12,31},{23,59,59}}"), None);
+        assert_eq!(forced("datetime", "{{9999,12,31},{23,59,59}}").unwrap(), "9999-12-31T23:59:59");
+        assert_eq!(forced("datetime", "{{0,1,1},{0,0,0}}").unwrap(), "0000-01-01T00:00:00");
+        for text in ["{{1900,2,29},{0,0,0}}", "{{2001,2,29},{0,0,0}}", "{{2000,13,1},{0,0,0}}", "{{2000,0,1},{0,0,0}}",
+                "{{2000,4,31},{0,0,0}}", "{{2000,1,0},{0,0,0}}", "{{2000,1,1},{24,0,0}}", "{{2000,1,1},{0,60,0}}",
+                "{{2000,1,1},{0,0,60}}", "{{2000,1,1},{0,0,-1}}", "{{10000,1,1},{0,0,0}}", "{{2000,1,1},{0,0,0.5}}",
+                "{{2000,1,1},{0,0}}"] {
+            assert_eq!(forced("datetime", text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn timestamps() {
+        let options = TimeOptions{ tuples: true, ..TimeOptions::default() };
+        assert_eq!(detect(&options, "{1255,295581,123456}"), Some(("datetime", "2009-10-11T21:13:01.123456Z".to_string())));
+        // Too early to detect, and a date rather than a timestamp.
+        assert_eq!(detect(&options, "{0,0,0}"), None);
+        assert_eq!(detect(&options, "{2000,1,1}"), None);
+        assert_eq!(forced("timestamp", "{0,0,0}").unwrap(), "1970-01-01T00:00:00Z");
+        assert_eq!(forced("timestamp", "{951,782400,0}").unwrap(), "2000-02-29T00:00:00Z");
+        assert_eq!(forced("timestamp", "{-2209,11200,0}").unwrap(), "1900-01-01T00:00:00Z");
+        assert_eq!(forced("timestamp", "{253402,300799,999999}").unwrap(), "9999-12-31T23:59:59.999999Z");
+        for text in ["{253402,300800,0}", "{0,1000000,0}", "{0,0,1000000}", "{0,-1,0}", "{0,0,-1}", "{0,0}", "{a,0,0}"] {
+            assert_eq!(forced("timestamp", text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn system_times() {
+        let options = TimeOptions{ integers: true, ..TimeOptions::default() };
+        assert_eq!(detect(&options, "951782400").unwrap().1, "2000-02-29T00:00:00Z");
+        assert_eq!(detect(&options, "951782400123").unwrap().1, "2000-02-29T00:00:00.123000Z");
+        assert_eq!(detect(&options, "951782400123456789").unwrap().1, "2000-02-29T00:00:00.123456Z");
+        assert_eq!(detect(&options, "0"), None);
+        assert_eq!(detect(&options, "4102444800"), None);
+        assert_eq!(forced("s", "0").unwrap(), "1970-01-01T00:00:00Z");
+        assert_eq!(forced("s", "-1").unwrap(), "1969-12-31T23:59:59Z");
+        assert_eq!(forced("ms", "-1").unwrap(), "1969-12-31T23:59:59.999000Z");
+        assert_eq!(forced("us", "253402300799999999").unwrap(), "9999-12-31T23:59:59.999999Z");
+        assert_eq!(forced("s", "253402300800"), None);
+        assert_eq!(forced("s", "-62167219201"), None);
+        assert_eq!(forced("ns", "100000000000000000000000"), None);
+        assert_eq!(forced("s", "1.5"), None);
+    }
+
+    #[test]
+    fn last_rule_wins() {
+        let rules = ["$=s", "$=ms"].iter().map(|rule| TimeRule::parse(rule).unwrap()).collect();
+        let options = TimeOptions{ integers: true, rules, ..TimeOptions::default() };
+        assert_eq!(detect(&options, "1000").unwrap().1, "1970-01-01T00:00:01Z");
+        let options = TimeOptions{ integers: true, rules: vec![TimeRule::parse("$=none").unwrap()], ..TimeOptions::default() };
+        assert_eq!(detect(&options, "951782400"), None);
+    }
+}